@@ -0,0 +1,95 @@
+use crate::{point::Point, ray::Ray};
+
+/** Axis-aligned bounding box spanned by its two extreme corners. */
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl Aabb {
+    pub fn from(min: Point, max: Point) -> Aabb {
+        Aabb { min, max }
+    }
+
+    pub fn from_points(points: &[Point]) -> Aabb {
+        let mut min = points[0];
+        let mut max = points[0];
+        for p in points {
+            for i in 0..=2 {
+                *min.at_mut(i) = min.at(i).min(p.at(i));
+                *max.at_mut(i) = max.at(i).max(p.at(i));
+            }
+        }
+        Aabb { min, max }
+    }
+
+    pub fn union(&self, rhs: &Aabb) -> Aabb {
+        Aabb::from_points(&[self.min, self.max, rhs.min, rhs.max])
+    }
+
+    /** Widens degenerate axes, so flat shapes still have a box with volume. */
+    pub fn pad(&self) -> Aabb {
+        const DELTA: f32 = 1e-4;
+        let mut aabb = *self;
+        for i in 0..=2 {
+            if aabb.max.at(i) - aabb.min.at(i) < DELTA {
+                *aabb.min.at_mut(i) -= DELTA / 2.;
+                *aabb.max.at_mut(i) += DELTA / 2.;
+            }
+        }
+        aabb
+    }
+
+    pub fn corners(&self) -> [Point; 8] {
+        [0, 1, 2, 3, 4, 5, 6, 7].map(|i| {
+            Point::from([0, 1, 2].map(|axis| {
+                if i & (1 << axis) == 0 {
+                    self.min.at(axis)
+                } else {
+                    self.max.at(axis)
+                }
+            }))
+        })
+    }
+
+    pub fn contains(&self, p: Point) -> bool {
+        (0..=2).all(|i| self.min.at(i) <= p.at(i) && p.at(i) <= self.max.at(i))
+    }
+
+    /** Slab test: returns the parameter interval of `ray` inside the box, clipped to [t_min, t_max]. */
+    pub fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<(f32, f32)> {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+        for i in 0..=2 {
+            let inv = 1. / ray.direct.at(i);
+            let mut t0 = (self.min.at(i) - ray.origin.at(i)) * inv;
+            let mut t1 = (self.max.at(i) - ray.origin.at(i)) * inv;
+            if inv < 0. {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max <= t_min {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec::Vec;
+
+    #[test]
+    fn test_hit() {
+        let aabb = Aabb::from(Point::from([-1., -1., -1.]), Point::from([1., 1., 1.]));
+        let ray = Ray::from(Point::from([0., 0., -3.]), Vec::from([0., 0., 1.]));
+        assert_eq!(aabb.hit(ray, 0., f32::MAX), Some((2., 4.)));
+        assert_eq!(aabb.hit(ray, 3., f32::MAX), Some((3., 4.)));
+        let ray = Ray::from(Point::from([0., 2., -3.]), Vec::from([0., 0., 1.]));
+        assert_eq!(aabb.hit(ray, 0., f32::MAX), None);
+    }
+}
@@ -0,0 +1,110 @@
+use crate::vec::Vec;
+
+/** Arbitrary output variables, the passes a render can write next to the beauty image.
+ * First-hit passes describe the surface a camera ray hits first and are zero where it
+ * escapes; depth is the distance along the ray. The per-lobe passes split the light
+ * leaving that surface by how it scatters there: direct light arrives after one more
+ * bounce, indirect light after more.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aov {
+    Depth,
+    Normal,
+    Albedo,
+    Position,
+    Uv,
+    MaterialId,
+    ObjectId,
+    DiffuseDirect,
+    DiffuseIndirect,
+    SpecularDirect,
+    SpecularIndirect,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 11] = [
+        Aov::Depth,
+        Aov::Normal,
+        Aov::Albedo,
+        Aov::Position,
+        Aov::Uv,
+        Aov::MaterialId,
+        Aov::ObjectId,
+        Aov::DiffuseDirect,
+        Aov::DiffuseIndirect,
+        Aov::SpecularDirect,
+        Aov::SpecularIndirect,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Position => "position",
+            Aov::Uv => "uv",
+            Aov::MaterialId => "material_id",
+            Aov::ObjectId => "object_id",
+            Aov::DiffuseDirect => "diffuse_direct",
+            Aov::DiffuseIndirect => "diffuse_indirect",
+            Aov::SpecularDirect => "specular_direct",
+            Aov::SpecularIndirect => "specular_indirect",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Aov> {
+        Aov::ALL.into_iter().find(|aov| aov.name() == name)
+    }
+
+    /** Names of the EXR channels, taken from the leading components of the value. */
+    pub fn channels(&self) -> &'static [&'static str] {
+        match self {
+            Aov::Depth => &["Z"],
+            Aov::Normal | Aov::Position => &["X", "Y", "Z"],
+            Aov::Uv => &["U", "V"],
+            Aov::MaterialId | Aov::ObjectId => &["id"],
+            _ => &["R", "G", "B"],
+        }
+    }
+
+    /** IDs are not averaged over the samples of a pixel, since an average of two IDs
+     * means nothing; the pixel keeps the ID of its first sample.
+     */
+    pub fn is_averaged(&self) -> bool {
+        !matches!(self, Aov::MaterialId | Aov::ObjectId)
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+/** The AOV values of one camera sample, scalars in the x component. */
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AovSample {
+    values: [Vec; 11],
+}
+
+impl AovSample {
+    pub fn get(&self, aov: Aov) -> Vec {
+        self.values[aov.index()]
+    }
+
+    pub fn set(&mut self, aov: Aov, value: Vec) {
+        self.values[aov.index()] = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        for aov in Aov::ALL {
+            assert_eq!(Aov::from_name(aov.name()), Some(aov));
+            assert_eq!(Aov::ALL[aov.index()], aov);
+        }
+        assert_eq!(Aov::from_name("beauty"), None);
+    }
+}
@@ -0,0 +1,508 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use crate::{
+    error::Error,
+    instance::Transformed,
+    integrator::Method,
+    material::{DiffuseLight, Material, Textured},
+    medium::Fog,
+    mesh::Mesh,
+    planar::{Cuboid, Disk, Plane, Quad},
+    point::Point,
+    scene::{Background, Scene},
+    shape::{Shape, Sphere, Surface},
+    texture::Texture,
+    transform::Transform,
+    vec::Vec,
+};
+
+/* Creates a shape once its material is known. */
+type Place = Box<dyn FnOnce(Arc<dyn Material>) -> Result<Placed, Error>>;
+
+/* Surfaces can be sampled, so they become lights when their material is one. */
+enum Placed {
+    Shape(Arc<dyn Shape>),
+    Surface(Arc<dyn Surface>),
+}
+
+/* What a material name stands for; a textured material looks its texture up at build. */
+enum MaterialData {
+    Material(Arc<dyn Material>),
+    Light(Arc<dyn Material>),
+    Textured(String),
+}
+
+enum Object {
+    Shape { material: String, place: Place },
+    Instance { mesh: String, transform: Transform },
+}
+
+struct MeshData {
+    vertices: std::vec::Vec<Point>,
+    triangles: std::vec::Vec<[usize; 3]>,
+    material: String,
+}
+
+/** Builds a scene out of named resources: materials, textures, lights (emissive
+ * materials) and meshes are registered under a name, and objects, textured materials
+ * and the fog refer to them by that name. Meshes
+ * are placed as instances, which share the geometry. Spheres, quads and disks made of
+ * a light are the scene's lights, which integrators sample directly; other shapes
+ * made of one still emit. Materials and lights are numbered from 1 in the order they
+ * are registered, which the material ID AOV shows. Nothing is checked before `build`,
+ * which reports the first invalid object or reference to a resource that does not
+ * exist, and any resources nothing refers to, which `unused` lists beforehand.
+ *
+ * ```
+ * use rtus::{material::Lambertian, point::Point, vec::Vec, SceneBuilder};
+ *
+ * let scene = SceneBuilder::new()
+ *     .material("grey", Lambertian::new_const([0.5, 0.5, 0.5]))
+ *     .light("lamp", Vec::from([4., 4., 4.]))
+ *     .sphere(Point::from([0., 0., -1.]), 0.5, "grey")
+ *     .sphere(Point::from([0., 2., -1.]), 0.5, "lamp")
+ *     .build()?;
+ * # Ok::<(), rtus::Error>(())
+ * ```
+ */
+#[derive(Default)]
+pub struct SceneBuilder {
+    materials: std::vec::Vec<(String, MaterialData)>,
+    textures: std::vec::Vec<(String, Arc<dyn Texture>)>,
+    meshes: std::vec::Vec<(String, MeshData)>,
+    objects: std::vec::Vec<(String, Object)>,
+    fog: Option<(f32, String)>,
+    background: Option<Background>,
+    integrator: Option<Method>,
+}
+
+impl SceneBuilder {
+    pub fn new() -> SceneBuilder {
+        SceneBuilder::default()
+    }
+
+    pub fn material(mut self, name: &str, material: impl Material + 'static) -> SceneBuilder {
+        let material = MaterialData::Material(Arc::new(material));
+        self.materials.push((name.to_string(), material));
+        self
+    }
+
+    pub fn texture(mut self, name: &str, texture: impl Texture + 'static) -> SceneBuilder {
+        self.textures.push((name.to_string(), Arc::new(texture)));
+        self
+    }
+
+    /** Registers a diffuse material whose albedo is the texture registered as `texture`. */
+    pub fn textured(mut self, name: &str, texture: &str) -> SceneBuilder {
+        let material = MaterialData::Textured(texture.to_string());
+        self.materials.push((name.to_string(), material));
+        self
+    }
+
+    /** Registers an emitter of the given radiance; shapes refer to it like to a material. */
+    pub fn light(mut self, name: &str, emit: Vec) -> SceneBuilder {
+        let light = MaterialData::Light(Arc::new(DiffuseLight::new(emit)));
+        self.materials.push((name.to_string(), light));
+        self
+    }
+
+    pub fn mesh(
+        mut self,
+        name: &str,
+        vertices: std::vec::Vec<Point>,
+        triangles: std::vec::Vec<[usize; 3]>,
+        material: &str,
+    ) -> SceneBuilder {
+        let mesh = MeshData {
+            vertices,
+            triangles,
+            material: material.to_string(),
+        };
+        self.meshes.push((name.to_string(), mesh));
+        self
+    }
+
+    /** Places a copy of a registered mesh that shares its geometry. */
+    pub fn instance(mut self, mesh: &str, transform: Transform) -> SceneBuilder {
+        let object = format!("instance of \"{mesh}\"");
+        let mesh = mesh.to_string();
+        self.objects
+            .push((object, Object::Instance { mesh, transform }));
+        self
+    }
+
+    /** Places any shape; `place` makes it once the material is looked up. */
+    pub fn shape<S: Shape + 'static>(
+        self,
+        kind: &str,
+        material: &str,
+        place: impl FnOnce(Arc<dyn Material>) -> Result<S, Error> + 'static,
+    ) -> SceneBuilder {
+        let place: Place = Box::new(|material| Ok(Placed::Shape(Arc::new(place(material)?))));
+        self.place(kind, material, place)
+    }
+
+    fn surface<S: Surface + 'static>(
+        self,
+        kind: &str,
+        material: &str,
+        place: impl FnOnce(Arc<dyn Material>) -> Result<S, Error> + 'static,
+    ) -> SceneBuilder {
+        let place: Place = Box::new(|material| Ok(Placed::Surface(Arc::new(place(material)?))));
+        self.place(kind, material, place)
+    }
+
+    fn place(mut self, kind: &str, material: &str, place: Place) -> SceneBuilder {
+        let object = format!("{kind} {}", self.objects.len());
+        let material = material.to_string();
+        self.objects
+            .push((object, Object::Shape { material, place }));
+        self
+    }
+
+    pub fn sphere(self, center: Point, radius: f32, material: &str) -> SceneBuilder {
+        self.surface("sphere", material, move |material| {
+            Sphere::new(center, radius, material)
+        })
+    }
+
+    pub fn plane(self, origin: Point, n: Vec, material: &str) -> SceneBuilder {
+        self.shape("plane", material, move |material| {
+            Plane::new(origin, n, material)
+        })
+    }
+
+    pub fn quad(self, origin: Point, u: Vec, v: Vec, material: &str) -> SceneBuilder {
+        self.surface("quad", material, move |material| {
+            Quad::new(origin, u, v, material)
+        })
+    }
+
+    pub fn disk(self, center: Point, n: Vec, radius: f32, material: &str) -> SceneBuilder {
+        self.surface("disk", material, move |material| {
+            Disk::new(center, n, radius, material)
+        })
+    }
+
+    pub fn cuboid(self, a: Point, b: Point, material: &str) -> SceneBuilder {
+        self.shape("box", material, move |material| Cuboid::new(a, b, material))
+    }
+
+    /** Fills the scene with a homogeneous medium scattering by the phase material. */
+    pub fn fog(mut self, density: f32, phase: &str) -> SceneBuilder {
+        self.fog = Some((density, phase.to_string()));
+        self
+    }
+
+    pub fn background(mut self, background: Background) -> SceneBuilder {
+        self.background = Some(background);
+        self
+    }
+
+    /** Chooses how the scene is rendered, unless the render settings choose otherwise. */
+    pub fn integrator(mut self, integrator: Method) -> SceneBuilder {
+        self.integrator = Some(integrator);
+        self
+    }
+
+    /** The registered resources nothing refers to, e.g. `material "red"`. */
+    pub fn unused(&self) -> std::vec::Vec<String> {
+        /* Each kind has its own names, so a mesh may share the name of a used material. */
+        let mut materials_used: std::vec::Vec<&str> = std::vec::Vec::new();
+        let mut meshes_used: std::vec::Vec<&str> = std::vec::Vec::new();
+        for (_, object) in &self.objects {
+            match object {
+                Object::Shape { material, .. } => materials_used.push(material),
+                Object::Instance { mesh, .. } => {
+                    meshes_used.push(mesh);
+                    if let Some((_, data)) = self.meshes.iter().find(|(name, _)| name == mesh) {
+                        materials_used.push(&data.material);
+                    }
+                }
+            }
+        }
+        materials_used.extend(self.fog.iter().map(|(_, phase)| phase.as_str()));
+        let textures_used: std::vec::Vec<&str> = self
+            .materials
+            .iter()
+            .filter_map(|(_, material)| match material {
+                MaterialData::Textured(texture) => Some(texture.as_str()),
+                _ => None,
+            })
+            .collect();
+
+        let materials = self.materials.iter().map(|(name, material)| {
+            let kind = match material {
+                MaterialData::Light(_) => "light",
+                _ => "material",
+            };
+            (kind, name, &materials_used)
+        });
+        let textures = self
+            .textures
+            .iter()
+            .map(|(name, _)| ("texture", name, &textures_used));
+        let meshes = self
+            .meshes
+            .iter()
+            .map(|(name, _)| ("mesh", name, &meshes_used));
+        materials
+            .chain(textures)
+            .chain(meshes)
+            .filter(|(_, name, used)| !used.contains(&name.as_str()))
+            .map(|(kind, name, _)| format!("{kind} \"{name}\""))
+            .collect()
+    }
+
+    /** Creates the scene, failing on the first name defined twice, reference to an
+     * unknown resource or invalid object, and then on any unused resources.
+     */
+    pub fn build(self) -> Result<Scene, Error> {
+        let unused = self.unused();
+        let mut textures = HashMap::new();
+        for (name, texture) in self.textures {
+            if textures.insert(name.clone(), texture).is_some() {
+                return Err(Error::invalid_scene(
+                    &format!("texture \"{name}\""),
+                    "defined twice",
+                ));
+            }
+        }
+        let mut materials = HashMap::new();
+        let mut lights = HashSet::new();
+        for (index, (name, material)) in self.materials.into_iter().enumerate() {
+            let material = match material {
+                MaterialData::Material(material) => material,
+                MaterialData::Light(light) => {
+                    lights.insert(name.clone());
+                    light
+                }
+                MaterialData::Textured(texture) => {
+                    let texture = textures.get(&texture).ok_or_else(|| {
+                        Error::invalid_scene(
+                            &format!("material \"{name}\""),
+                            format!("unknown texture \"{texture}\""),
+                        )
+                    })?;
+                    Arc::new(Textured::new(texture.clone()))
+                }
+            };
+            if materials
+                .insert(name.clone(), (material, index + 1))
+                .is_some()
+            {
+                return Err(Error::invalid_scene(
+                    &format!("material \"{name}\""),
+                    "defined twice",
+                ));
+            }
+        }
+        let lookup = |object: &str, name: &str| {
+            materials
+                .get(name)
+                .cloned()
+                .ok_or_else(|| Error::invalid_scene(object, format!("unknown material \"{name}\"")))
+        };
+
+        let mut meshes = HashMap::new();
+        for (name, data) in self.meshes {
+            let object = format!("mesh \"{name}\"");
+            let (material, id) = lookup(&object, &data.material)?;
+            let mesh = Mesh::new(data.vertices, data.triangles, material)?;
+            if meshes.insert(name, (Arc::new(mesh), id)).is_some() {
+                return Err(Error::invalid_scene(&object, "defined twice"));
+            }
+        }
+
+        let mut scene = Scene::new();
+        let count = self.objects.len();
+        for (index, (object, placement)) in self.objects.into_iter().enumerate() {
+            let id = match placement {
+                Object::Shape {
+                    material: name,
+                    place,
+                } => {
+                    let (material, id) = lookup(&object, &name)?;
+                    match place(material)? {
+                        Placed::Surface(surface) if lights.contains(&name) => {
+                            scene.push_light(surface);
+                        }
+                        Placed::Surface(surface) => scene.push(surface),
+                        Placed::Shape(shape) => scene.push(shape),
+                    }
+                    id
+                }
+                Object::Instance { mesh, transform } => {
+                    let (mesh, id) = meshes.get(&mesh).ok_or_else(|| {
+                        Error::invalid_scene(&object, format!("unknown mesh \"{mesh}\""))
+                    })?;
+                    if !transform.is_invertible() {
+                        return Err(Error::invalid_scene(
+                            &object,
+                            "the transform scales by 0 and cannot be inverted",
+                        ));
+                    }
+                    scene.push(Transformed::new(mesh.clone(), transform)?);
+                    *id
+                }
+            };
+            scene.set_material_id(index, id);
+        }
+        if let Some((density, phase)) = self.fog {
+            let (phase, id) = lookup("fog", &phase)?;
+            scene.set_fog(Fog::new(density, phase));
+            scene.set_material_id(count, id);
+        }
+        if let Some(background) = self.background {
+            scene.set_background(background);
+        }
+        if let Some(integrator) = self.integrator {
+            scene.set_integrator(integrator);
+        }
+        if !unused.is_empty() {
+            return Err(Error::invalid_scene(
+                &unused.join(", "),
+                "defined but never used",
+            ));
+        }
+        Ok(scene)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::Lambertian, ray::Ray, texture::Checker};
+
+    fn triangle() -> (std::vec::Vec<Point>, std::vec::Vec<[usize; 3]>) {
+        let vertices = vec![
+            Point::from([-1., -1., 0.]),
+            Point::from([1., -1., 0.]),
+            Point::from([0., 1., 0.]),
+        ];
+        (vertices, vec![[0, 1, 2]])
+    }
+
+    #[test]
+    fn test_build() {
+        let (vertices, triangles) = triangle();
+        let builder = SceneBuilder::new()
+            .material("grey", Lambertian::new_const([0.5, 0.5, 0.5]))
+            .material("red", Lambertian::new_const([1., 0., 0.]))
+            .light("lamp", Vec::from([4., 4., 4.]))
+            .texture("checker", Checker::new(Vec::new(), Vec::from([1.; 3]), 4.))
+            .mesh("triangle", vertices, triangles, "grey")
+            .instance("triangle", Transform::translate(Vec::from([0., 0., -2.])))
+            .instance("triangle", Transform::translate(Vec::from([0., 0., -4.])))
+            .sphere(Point::from([0., 0., 2.]), 0.5, "lamp");
+        assert_eq!(
+            builder.unused(),
+            ["material \"red\"", "texture \"checker\""]
+        );
+        let error = builder.build().err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "invalid material \"red\", texture \"checker\": defined but never used"
+        );
+
+        /* A name used for one kind of resource does not use the others of that name. */
+        let (vertices, triangles) = triangle();
+        let builder = SceneBuilder::new()
+            .material("grey", Lambertian::new_const([0.5, 0.5, 0.5]))
+            .texture("grey", Checker::new(Vec::new(), Vec::from([1.; 3]), 4.))
+            .mesh("grey", vertices, triangles, "grey")
+            .sphere(Point::new(), 1., "grey");
+        assert_eq!(builder.unused(), ["texture \"grey\"", "mesh \"grey\""]);
+
+        let (vertices, triangles) = triangle();
+        let builder = SceneBuilder::new()
+            .material("grey", Lambertian::new_const([0.5, 0.5, 0.5]))
+            .textured("red", "checker")
+            .light("lamp", Vec::from([4., 4., 4.]))
+            .texture("checker", Checker::new(Vec::new(), Vec::from([1.; 3]), 4.))
+            .mesh("triangle", vertices, triangles, "grey")
+            .instance("triangle", Transform::translate(Vec::from([0., 0., -2.])))
+            .instance("triangle", Transform::translate(Vec::from([0., 0., -4.])))
+            .sphere(Point::from([0., 0., 2.]), 0.5, "lamp")
+            .quad(
+                Point::from([0., -1., 0.]),
+                Vec::from([1., 0., 0.]),
+                Vec::from([0., 0., 1.]),
+                "red",
+            );
+        assert!(builder.unused().is_empty());
+        let scene = builder.build().unwrap();
+
+        let ray = Ray::from(Point::new(), Vec::from([0., 0., -1.]));
+        let (index, record) = scene.hit_object(ray, 0.001, f32::MAX).unwrap();
+        assert_eq!((index, record.t), (0, 2.));
+        assert_eq!(record.material.albedo(&record).x(), 0.5);
+        let back = Ray::from(Point::new(), Vec::from([0., 0., 1.]));
+        let record = scene.hit(back, 0.001, f32::MAX).unwrap();
+        assert_eq!(record.material.emitted(&record).y(), 4.);
+        let lights: std::vec::Vec<_> = scene.lights().iter().map(|(index, _)| *index).collect();
+        assert_eq!(lights, [2]);
+        let ids: std::vec::Vec<_> = (0..4).map(|index| scene.material_id(index)).collect();
+        assert_eq!(ids, [1, 1, 3, 2]);
+        let down = Ray::from(Point::from([0.1, 0., 0.1]), Vec::from([0., -1., 0.]));
+        let record = scene.hit(down, 0.001, f32::MAX).unwrap();
+        assert_eq!(record.material.albedo(&record).x(), 0.);
+        let down = Ray::from(Point::from([0.3, 0., 0.1]), Vec::from([0., -1., 0.]));
+        let record = scene.hit(down, 0.001, f32::MAX).unwrap();
+        assert_eq!(record.material.albedo(&record).x(), 1.);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let grey = || SceneBuilder::new().material("grey", Lambertian::new_const([0.5; 3]));
+        let error = grey()
+            .sphere(Point::new(), 1., "grey")
+            .sphere(Point::new(), 1., "gray")
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "invalid sphere 1: unknown material \"gray\""
+        );
+        let error = grey()
+            .instance("bunny", Transform::new())
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "invalid instance of \"bunny\": unknown mesh \"bunny\""
+        );
+        let (vertices, triangles) = triangle();
+        let error = grey()
+            .mesh("triangle", vertices, triangles, "grey")
+            .instance("triangle", Transform::scale(Vec::from([2., 0., 2.])))
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "invalid instance of \"triangle\": the transform scales by 0 and cannot be inverted"
+        );
+        let builder = grey().material("grey", Lambertian::new_const([1.; 3]));
+        assert!(builder.build().is_err());
+        let builder = grey().sphere(Point::new(), 0., "grey");
+        assert!(matches!(builder.build(), Err(Error::InvalidScene { .. })));
+        assert!(grey().fog(0.1, "haze").build().is_err());
+        let error = grey()
+            .sphere(Point::new(), 1., "grey")
+            .textured("wood", "grain")
+            .sphere(Point::new(), 2., "wood")
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "invalid material \"wood\": unknown texture \"grain\""
+        );
+    }
+}
@@ -1,10 +1,12 @@
-use crate::{point::Point, ray::Ray, vec::Vec};
+use crate::{curve::Curve, point::Point, ray::Ray, rng, vec::Vec};
 
 pub struct Camera {
     origin: Point,
     horizontal: Vec,
     vertical: Vec,
     lower_left: Vec,
+    shutter_open: f32,
+    shutter_close: f32,
 }
 
 impl Camera {
@@ -14,10 +16,44 @@ impl Camera {
         Camera::default()
     }
 
+    /** A camera at `from` looking at `at`, with a vertical field of view in degrees. */
+    pub fn look_at(from: Point, at: Point, vup: Vec, vfov: f32, aspect: f32) -> Camera {
+        let height = 2. * (vfov.to_radians() / 2.).tan() * Camera::DEFAULT_FOCAL_DEPTH;
+        let width = aspect * height;
+        let w = (from - at).to_unit();
+        let u = vup.cross(&w).to_unit();
+        let v = w.cross(&u);
+        let horizontal = width * u;
+        let vertical = height * v;
+        Camera {
+            origin: from,
+            horizontal,
+            vertical,
+            lower_left: -horizontal / 2. - vertical / 2. - Camera::DEFAULT_FOCAL_DEPTH * w,
+            shutter_open: 0.,
+            shutter_close: 0.,
+        }
+    }
+
+    /** Rays are spread uniformly over [open, close]; an empty interval gives a still frame. */
+    pub fn with_shutter(self, open: f32, close: f32) -> Camera {
+        Camera {
+            shutter_open: open,
+            shutter_close: close,
+            ..self
+        }
+    }
+
     pub fn get_ray(&self, u: f32, v: f32) -> Ray {
-        Ray::from(
+        let time = if self.shutter_close > self.shutter_open {
+            self.shutter_open + rng::random() * (self.shutter_close - self.shutter_open)
+        } else {
+            self.shutter_open
+        };
+        Ray::new(
             self.origin,
             self.lower_left + u * self.horizontal + v * self.vertical,
+            time,
         )
     }
 }
@@ -38,6 +74,30 @@ impl Default for Camera {
             horizontal,
             vertical,
             lower_left,
+            shutter_open: 0.,
+            shutter_close: 0.,
         }
     }
 }
+
+/** Keyframed camera parameters; positions are given as vectors from the world origin. */
+pub struct CameraAnimation {
+    pub from: Curve<Vec>,
+    pub at: Curve<Vec>,
+    pub vup: Vec,
+    pub vfov: Curve<f32>,
+}
+
+impl CameraAnimation {
+    /** The camera at `time`, with its shutter open over [time, time + exposure]. */
+    pub fn at(&self, time: f32, exposure: f32, aspect: f32) -> Camera {
+        Camera::look_at(
+            Point::new() + self.from.at(time),
+            Point::new() + self.at.at(time),
+            self.vup,
+            self.vfov.at(time),
+            aspect,
+        )
+        .with_shutter(time, time + exposure)
+    }
+}
@@ -0,0 +1,493 @@
+use std::{ops::RangeInclusive, time::Duration};
+
+use rtus::{
+    aov::Aov, diff::Metric, film::Window, integrator::Method, scenes::Builtin, Error, Settings,
+};
+
+pub const USAGE: &str = "\
+usage: rtus [render] [--builtin <scene>] [--frames <first>..<last>] [--fps <n>] [--output <path>]
+       rtus diff <image> <reference> [--heatmap <path>] [--metric <name>] [--threshold <value>]";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Render(Box<Args>),
+    Diff(DiffArgs),
+}
+
+impl Command {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+        let mut args = args.into_iter().peekable();
+        if args.peek().map(String::as_str) == Some("diff") {
+            args.next();
+            return Ok(Command::Diff(DiffArgs::parse(args)?));
+        }
+        Ok(Command::Render(Box::new(Args::parse(args)?)))
+    }
+}
+
+/* Compares an image with a reference. With a threshold, the comparison fails when the
+ * metric is worse than it, which `rtus diff` reports with exit code 1 like cmp.
+ */
+#[derive(Debug, PartialEq)]
+pub struct DiffArgs {
+    pub image: String,
+    pub reference: String,
+    /* Where to write the error of every pixel in false color. */
+    pub heatmap: Option<String>,
+    pub metric: Metric,
+    pub threshold: Option<f32>,
+}
+
+impl DiffArgs {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<DiffArgs, String> {
+        let mut images = std::vec::Vec::new();
+        let (mut heatmap, mut metric, mut threshold) = (None, Metric::Rmse, None);
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("missing value for {arg}"));
+            match arg.as_str() {
+                "--heatmap" => heatmap = Some(value()?),
+                "--metric" => {
+                    let value = value()?;
+                    metric = Metric::from_name(&value).ok_or_else(|| {
+                        let names: std::vec::Vec<_> =
+                            Metric::ALL.iter().map(|m| m.name()).collect();
+                        format!(
+                            "unknown metric {value}, expected one of {}",
+                            names.join(", ")
+                        )
+                    })?;
+                }
+                "--threshold" => threshold = Some(parse_number(&arg, &value()?)?),
+                _ if arg.starts_with("--") => return Err(format!("unknown argument {arg}")),
+                _ => images.push(arg),
+            }
+        }
+        let [image, reference]: [String; 2] = images
+            .try_into()
+            .map_err(|_| "expected an image and a reference".to_string())?;
+        Ok(DiffArgs {
+            image,
+            reference,
+            heatmap,
+            metric,
+            threshold,
+        })
+    }
+}
+
+/* A crop window from (x0, y0) to (x1, y1), exclusive, with the origin at the top left
+ * like image viewers show it. Values written with a decimal point are fractions of the
+ * image size.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Crop {
+    Pixels([usize; 4]),
+    Normalized([f32; 4]),
+}
+
+impl Crop {
+    /* The film window of the crop in an image of the given size. */
+    pub fn window(&self, width: usize, height: usize) -> Result<Window, Error> {
+        let [x0, y0, x1, y1] = match *self {
+            Crop::Pixels(pixels) => pixels,
+            Crop::Normalized([x0, y0, x1, y1]) => [
+                (x0 * width as f32).floor() as usize,
+                (y0 * height as f32).floor() as usize,
+                (x1 * width as f32).ceil() as usize,
+                (y1 * height as f32).ceil() as usize,
+            ],
+        };
+        if x0 >= x1 || y0 >= y1 || x1 > width || y1 > height {
+            return Err(Error::invalid_argument(
+                "crop window",
+                format!("{x0},{y0},{x1},{y1} is empty or outside the {width}x{height} image"),
+            ));
+        }
+        Ok(Window {
+            x: x0,
+            y: height - y1,
+            width: x1 - x0,
+            height: y1 - y0,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Args {
+    /* The scene to render; generated scenes are drawn from `seed`. */
+    pub builtin: Builtin,
+    /* Inclusive range of frames to render as an image sequence. */
+    pub frames: Option<RangeInclusive<u32>>,
+    pub fps: f32,
+    /* The image file for a single frame, the directory for a sequence. */
+    pub output: Option<String>,
+    /* Unlimited by default when another stopping criterion is given. */
+    pub spp: Option<u32>,
+    pub seed: u64,
+    pub time: Option<Duration>,
+    pub target_error: Option<f32>,
+    /* Error threshold that turns on adaptive sampling. */
+    pub adaptive: Option<f32>,
+    pub min_spp: Option<u32>,
+    pub max_spp: Option<u32>,
+    /* Where to write the number of samples per pixel as an image. */
+    pub sample_map: Option<String>,
+    pub crop: Option<Crop>,
+    /* Writes a crop into a full-size image instead of an image of the crop's size. */
+    pub composite: bool,
+    pub aovs: std::vec::Vec<Aov>,
+    /* Writes the image and its AOVs as layers of one EXR instead of one EXR per AOV. */
+    pub multilayer: bool,
+    /* Strength of the denoiser applied to the final image, if any. */
+    pub denoise: Option<f32>,
+    pub checkpoint: Option<String>,
+    pub checkpoint_every: u32,
+    /* A checkpoint to continue from, rendered with the same size, crop, seed and integrator;
+     * it keeps being updated unless `checkpoint` is given.
+     */
+    pub resume: Option<String>,
+    /* Where to write the render statistics as JSON, summed over all frames. */
+    pub stats: Option<String>,
+    /* Bounces after which Russian roulette may end paths; `off` never ends them early. */
+    pub roulette_depth: Option<u32>,
+    /* Overrides the integrator the scene chooses. */
+    pub integrator: Option<Method>,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Args {
+            builtin: Builtin::Spheres,
+            frames: None,
+            fps: 24.,
+            output: None,
+            spp: None,
+            seed: 0,
+            time: None,
+            target_error: None,
+            adaptive: None,
+            min_spp: None,
+            max_spp: None,
+            sample_map: None,
+            crop: None,
+            composite: false,
+            aovs: std::vec::Vec::new(),
+            multilayer: false,
+            denoise: None,
+            checkpoint: None,
+            checkpoint_every: 10,
+            resume: None,
+            stats: None,
+            roulette_depth: Settings::default().roulette_depth,
+            integrator: None,
+        }
+    }
+}
+
+impl Args {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter().peekable();
+        if args.peek().map(String::as_str) == Some("render") {
+            args.next();
+        }
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("missing value for {arg}"));
+            match arg.as_str() {
+                "--builtin" => {
+                    let value = value()?;
+                    parsed.builtin = Builtin::from_name(&value).ok_or_else(|| {
+                        let names: std::vec::Vec<_> =
+                            Builtin::ALL.iter().map(|b| b.name()).collect();
+                        format!(
+                            "unknown scene {value}, expected one of {}",
+                            names.join(", ")
+                        )
+                    })?;
+                }
+                "--frames" => parsed.frames = Some(parse_frames(&value()?)?),
+                "--fps" => {
+                    let value = value()?;
+                    parsed.fps = value
+                        .parse()
+                        .ok()
+                        .filter(|fps: &f32| *fps > 0.)
+                        .ok_or(format!("invalid frame rate {value}"))?;
+                }
+                "--output" => parsed.output = Some(value()?),
+                "--spp" => parsed.spp = Some(parse_number(&arg, &value()?)?),
+                "--seed" => parsed.seed = parse_number(&arg, &value()?)?,
+                "--time" => parsed.time = Some(parse_duration(&value()?)?),
+                "--target-error" => parsed.target_error = Some(parse_error(&value()?)?),
+                "--adaptive" => parsed.adaptive = Some(parse_error(&value()?)?),
+                "--min-spp" => parsed.min_spp = Some(parse_number(&arg, &value()?)?),
+                "--max-spp" => parsed.max_spp = Some(parse_number(&arg, &value()?)?),
+                "--sample-map" => parsed.sample_map = Some(value()?),
+                "--crop" => parsed.crop = Some(parse_crop(&value()?)?),
+                "--composite" => parsed.composite = true,
+                "--aovs" => parsed.aovs = parse_aovs(&value()?)?,
+                "--multilayer" => parsed.multilayer = true,
+                "--denoise" => {
+                    let value = value()?;
+                    parsed.denoise = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|strength: &f32| *strength >= 0.)
+                            .ok_or(format!("invalid denoiser strength {value}"))?,
+                    );
+                }
+                "--checkpoint" => parsed.checkpoint = Some(value()?),
+                "--checkpoint-every" => parsed.checkpoint_every = parse_number(&arg, &value()?)?,
+                "--resume" => parsed.resume = Some(value()?),
+                "--stats" => parsed.stats = Some(value()?),
+                "--roulette-depth" => {
+                    parsed.roulette_depth = match value()?.as_str() {
+                        "off" => None,
+                        value => Some(parse_number(&arg, value)?),
+                    };
+                }
+                "--integrator" => {
+                    let value = value()?;
+                    parsed.integrator = Some(Method::from_name(&value).ok_or_else(|| {
+                        format!(
+                            "unknown integrator {value}, expected one of {}",
+                            Method::NAMES.join(", ")
+                        )
+                    })?);
+                }
+                _ => return Err(format!("unknown argument {arg}")),
+            }
+        }
+        /* Sequences resume by skipping finished frames and have no one sample map. */
+        if parsed.frames.is_some() {
+            let single = [
+                ("--resume", parsed.resume.is_some()),
+                ("--checkpoint", parsed.checkpoint.is_some()),
+                ("--sample-map", parsed.sample_map.is_some()),
+            ];
+            if let Some((flag, _)) = single.into_iter().find(|(_, given)| *given) {
+                return Err(format!("{flag} cannot be used with --frames"));
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(arg: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {value} for {arg}"))
+}
+
+fn parse_error(value: &str) -> Result<f32, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|error: &f32| *error > 0.)
+        .ok_or(format!("invalid relative error {value}"))
+}
+
+fn parse_crop(value: &str) -> Result<Crop, String> {
+    let invalid = || format!("invalid crop window {value}, expected <x0>,<y0>,<x1>,<y1>");
+    let parts: std::vec::Vec<&str> = value.split(',').collect();
+    if parts.len() != 4 {
+        return Err(invalid());
+    }
+    if value.contains('.') {
+        let mut bounds = [0.; 4];
+        for (bound, part) in bounds.iter_mut().zip(parts) {
+            *bound = part
+                .parse()
+                .ok()
+                .filter(|bound| (0. ..=1.).contains(bound))
+                .ok_or_else(invalid)?;
+        }
+        Ok(Crop::Normalized(bounds))
+    } else {
+        let mut bounds = [0; 4];
+        for (bound, part) in bounds.iter_mut().zip(parts) {
+            *bound = part.parse().map_err(|_| invalid())?;
+        }
+        Ok(Crop::Pixels(bounds))
+    }
+}
+
+/* A comma-separated list of AOV names, or `all`. */
+fn parse_aovs(value: &str) -> Result<std::vec::Vec<Aov>, String> {
+    if value == "all" {
+        return Ok(Aov::ALL.to_vec());
+    }
+    value
+        .split(',')
+        .map(|name| Aov::from_name(name).ok_or(format!("unknown AOV {name}")))
+        .collect()
+}
+
+fn parse_frames(value: &str) -> Result<RangeInclusive<u32>, String> {
+    let invalid = || format!("invalid frame range {value}, expected <first>..<last>");
+    let (first, last) = value.split_once("..").ok_or_else(invalid)?;
+    let first: u32 = first.parse().map_err(|_| invalid())?;
+    let last: u32 = last.parse().map_err(|_| invalid())?;
+    if first > last {
+        return Err(invalid());
+    }
+    Ok(first..=last)
+}
+
+/* Parses durations like `90`, `45s`, `10m` or `1h30m`; bare numbers are seconds. */
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration {value}, expected e.g. 10m or 1h30m");
+    let mut seconds = 0.;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let end = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let number: f64 = rest[..end].parse().map_err(|_| invalid())?;
+        let unit = rest[end..].chars().next();
+        seconds += number
+            * match unit {
+                None | Some('s') => 1.,
+                Some('m') => 60.,
+                Some('h') => 3600.,
+                _ => return Err(invalid()),
+            };
+        rest = &rest[(end + unit.map_or(0, char::len_utf8))..];
+    }
+    if seconds <= 0. {
+        return Err(invalid());
+    }
+    Duration::try_from_secs_f64(seconds).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Args, String> {
+        Args::parse(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(""), Ok(Args::default()));
+        let args = parse("render --frames 1..24 --fps 30 --output out").unwrap();
+        assert_eq!(args.builtin, Builtin::Spheres);
+        assert_eq!(args.frames, Some(1..=24));
+        assert_eq!(args.fps, 30.);
+        assert_eq!(args.output.as_deref(), Some("out"));
+        let args = parse("--spp 16 --seed 3 --resume ck.film --checkpoint-every 2").unwrap();
+        assert_eq!(
+            (args.spp, args.seed, args.checkpoint_every),
+            (Some(16), 3, 2)
+        );
+        assert_eq!(args.resume.as_deref(), Some("ck.film"));
+        let args = parse("--stats stats.json").unwrap();
+        assert_eq!(args.stats.as_deref(), Some("stats.json"));
+        assert_eq!(parse("--roulette-depth 8").unwrap().roulette_depth, Some(8));
+        assert_eq!(parse("--roulette-depth off").unwrap().roulette_depth, None);
+        assert!(parse("--roulette-depth never").is_err());
+        assert_eq!(
+            parse("--integrator ao:50").unwrap().integrator,
+            Some(Method::AmbientOcclusion { distance: 50. })
+        );
+        assert_eq!(parse("").unwrap().integrator, None);
+        assert!(parse("--integrator bdpt").is_err());
+        assert!(parse("--spp many").is_err());
+        let args = parse("--builtin random-spheres --seed 7").unwrap();
+        assert_eq!((args.builtin, args.seed), (Builtin::RandomSpheres, 7));
+        assert!(parse("--builtin teapot").is_err());
+        assert!(parse("--frames 3..1").is_err());
+        assert!(parse("--frames 0..0 --resume nothere.film").is_err());
+        assert!(parse("--frames 0..0 --checkpoint ck.film").is_err());
+        assert!(parse("--sample-map n.ppm --frames 0..0").is_err());
+        assert!(parse("--frames 1-3").is_err());
+        assert!(parse("--fps").is_err());
+        assert!(parse("--bogus").is_err());
+        let args = parse("--time 10m --target-error 0.01").unwrap();
+        assert_eq!(args.time, Some(Duration::from_secs(600)));
+        assert_eq!(args.target_error, Some(0.01));
+        assert!(parse("--target-error -1").is_err());
+        let args = parse("--adaptive 0.02 --min-spp 4 --max-spp 256 --sample-map n.ppm").unwrap();
+        assert_eq!(args.adaptive, Some(0.02));
+        assert_eq!((args.min_spp, args.max_spp), (Some(4), Some(256)));
+        assert_eq!(args.sample_map.as_deref(), Some("n.ppm"));
+        assert!(parse("--crop 1,2,3").is_err());
+        assert!(parse("--crop 0.5,0,1.5,1").is_err());
+        let args = parse("--aovs depth,object_id --multilayer").unwrap();
+        assert_eq!(args.aovs, [Aov::Depth, Aov::ObjectId]);
+        assert!(args.multilayer);
+        assert_eq!(parse("--aovs all").unwrap().aovs.len(), Aov::ALL.len());
+        assert!(parse("--aovs depth,beauty").is_err());
+        assert_eq!(parse("--denoise 0.5").unwrap().denoise, Some(0.5));
+        assert!(parse("--denoise -1").is_err());
+    }
+
+    #[test]
+    fn test_parse_diff() {
+        let command = |args: &str| Command::parse(args.split_whitespace().map(String::from));
+        let Ok(Command::Diff(args)) = command("diff a.png b.exr --metric psnr --threshold 30")
+        else {
+            panic!("not a diff command");
+        };
+        assert_eq!(
+            (args.image.as_str(), args.reference.as_str()),
+            ("a.png", "b.exr")
+        );
+        assert_eq!((args.metric, args.threshold), (Metric::Psnr, Some(30.)));
+        assert_eq!(args.heatmap, None);
+        let Ok(Command::Diff(args)) = command("diff --heatmap h.ppm a.ppm b.ppm") else {
+            panic!("not a diff command");
+        };
+        assert_eq!(
+            (args.heatmap.as_deref(), args.metric),
+            (Some("h.ppm"), Metric::Rmse)
+        );
+        assert!(command("diff a.ppm").is_err());
+        assert!(command("diff a.ppm b.ppm c.ppm").is_err());
+        assert!(command("diff a.ppm b.ppm --metric flip").is_err());
+        assert!(command("diff a.ppm b.ppm --spp 4").is_err());
+        assert_eq!(
+            command("render --spp 4"),
+            Ok(Command::Render(Box::new(parse("--spp 4").unwrap())))
+        );
+    }
+
+    #[test]
+    fn test_crop() {
+        let args = parse("--crop 10,20,110,70 --composite").unwrap();
+        assert_eq!(args.crop, Some(Crop::Pixels([10, 20, 110, 70])));
+        assert!(args.composite);
+        let window = args.crop.unwrap().window(400, 225).unwrap();
+        assert_eq!(
+            window,
+            Window {
+                x: 10,
+                y: 155,
+                width: 100,
+                height: 50
+            }
+        );
+        let crop = parse("--crop 0.5,0.,1.,0.5").unwrap().crop.unwrap();
+        assert_eq!(crop, Crop::Normalized([0.5, 0., 1., 0.5]));
+        let window = crop.window(400, 225).unwrap();
+        assert_eq!((window.x, window.y, window.width), (200, 112, 200));
+        assert_eq!(window.height, 113);
+        assert!(Crop::Pixels([0, 0, 401, 10]).window(400, 225).is_err());
+        assert!(Crop::Pixels([5, 0, 5, 10]).window(400, 225).is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("45s"), Ok(Duration::from_secs(45)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("1.5m"), Ok(Duration::from_secs(90)));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("10x").is_err());
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("99999999999999999999999").is_err());
+    }
+}
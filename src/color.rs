@@ -36,13 +36,11 @@ impl From<[u32; 3]> for Color {
     }
 }
 
+/** Gamma 2 encoding of a linear color; values outside [0, 1], e.g. on lights, are clamped. */
 impl From<Vec> for Color {
     fn from(value: Vec) -> Self {
-        Color::from([
-            (255.99 * value.x().sqrt()) as u32,
-            (255.99 * value.y().sqrt()) as u32,
-            (255.99 * value.z().sqrt()) as u32,
-        ])
+        let encode = |x: f32| (255.99 * x.clamp(0., 1.).sqrt()) as u32;
+        Color::from([encode(value.x()), encode(value.y()), encode(value.z())])
     }
 }
 
@@ -0,0 +1,414 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    error::{ensure, Error},
+    instance::{to_world, Moving, Transformed},
+    mesh::Mesh,
+    motion::Animation,
+    planar::Cuboid,
+    quadric::{Cone, Cylinder, Torus},
+    ray::{HitRecord, Ray},
+    shape::{Shape, Sphere},
+};
+
+/** A part of a ray inside a solid, between the records where it enters and exits. */
+#[derive(Clone, Copy)]
+pub struct Span<'a> {
+    pub enter: HitRecord<'a>,
+    pub exit: HitRecord<'a>,
+}
+
+/** Closed shapes, which can answer for every intersection along a ray. */
+pub trait Solid: Shape {
+    /** All disjoint spans of the whole line through `ray` inside the solid, sorted by t.
+     * The default collects every hit of `Shape::hit` and pairs entries with exits.
+     */
+    fn spans(&self, ray: Ray) -> Vec<Span<'_>> {
+        let mut spans = Vec::new();
+        let mut enter: Option<HitRecord> = None;
+        let mut t_min = f32::MIN;
+        while let Some(record) = self.hit(ray, t_min, f32::MAX) {
+            if record.t < t_min {
+                break;
+            }
+            t_min = record.t + 1e-4 * f32::max(1., record.t.abs());
+            match (record.is_front, enter) {
+                (true, None) => enter = Some(record),
+                (false, Some(start)) => {
+                    spans.push(Span {
+                        enter: start,
+                        exit: record,
+                    });
+                    enter = None;
+                }
+                _ => (),
+            }
+        }
+        spans
+    }
+
+    /** Whether the boundary encloses a volume. Shapes that can be left open, like
+     * uncapped cylinders, answer for their configuration; the CSG operations reject
+     * open operands, whose spans would never close.
+     */
+    fn is_closed(&self) -> bool {
+        true
+    }
+}
+
+/* A sphere is entered at its first crossing and left at its second, also when a
+ * negative radius turns its normals inwards and so flips `is_front`.
+ */
+impl Solid for Sphere {
+    fn spans(&self, ray: Ray) -> Vec<Span<'_>> {
+        let Some(enter) = self.hit(ray, f32::MIN, f32::MAX) else {
+            return Vec::new();
+        };
+        let t_min = enter.t + 1e-4 * f32::max(1., enter.t.abs());
+        match self.hit(ray, t_min, f32::MAX) {
+            Some(exit) => vec![Span { enter, exit }],
+            None => Vec::new(),
+        }
+    }
+}
+
+impl Solid for Cuboid {}
+
+impl Solid for Cylinder {
+    fn is_closed(&self) -> bool {
+        Cylinder::is_closed(self)
+    }
+}
+
+impl Solid for Cone {
+    fn is_closed(&self) -> bool {
+        Cone::is_closed(self)
+    }
+}
+
+impl Solid for Torus {
+    fn is_closed(&self) -> bool {
+        Torus::is_closed(self)
+    }
+}
+
+impl Solid for Mesh {
+    fn is_closed(&self) -> bool {
+        Mesh::is_closed(self)
+    }
+}
+
+impl<S: Solid + ?Sized> Solid for Arc<S> {
+    fn spans(&self, ray: Ray) -> Vec<Span<'_>> {
+        self.as_ref().spans(ray)
+    }
+
+    fn is_closed(&self) -> bool {
+        self.as_ref().is_closed()
+    }
+}
+
+impl<S: Solid> Solid for Transformed<S> {
+    fn spans(&self, ray: Ray) -> Vec<Span<'_>> {
+        let local_ray = self.transform().inverse().apply_ray(ray);
+        self.shape()
+            .spans(local_ray)
+            .into_iter()
+            .map(|span| Span {
+                enter: self.to_world(ray, span.enter),
+                exit: self.to_world(ray, span.exit),
+            })
+            .collect()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.shape().is_closed()
+    }
+}
+
+impl<S: Solid, A: Animation> Solid for Moving<S, A> {
+    fn spans(&self, ray: Ray) -> Vec<Span<'_>> {
+        let transform = self.motion().at(ray.time);
+        let local_ray = transform.inverse().apply_ray(ray);
+        self.shape()
+            .spans(local_ray)
+            .into_iter()
+            .map(|span| Span {
+                enter: to_world(&transform, ray, span.enter),
+                exit: to_world(&transform, ray, span.exit),
+            })
+            .collect()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.shape().is_closed()
+    }
+}
+
+/* Merges the spans of both operands, keeping the parts where `inside(in_a, in_b)` holds.
+ * Where a boundary of an operand flips the result the other way round than it flips the
+ * operand (e.g. leaving the subtracted solid of a difference), the record is turned inside out.
+ */
+fn combine<'a>(
+    a: Vec<Span<'a>>,
+    b: Vec<Span<'a>>,
+    inside: impl Fn(bool, bool) -> bool,
+) -> Vec<Span<'a>> {
+    let mut events: Vec<(HitRecord, bool, bool)> = Vec::new();
+    for (spans, is_a) in [(a, true), (b, false)] {
+        for span in spans {
+            events.push((span.enter, is_a, true));
+            events.push((span.exit, is_a, false));
+        }
+    }
+    events.sort_by(|x, y| x.0.t.total_cmp(&y.0.t));
+
+    let mut spans = Vec::new();
+    let mut enter: Option<HitRecord> = None;
+    let (mut in_a, mut in_b) = (false, false);
+    for (record, is_a, entering) in events {
+        let was_inside = inside(in_a, in_b);
+        if is_a {
+            in_a = entering;
+        } else {
+            in_b = entering;
+        }
+        let is_inside = inside(in_a, in_b);
+        if was_inside == is_inside {
+            continue;
+        }
+        let record = if is_inside == entering {
+            record
+        } else {
+            HitRecord {
+                is_front: !record.is_front,
+                ..record
+            }
+        };
+        match enter {
+            None => enter = Some(record),
+            Some(start) => {
+                spans.push(Span {
+                    enter: start,
+                    exit: record,
+                });
+                enter = None;
+            }
+        }
+    }
+    spans
+}
+
+fn check_closed(object: &str, a: &impl Solid, b: &impl Solid) -> Result<(), Error> {
+    ensure(
+        a.is_closed() && b.is_closed(),
+        object,
+        "operands must be closed solids",
+    )
+}
+
+/* The closest span boundary in [t_min, t_max]. */
+fn closest_hit(spans: Vec<Span<'_>>, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+    spans
+        .into_iter()
+        .flat_map(|span| [span.enter, span.exit])
+        .find(|record| t_min <= record.t && record.t <= t_max)
+}
+
+pub struct Union<A: Solid, B: Solid> {
+    a: A,
+    b: B,
+}
+
+impl<A: Solid, B: Solid> Union<A, B> {
+    pub fn new(a: A, b: B) -> Result<Union<A, B>, Error> {
+        check_closed("union", &a, &b)?;
+        Ok(Union { a, b })
+    }
+}
+
+impl<A: Solid, B: Solid> Shape for Union<A, B> {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        closest_hit(self.spans(ray), t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.a.bounding_box()?.union(&self.b.bounding_box()?))
+    }
+}
+
+impl<A: Solid, B: Solid> Solid for Union<A, B> {
+    fn spans(&self, ray: Ray) -> Vec<Span<'_>> {
+        combine(self.a.spans(ray), self.b.spans(ray), |a, b| a || b)
+    }
+}
+
+pub struct Intersection<A: Solid, B: Solid> {
+    a: A,
+    b: B,
+}
+
+impl<A: Solid, B: Solid> Intersection<A, B> {
+    pub fn new(a: A, b: B) -> Result<Intersection<A, B>, Error> {
+        check_closed("intersection", &a, &b)?;
+        Ok(Intersection { a, b })
+    }
+}
+
+impl<A: Solid, B: Solid> Shape for Intersection<A, B> {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        closest_hit(self.spans(ray), t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.a.bounding_box().or(self.b.bounding_box())
+    }
+}
+
+impl<A: Solid, B: Solid> Solid for Intersection<A, B> {
+    fn spans(&self, ray: Ray) -> Vec<Span<'_>> {
+        combine(self.a.spans(ray), self.b.spans(ray), |a, b| a && b)
+    }
+}
+
+/** The part of `a` outside of `b`; surfaces cut by `b` take the material of `b`. */
+pub struct Difference<A: Solid, B: Solid> {
+    a: A,
+    b: B,
+}
+
+impl<A: Solid, B: Solid> Difference<A, B> {
+    pub fn new(a: A, b: B) -> Result<Difference<A, B>, Error> {
+        check_closed("difference", &a, &b)?;
+        Ok(Difference { a, b })
+    }
+}
+
+impl<A: Solid, B: Solid> Shape for Difference<A, B> {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        closest_hit(self.spans(ray), t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.a.bounding_box()
+    }
+}
+
+impl<A: Solid, B: Solid> Solid for Difference<A, B> {
+    fn spans(&self, ray: Ray) -> Vec<Span<'_>> {
+        combine(self.a.spans(ray), self.b.spans(ray), |a, b| a && !b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        material::{Lambertian, Material},
+        point::Point,
+        vec::Vec,
+    };
+
+    fn materials() -> (Arc<dyn Material>, Arc<dyn Material>) {
+        (
+            Arc::new(Lambertian::new_const([1., 0., 0.])),
+            Arc::new(Lambertian::new_const([0., 0., 1.])),
+        )
+    }
+
+    fn is(material: &dyn Material, expected: &Arc<dyn Material>) -> bool {
+        std::ptr::addr_eq(material, expected.as_ref())
+    }
+
+    fn sphere(x: f32, material: &Arc<dyn Material>) -> Sphere {
+        Sphere::new(Point::from([x, 0., 0.]), 1., material.clone()).unwrap()
+    }
+
+    #[test]
+    fn test_spans() {
+        let (red, _) = materials();
+        let ray = Ray::from(Point::from([-5., 0., 0.]), Vec::from([1., 0., 0.]));
+        let sphere = sphere(0., &red);
+        let spans = sphere.spans(ray);
+        assert_eq!(spans.len(), 1);
+        assert_eq!((spans[0].enter.t, spans[0].exit.t), (4., 6.));
+
+        let hollow = Sphere::new(Point::new(), -1., red).unwrap();
+        let spans = hollow.spans(ray);
+        assert_eq!(spans.len(), 1);
+        assert_eq!((spans[0].enter.t, spans[0].exit.t), (4., 6.));
+        assert!(!spans[0].enter.is_front && spans[0].exit.is_front);
+    }
+
+    #[test]
+    fn test_open_operands() {
+        let (red, _) = materials();
+        let cylinder =
+            |phi_max, capped| Cylinder::new(1., -1., 1., phi_max, capped, red.clone()).unwrap();
+        assert!(Union::new(cylinder(360., true), sphere(0., &red)).is_ok());
+        assert!(Union::new(cylinder(360., false), sphere(0., &red)).is_err());
+        assert!(Intersection::new(sphere(0., &red), cylinder(180., true)).is_err());
+        let torus = Torus::new(2., 0.5, 90., red.clone()).unwrap();
+        assert!(Difference::new(sphere(0., &red), torus).is_err());
+        let triangle = Mesh::new(
+            vec![
+                Point::new(),
+                Point::from([1., 0., 0.]),
+                Point::from([0., 1., 0.]),
+            ],
+            vec![[0, 1, 2]],
+            red.clone(),
+        )
+        .unwrap();
+        assert!(Union::new(Arc::new(triangle), sphere(0., &red)).is_err());
+    }
+
+    #[test]
+    fn test_union() {
+        let (red, blue) = materials();
+        let ray = Ray::from(Point::from([-5., 0., 0.]), Vec::from([1., 0., 0.]));
+        let union = Union::new(sphere(0., &red), sphere(1., &blue)).unwrap();
+        let spans = union.spans(ray);
+        assert_eq!(spans.len(), 1);
+        assert_eq!((spans[0].enter.t, spans[0].exit.t), (4., 7.));
+        assert!(is(spans[0].exit.material, &blue));
+
+        let inside = Ray::from(Point::from([0.5, 0., 0.]), Vec::from([1., 0., 0.]));
+        let record = union.hit(inside, 0.001, f32::MAX).unwrap();
+        assert_eq!(record.t, 1.5);
+        assert!(!record.is_front);
+    }
+
+    #[test]
+    fn test_intersection() {
+        let (red, blue) = materials();
+        let ray = Ray::from(Point::from([-5., 0., 0.]), Vec::from([1., 0., 0.]));
+        let lens = Intersection::new(sphere(0., &red), sphere(1., &blue)).unwrap();
+        let record = lens.hit(ray, 0.001, f32::MAX).unwrap();
+        assert_eq!(record.t, 5.);
+        assert!(record.is_front && is(record.material, &blue));
+        assert_eq!(record.n.x(), -1.);
+    }
+
+    #[test]
+    fn test_difference() {
+        let (red, blue) = materials();
+        let ray = Ray::from(Point::from([5., 0., 0.]), Vec::from([-1., 0., 0.]));
+        let bitten = Difference::new(sphere(0., &red), sphere(1., &blue)).unwrap();
+        let record = bitten.hit(ray, 0.001, f32::MAX).unwrap();
+        assert_eq!(record.t, 5.);
+        assert!(record.is_front && is(record.material, &blue));
+        assert_eq!(record.n.x(), 1.);
+
+        let union = Union::new(sphere(0., &red), sphere(3., &red)).unwrap();
+        let nested = Difference::new(union, bitten).unwrap();
+        let spans = nested.spans(ray);
+        assert_eq!(spans.len(), 2);
+        assert_eq!((spans[0].enter.t, spans[0].exit.t), (1., 3.));
+        assert_eq!((spans[1].enter.t, spans[1].exit.t), (4., 5.));
+        assert!(is(spans[1].exit.material, &blue) && !spans[1].exit.is_front);
+    }
+}
@@ -0,0 +1,263 @@
+use std::ops::{Add, Mul, Sub};
+
+use crate::error::{ensure, Error};
+
+/** Values that can be keyframed: anything that can be blended linearly. */
+pub trait Animatable:
+    Copy + Add<Output = Self> + Sub<Output = Self> + Mul<f32, Output = Self>
+{
+}
+
+impl<T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T>> Animatable for T {}
+
+/** How a curve moves from a key to the next one. */
+#[derive(Debug, Clone, Copy)]
+pub enum Interpolation<T> {
+    Linear,
+    /* A cubic Bezier segment; the handles are the inner control values of the segment. */
+    Bezier {
+        out_handle: T,
+        in_handle: T,
+    },
+    /* A Kochanek-Bartels spline through the neighbouring keys; all zeros is Catmull-Rom. */
+    Tcb {
+        tension: f32,
+        continuity: f32,
+        bias: f32,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Key<T> {
+    pub time: f32,
+    pub value: T,
+    pub interpolation: Interpolation<T>,
+}
+
+impl<T> Key<T> {
+    pub fn linear(time: f32, value: T) -> Key<T> {
+        Key {
+            time,
+            value,
+            interpolation: Interpolation::Linear,
+        }
+    }
+
+    pub fn bezier(time: f32, value: T, out_handle: T, in_handle: T) -> Key<T> {
+        Key {
+            time,
+            value,
+            interpolation: Interpolation::Bezier {
+                out_handle,
+                in_handle,
+            },
+        }
+    }
+
+    pub fn tcb(time: f32, value: T, tension: f32, continuity: f32, bias: f32) -> Key<T> {
+        Key {
+            time,
+            value,
+            interpolation: Interpolation::Tcb {
+                tension,
+                continuity,
+                bias,
+            },
+        }
+    }
+}
+
+/** A keyframed value. The interpolation of a key shapes the segment leaving it;
+ * before the first and after the last key the curve holds still.
+ */
+#[derive(Debug, Clone)]
+pub struct Curve<T: Animatable> {
+    keys: Vec<Key<T>>,
+}
+
+impl<T: Animatable> Curve<T> {
+    pub fn new(keys: Vec<Key<T>>) -> Result<Curve<T>, Error> {
+        ensure(!keys.is_empty(), "curve", "it has no keys")?;
+        let mut keys = keys;
+        keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Ok(Curve { keys })
+    }
+
+    pub fn constant(value: T) -> Curve<T> {
+        Curve {
+            keys: vec![Key::linear(0., value)],
+        }
+    }
+
+    pub fn keys(&self) -> &[Key<T>] {
+        &self.keys
+    }
+
+    pub fn at(&self, time: f32) -> T {
+        let index = self.keys.partition_point(|key| key.time <= time);
+        if index == 0 {
+            return self.keys[0].value;
+        }
+        if index == self.keys.len() {
+            return self.keys[index - 1].value;
+        }
+        let i = index - 1;
+        let (a, b) = (&self.keys[i], &self.keys[i + 1]);
+        let u = (time - a.time) / (b.time - a.time);
+        if let Interpolation::Linear = a.interpolation {
+            return a.value + (b.value - a.value) * u;
+        }
+        let [p0, p1, p2, p3] = self.control_values(i);
+        let v = 1. - u;
+        p0 * (v * v * v) + p1 * (3. * v * v * u) + p2 * (3. * v * u * u) + p3 * (u * u * u)
+    }
+
+    /** The cubic Bezier control values of the segment from key i to key i + 1. The
+     * segment stays within their convex hull and its derivative with respect to the
+     * segment parameter within that of three times their differences.
+     */
+    pub fn control_values(&self, i: usize) -> [T; 4] {
+        let (a, b) = (&self.keys[i], &self.keys[i + 1]);
+        match a.interpolation {
+            Interpolation::Linear => {
+                let third = (b.value - a.value) * (1. / 3.);
+                [a.value, a.value + third, b.value - third, b.value]
+            }
+            Interpolation::Bezier {
+                out_handle,
+                in_handle,
+            } => [a.value, out_handle, in_handle, b.value],
+            Interpolation::Tcb {
+                tension,
+                continuity,
+                bias,
+            } => {
+                let (out_tangent, _) = self.tcb_tangents(i, tension, continuity, bias);
+                /* The incoming tangent follows the parameters of the next key if it has any. */
+                let (t, c, s) = match b.interpolation {
+                    Interpolation::Tcb {
+                        tension,
+                        continuity,
+                        bias,
+                    } => (tension, continuity, bias),
+                    _ => (tension, continuity, bias),
+                };
+                let (_, in_tangent) = self.tcb_tangents(i + 1, t, c, s);
+                [
+                    a.value,
+                    a.value + out_tangent * (1. / 3.),
+                    b.value - in_tangent * (1. / 3.),
+                    b.value,
+                ]
+            }
+        }
+    }
+
+    /* The outgoing and incoming Kochanek-Bartels tangents at key i. End keys reuse
+     * their only neighbour on both sides.
+     */
+    fn tcb_tangents(&self, i: usize, tension: f32, continuity: f32, bias: f32) -> (T, T) {
+        let value = self.keys[i].value;
+        let prev = if i > 0 {
+            Some(self.keys[i - 1].value)
+        } else {
+            None
+        };
+        let next = self.keys.get(i + 1).map(|key| key.value);
+        let (before, after) = match (prev, next) {
+            (Some(prev), Some(next)) => (value - prev, next - value),
+            (Some(prev), None) => (value - prev, value - prev),
+            (None, Some(next)) => (next - value, next - value),
+            (None, None) => (value * 0., value * 0.),
+        };
+        let (t, c, b) = (tension, continuity, bias);
+        let out_tangent = before * ((1. - t) * (1. + c) * (1. + b) / 2.)
+            + after * ((1. - t) * (1. - c) * (1. - b) / 2.);
+        let in_tangent = before * ((1. - t) * (1. - c) * (1. + b) / 2.)
+            + after * ((1. - t) * (1. + c) * (1. - b) / 2.);
+        (out_tangent, in_tangent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec::Vec;
+
+    #[test]
+    fn test_linear() {
+        let curve = Curve::new(vec![Key::linear(1., 10.), Key::linear(0., 0.)]).unwrap();
+        assert_eq!(curve.at(0.25), 2.5);
+        assert_eq!(curve.at(-1.), 0.);
+        assert_eq!(curve.at(2.), 10.);
+
+        let curve = Curve::new(vec![
+            Key::linear(0., Vec::from([0., 0., 0.])),
+            Key::linear(2., Vec::from([2., 4., 6.])),
+        ])
+        .unwrap();
+        assert_eq!(curve.at(1.).z(), 3.);
+        assert!(Curve::<f32>::new(vec![]).is_err());
+    }
+
+    #[test]
+    fn test_bezier() {
+        let curve = Curve::new(vec![Key::bezier(0., 0., 0., 1.), Key::linear(1., 1.)]).unwrap();
+        assert_eq!(curve.at(0.), 0.);
+        assert_eq!(curve.at(1.), 1.);
+        assert_eq!(curve.at(0.5), 0.5);
+        assert!(curve.at(0.25) < 0.25);
+
+        let straight = Curve::new(vec![
+            Key::bezier(0., 0., 1. / 3., 2. / 3.),
+            Key::linear(1., 1.),
+        ])
+        .unwrap();
+        assert!((straight.at(0.3) - 0.3).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_tcb() {
+        let keys = [0., 1., 0., 1.];
+        let curve = Curve::new(
+            keys.iter()
+                .enumerate()
+                .map(|(i, &v)| Key::tcb(i as f32, v, 0., 0., 0.))
+                .collect(),
+        )
+        .unwrap();
+        for (i, &v) in keys.iter().enumerate() {
+            assert!((curve.at(i as f32) - v).abs() < 1e-6);
+        }
+        assert!((curve.at(1.5) - 0.5).abs() < 1e-6);
+        assert!(curve.at(0.9) > 0.9);
+
+        let tense = Curve::new(vec![
+            Key::tcb(0., 0., 1., 0., 0.),
+            Key::tcb(1., 1., 1., 0., 0.),
+        ])
+        .unwrap();
+        assert!(tense.at(0.1) < 0.1);
+    }
+
+    #[test]
+    fn test_control_values() {
+        let curve = Curve::new(vec![
+            Key::tcb(0., 0., 0.5, 0., 0.),
+            Key::linear(1., 3.),
+            Key::tcb(3., 1., 0., 0., 0.),
+            Key::linear(4., 2.),
+        ])
+        .unwrap();
+        for i in 0..3 {
+            let p = curve.control_values(i);
+            let [a, b] = [curve.keys()[i].time, curve.keys()[i + 1].time];
+            let lowest = p.iter().copied().fold(f32::MAX, f32::min);
+            let highest = p.iter().copied().fold(f32::MIN, f32::max);
+            for k in 0..=16 {
+                let value = curve.at(a + (b - a) * k as f32 / 16.);
+                assert!(lowest - 1e-6 <= value && value <= highest + 1e-6);
+            }
+        }
+    }
+}
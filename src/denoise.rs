@@ -0,0 +1,202 @@
+use crate::{aov::Aov, film::Film, vec::Vec};
+
+/** Edge-avoiding À-trous wavelet filter (Dammertz et al. 2010). Each iteration blurs
+ * with a 5x5 B3-spline kernel whose taps are spread twice as far apart as in the
+ * previous one, weighting every tap by how alike the two pixels are: in color, relative
+ * to the noise the film measured in them, and in the albedo, normal and depth AOVs when
+ * the film records them. Edges in any of these stop the blur.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Denoiser {
+    /** Scales how large a color difference is still taken for noise; 0 disables it. */
+    pub strength: f32,
+    pub iterations: u32,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Denoiser {
+            strength: 1.,
+            iterations: 5,
+        }
+    }
+}
+
+const KERNEL: [f32; 5] = [1. / 16., 1. / 4., 3. / 8., 1. / 4., 1. / 16.];
+const NORMAL_EXPONENT: i32 = 32;
+const SIGMA_ALBEDO: f32 = 0.1;
+const SIGMA_DEPTH: f32 = 0.05;
+
+/* The per-pixel inputs of the filter. */
+struct Guide {
+    normal: Vec,
+    albedo: Vec,
+    depth: f32,
+}
+
+impl Guide {
+    /* How alike two pixels are in the AOVs, in [0, 1]. Pixels whose camera rays
+     * escaped have zero normals and only match each other.
+     */
+    fn weight(&self, other: &Guide, step: usize) -> f32 {
+        let normal = match (self.normal.near_zero(), other.normal.near_zero()) {
+            (true, true) => 1.,
+            (false, false) => (self.normal * other.normal).max(0.).powi(NORMAL_EXPONENT),
+            _ => 0.,
+        };
+        let albedo = self.albedo - other.albedo;
+        let albedo = (-(albedo * albedo) / (SIGMA_ALBEDO * SIGMA_ALBEDO)).exp();
+        /* Depth changes steadily along slanted surfaces, more so across wider taps. */
+        let depth = (self.depth - other.depth).abs()
+            / (SIGMA_DEPTH * step as f32 * self.depth.max(other.depth) + 1e-4);
+        normal * albedo * (-depth * depth).exp()
+    }
+}
+
+impl Denoiser {
+    /** Returns a copy of the film with denoised pixels; sample counts are kept. */
+    pub fn denoise(&self, film: &Film) -> Film {
+        let (width, height) = (film.width(), film.height());
+        let mut denoised = film.clone();
+        if self.strength <= 0. || width == 0 || height == 0 {
+            return denoised;
+        }
+        let mut guides = std::vec::Vec::with_capacity(width * height);
+        let mut colors = std::vec::Vec::with_capacity(width * height);
+        let mut variances = std::vec::Vec::with_capacity(width * height);
+        for j in 0..height {
+            for i in 0..width {
+                guides.push(Guide {
+                    normal: film.aov(Aov::Normal, i, j),
+                    albedo: film.aov(Aov::Albedo, i, j),
+                    depth: film.aov(Aov::Depth, i, j).x(),
+                });
+                let pixel = film.pixel(i, j);
+                colors.push(pixel.mean());
+                /* The variance of the pixel's estimate, not of its samples. */
+                variances.push(pixel.variance() / pixel.samples.max(1) as f32);
+            }
+        }
+
+        let strength = self.strength * self.strength;
+        for iteration in 0..self.iterations {
+            let step = 1 << iteration;
+            let mut filtered = colors.clone();
+            for j in 0..height {
+                for i in 0..width {
+                    let p = j * width + i;
+                    let (mut sum, mut total) = (Vec::new(), 0.);
+                    for (dy, ky) in KERNEL.iter().enumerate() {
+                        for (dx, kx) in KERNEL.iter().enumerate() {
+                            let x = i as isize + (dx as isize - 2) * step as isize;
+                            let y = j as isize + (dy as isize - 2) * step as isize;
+                            if x < 0 || y < 0 || x >= width as isize || y >= height as isize {
+                                continue;
+                            }
+                            let q = y as usize * width + x as usize;
+                            let difference = colors[p] - colors[q];
+                            /* Two estimates of the same color differ by the sum of their
+                             * variances on average; the variances themselves are rough
+                             * estimates at low sample counts, hence the generous factor.
+                             */
+                            let noise = 8. * strength * (variances[p] + variances[q]) + 1e-6;
+                            let weight = kx
+                                * ky
+                                * guides[p].weight(&guides[q], step)
+                                * (-(difference * difference) / noise).exp();
+                            sum += colors[q] * weight;
+                            total += weight;
+                        }
+                    }
+                    /* The center tap always has a positive weight. */
+                    filtered[p] = sum / total;
+                }
+            }
+            colors = filtered;
+        }
+
+        for j in 0..height {
+            for i in 0..width {
+                let pixel = denoised.pixel_mut(i, j);
+                pixel.sum = colors[j * width + i] * pixel.samples as f32;
+            }
+        }
+        denoised
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        camera::Camera,
+        material::{DiffuseLight, Lambertian, Material},
+        point::Point,
+        render::{render_film, Settings},
+        scene::Scene,
+        shape::Sphere,
+    };
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new_const([0.7, 0.3, 0.3]))
+    }
+    fn ground() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new_const([0.5, 0.5, 0.5]))
+    }
+    fn room() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new_const([0.2, 0.2, 0.2]))
+    }
+    fn light() -> Arc<dyn Material> {
+        Arc::new(DiffuseLight::new_const([8., 8., 8.]))
+    }
+
+    fn film(spp: u32, seed: u64) -> Film {
+        let mut scene = Scene::new();
+        scene.push(Sphere::new(Point::from([0., 0., -1.]), 0.5, material()).unwrap());
+        scene.push(Sphere::new(Point::from([0., -100.5, -1.]), 100., ground()).unwrap());
+        /* A dark room lit by a small light is noisy everywhere, not just along edges. */
+        scene.push(Sphere::new(Point::from([0., 0., 0.]), 20., room()).unwrap());
+        scene.push(Sphere::new(Point::from([1., 1.5, -0.5]), 0.4, light()).unwrap());
+        let mut film = Film::new(32, 18);
+        for aov in [Aov::Normal, Aov::Albedo, Aov::Depth] {
+            film.add_layer(aov);
+        }
+        let settings = Settings {
+            spp,
+            seed,
+            ..Settings::default()
+        };
+        render_film(&scene, &Camera::new(), &settings, &mut film).unwrap();
+        film
+    }
+
+    fn rmse(a: &Film, b: &Film) -> f32 {
+        let mut total = 0.;
+        for j in 0..a.height() {
+            for i in 0..a.width() {
+                let difference = a.pixel(i, j).mean() - b.pixel(i, j).mean();
+                total += difference * difference;
+            }
+        }
+        (total / (3 * a.width() * a.height()) as f32).sqrt()
+    }
+
+    #[test]
+    fn test_denoise() {
+        /* 128 times the samples of the noisy film, so its own noise hardly counts. */
+        let reference = film(512, 1);
+        let noisy = film(4, 2);
+        let denoised = Denoiser::default().denoise(&noisy);
+        let (before, after) = (rmse(&noisy, &reference), rmse(&denoised, &reference));
+        assert!(after < 0.6 * before, "{before} -> {after}");
+        assert_eq!(denoised.pixel(3, 4).samples, noisy.pixel(3, 4).samples);
+
+        let off = Denoiser {
+            strength: 0.,
+            ..Denoiser::default()
+        };
+        assert_eq!(rmse(&off.denoise(&noisy), &noisy), 0.);
+    }
+}
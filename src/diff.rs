@@ -0,0 +1,398 @@
+use std::fmt;
+
+use crate::{
+    color::Color,
+    error::Error,
+    image::{self, Exr, Image, Png, Ppm},
+    vec::Vec,
+};
+
+/* Keeps the relative error of nearly black reference pixels finite. */
+const RELATIVE_EPSILON: f32 = 0.01;
+/* Width and standard deviation of the Gaussian window of SSIM, and its constants for
+ * values in [0, 1] (Wang et al. 2004).
+ */
+const SSIM_RADIUS: isize = 5;
+const SSIM_SIGMA: f32 = 1.5;
+const SSIM_C1: f32 = 0.01 * 0.01;
+const SSIM_C2: f32 = 0.03 * 0.03;
+
+/** The ways `LinearImage::compare` measures how far an image is from a reference. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    /** Mean squared error of the linear channels. */
+    Mse,
+    Rmse,
+    /** Mean squared error relative to the squared reference, which weighs errors in
+     * dark regions as much as in bright ones.
+     */
+    RelMse,
+    /** Peak signal-to-noise ratio in dB, for a peak of 1: the white of 8-bit images. */
+    Psnr,
+    /** Mean structural similarity of the displayed luminance, 1 for equal images. */
+    Ssim,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 5] = [
+        Metric::Mse,
+        Metric::Rmse,
+        Metric::RelMse,
+        Metric::Psnr,
+        Metric::Ssim,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::Mse => "mse",
+            Metric::Rmse => "rmse",
+            Metric::RelMse => "relmse",
+            Metric::Psnr => "psnr",
+            Metric::Ssim => "ssim",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Metric> {
+        Metric::ALL.into_iter().find(|metric| metric.name() == name)
+    }
+
+    /** Whether a value of the metric is as good as the threshold or better; PSNR and
+     * SSIM grow as images get closer, the others shrink.
+     */
+    pub fn is_within(&self, value: f32, threshold: f32) -> bool {
+        match self {
+            Metric::Psnr | Metric::Ssim => value >= threshold,
+            _ => value <= threshold,
+        }
+    }
+}
+
+/** All the metrics of one comparison. */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Metrics {
+    pub mse: f32,
+    pub rmse: f32,
+    pub rel_mse: f32,
+    /** Infinite for equal images. */
+    pub psnr: f32,
+    pub ssim: f32,
+}
+
+impl Metrics {
+    pub fn get(&self, metric: Metric) -> f32 {
+        match metric {
+            Metric::Mse => self.mse,
+            Metric::Rmse => self.rmse,
+            Metric::RelMse => self.rel_mse,
+            Metric::Psnr => self.psnr,
+            Metric::Ssim => self.ssim,
+        }
+    }
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "MSE {:.6}, RMSE {:.6}, relMSE {:.6}, PSNR {:.2} dB, SSIM {:.4}",
+            self.mse, self.rmse, self.rel_mse, self.psnr, self.ssim
+        )
+    }
+}
+
+/** An image as linear RGB values, rows counting from the bottom like `Image::plot`.
+ * It is what images of any format are compared as.
+ */
+#[derive(Debug, Clone)]
+pub struct LinearImage {
+    pub width: usize,
+    pub height: usize,
+    values: std::vec::Vec<Vec>,
+}
+
+impl LinearImage {
+    /** Decodes 8-bit colors with the gamma `Color::from(Vec)` encodes them with. */
+    pub fn from_image(image: &dyn Image) -> LinearImage {
+        let (width, height) = (image.width(), image.height());
+        let mut values = std::vec::Vec::with_capacity(width * height);
+        for x in 0..height {
+            for y in 0..width {
+                let color = image.pixel(x, y).unwrap_or_default();
+                let decode = |index: usize| (color.at(index) as f32 / 255.).powi(2);
+                values.push(Vec::from([decode(0), decode(1), decode(2)]));
+            }
+        }
+        LinearImage {
+            width,
+            height,
+            values,
+        }
+    }
+
+    /** Reads a PPM, PNG or EXR file, by its extension. An EXR image gives its R, G and
+     * B channels, or without them the channels of its only layer, e.g. `normal.X`,
+     * `normal.Y` and `normal.Z`; a single channel is read as grey.
+     */
+    pub fn from_file(fname: &str) -> Result<LinearImage, Error> {
+        match image::extension(fname).as_deref() {
+            Some("ppm") => Ok(LinearImage::from_image(&Ppm::from_file(fname)?)),
+            Some("png") => Ok(LinearImage::from_image(&Png::from_file(fname)?)),
+            Some("exr") => LinearImage::from_exr(&Exr::from_file(fname)?, fname),
+            _ => Err(Error::unsupported_format(
+                fname,
+                "image, expected a .ppm, .png or .exr file",
+            )),
+        }
+    }
+
+    fn from_exr(exr: &Exr, fname: &str) -> Result<LinearImage, Error> {
+        fn layer(name: &str) -> &str {
+            name.rsplit_once('.').map_or("", |(layer, _)| layer)
+        }
+        let names: std::vec::Vec<&str> = exr.channel_names().collect();
+        let channels = if ["R", "G", "B"].iter().all(|name| names.contains(name)) {
+            vec!["R", "G", "B"]
+        } else if !names.is_empty() && names.iter().all(|name| layer(name) == layer(names[0])) {
+            names.into_iter().take(3).collect()
+        } else {
+            return Err(Error::unsupported_format(
+                fname,
+                "EXR without R, G and B channels or a single layer",
+            ));
+        };
+        let channels: std::vec::Vec<&[f32]> = channels
+            .into_iter()
+            .filter_map(|name| exr.channel(name))
+            .collect();
+        let values = (0..exr.width * exr.height)
+            .map(|k| match channels[..] {
+                [grey] => Vec::from([grey[k]; 3]),
+                [x, y] => Vec::from([x[k], y[k], 0.]),
+                _ => Vec::from([channels[0][k], channels[1][k], channels[2][k]]),
+            })
+            .collect();
+        Ok(LinearImage {
+            width: exr.width,
+            height: exr.height,
+            values,
+        })
+    }
+
+    /** The value at row x from the bottom and column y. */
+    pub fn at(&self, x: usize, y: usize) -> Vec {
+        self.values[x * self.width + y]
+    }
+
+    /** Averages blocks of block x block pixels into one, dropping the pixels of partial
+     * blocks at the top and right edges. Noise averages out, while shifts in color or
+     * brightness over whole regions remain, so noisy renders compare better this way.
+     */
+    pub fn downsampled(&self, block: usize) -> LinearImage {
+        let block = block.max(1);
+        let (width, height) = (self.width / block, self.height / block);
+        let mut values = std::vec::Vec::with_capacity(width * height);
+        for row in 0..height {
+            for column in 0..width {
+                let mut sum = Vec::new();
+                for x in row * block..(row + 1) * block {
+                    for y in column * block..(column + 1) * block {
+                        sum += self.at(x, y);
+                    }
+                }
+                values.push(sum / (block * block) as f32);
+            }
+        }
+        LinearImage {
+            width,
+            height,
+            values,
+        }
+    }
+
+    fn check_size(&self, reference: &LinearImage) -> Result<(), Error> {
+        if (self.width, self.height) != (reference.width, reference.height) {
+            return Err(Error::invalid_argument(
+                "reference",
+                format!(
+                    "{}x{} image for a {}x{} one",
+                    reference.width, reference.height, self.width, self.height
+                ),
+            ));
+        }
+        if self.values.is_empty() {
+            return Err(Error::invalid_argument("image", "nothing to compare"));
+        }
+        Ok(())
+    }
+
+    /** Measures how far the image is from a reference of the same size. */
+    pub fn compare(&self, reference: &LinearImage) -> Result<Metrics, Error> {
+        self.check_size(reference)?;
+        let (mut squared, mut relative) = (0_f64, 0_f64);
+        for (value, expected) in self.values.iter().zip(&reference.values) {
+            for c in 0..3 {
+                let difference = (value.at(c) - expected.at(c)) as f64;
+                squared += difference * difference;
+                relative += difference * difference
+                    / (expected.at(c) * expected.at(c) + RELATIVE_EPSILON) as f64;
+            }
+        }
+        let count = 3. * self.values.len() as f64;
+        let mse = (squared / count) as f32;
+        Ok(Metrics {
+            mse,
+            rmse: mse.sqrt(),
+            rel_mse: (relative / count) as f32,
+            psnr: -10. * mse.log10(),
+            ssim: self.ssim(reference),
+        })
+    }
+
+    /* SSIM over a Gaussian window around every pixel, cut off at the image borders. */
+    fn ssim(&self, reference: &LinearImage) -> f32 {
+        let display = |image: &LinearImage| -> std::vec::Vec<f32> {
+            let luminance = image.values.iter().map(Vec::luminance);
+            luminance.map(|l| l.clamp(0., 1.).sqrt()).collect()
+        };
+        let (a, b) = (display(self), display(reference));
+        let (width, height) = (self.width as isize, self.height as isize);
+        let mut total = 0_f64;
+        for x in 0..height {
+            for y in 0..width {
+                let (mut weights, mut mean_a, mut mean_b) = (0., 0., 0.);
+                let (mut aa, mut bb, mut ab) = (0., 0., 0.);
+                for dx in -SSIM_RADIUS..=SSIM_RADIUS {
+                    for dy in -SSIM_RADIUS..=SSIM_RADIUS {
+                        let (i, j) = (x + dx, y + dy);
+                        if i < 0 || j < 0 || i >= height || j >= width {
+                            continue;
+                        }
+                        let k = (i * width + j) as usize;
+                        let distance = (dx * dx + dy * dy) as f32;
+                        let weight = (-distance / (2. * SSIM_SIGMA * SSIM_SIGMA)).exp();
+                        weights += weight;
+                        mean_a += weight * a[k];
+                        mean_b += weight * b[k];
+                        aa += weight * a[k] * a[k];
+                        bb += weight * b[k] * b[k];
+                        ab += weight * a[k] * b[k];
+                    }
+                }
+                let (mean_a, mean_b) = (mean_a / weights, mean_b / weights);
+                let variance_a = aa / weights - mean_a * mean_a;
+                let variance_b = bb / weights - mean_b * mean_b;
+                let covariance = ab / weights - mean_a * mean_b;
+                let ssim = (2. * mean_a * mean_b + SSIM_C1) * (2. * covariance + SSIM_C2)
+                    / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1)
+                        * (variance_a + variance_b + SSIM_C2));
+                total += ssim as f64;
+            }
+        }
+        (total / self.values.len() as f64) as f32
+    }
+
+    /** Plots the error of every pixel, the root mean square difference of its channels,
+     * in false color from blue for none through green to red for the largest one, which
+     * it returns.
+     */
+    pub fn heatmap(&self, reference: &LinearImage, image: &mut dyn Image) -> Result<f32, Error> {
+        self.check_size(reference)?;
+        let errors: std::vec::Vec<f32> = self
+            .values
+            .iter()
+            .zip(&reference.values)
+            .map(|(&value, &expected)| {
+                let difference = value - expected;
+                (difference * difference / 3.).sqrt()
+            })
+            .collect();
+        let largest = errors.iter().copied().fold(0., f32::max);
+        for (k, error) in errors.into_iter().enumerate() {
+            let t = if largest > 0. { error / largest } else { 0. };
+            image.plot(k / self.width, k % self.width, false_color(t))?;
+        }
+        Ok(largest)
+    }
+}
+
+/* Maps [0, 1] to blue, cyan, green, yellow and red. */
+fn false_color(t: f32) -> Color {
+    const STOPS: [[f32; 3]; 5] = [
+        [0., 0., 1.],
+        [0., 1., 1.],
+        [0., 1., 0.],
+        [1., 1., 0.],
+        [1., 0., 0.],
+    ];
+    let t = t.clamp(0., 1.) * (STOPS.len() - 1) as f32;
+    let k = (t as usize).min(STOPS.len() - 2);
+    let (from, to, f) = (STOPS[k], STOPS[k + 1], t - k as f32);
+    let channel = |c: usize| (255. * (from[c] + (to[c] - from[c]) * f)).round() as u32;
+    Color::from([channel(0), channel(1), channel(2)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(width: usize, height: usize) -> Ppm {
+        let mut ppm = Ppm::with_size(width, height);
+        for x in 0..height {
+            for y in 0..width {
+                let level = (255 * (x + y) / (width + height)) as u32;
+                ppm.plot(x, y, Color::from([level, 255 - level, 128]))
+                    .unwrap();
+            }
+        }
+        ppm
+    }
+
+    #[test]
+    fn test_compare() {
+        let image = LinearImage::from_image(&gradient(24, 16));
+        let metrics = image.compare(&image).unwrap();
+        assert_eq!((metrics.mse, metrics.rel_mse), (0., 0.));
+        assert_eq!(metrics.psnr, f32::INFINITY);
+        assert!((metrics.ssim - 1.).abs() < 1e-4);
+
+        let mut brighter = image.clone();
+        for value in &mut brighter.values {
+            *value += Vec::from([0.1, 0.1, 0.1]);
+        }
+        let metrics = brighter.compare(&image).unwrap();
+        assert!((metrics.rmse - 0.1).abs() < 1e-4);
+        assert!((metrics.psnr - 20.).abs() < 1e-2);
+        assert!(metrics.ssim < 1.);
+        assert!(Metric::Rmse.is_within(metrics.get(Metric::Rmse), 0.2));
+        assert!(!Metric::Psnr.is_within(metrics.get(Metric::Psnr), 30.));
+
+        let smaller = LinearImage::from_image(&gradient(24, 15));
+        assert!(image.compare(&smaller).is_err());
+    }
+
+    #[test]
+    fn test_downsampled_and_heatmap() {
+        let image = LinearImage::from_image(&gradient(24, 16));
+        let small = image.downsampled(5);
+        assert_eq!((small.width, small.height), (4, 3));
+        let mean = (0..5).flat_map(|x| (0..5).map(move |y| (x, y)));
+        let mean = mean.map(|(x, y)| image.at(x, y)).sum::<Vec>() / 25.;
+        assert!((small.at(0, 0) - mean).len() < 1e-6);
+
+        let mut changed = image.clone();
+        changed.values[3 * 24 + 5] = Vec::from([1., 1., 1.]);
+        let mut heatmap = Ppm::with_size(24, 16);
+        assert!(changed.heatmap(&image, &mut heatmap).unwrap() > 0.);
+        let (hot, cold) = (heatmap.pixel(3, 5).unwrap(), heatmap.pixel(0, 0).unwrap());
+        assert_eq!([hot.r(), hot.g(), hot.b()], [255, 0, 0]);
+        assert_eq!([cold.r(), cold.g(), cold.b()], [0, 0, 255]);
+    }
+
+    #[test]
+    fn test_metric_names() {
+        for metric in Metric::ALL {
+            assert_eq!(Metric::from_name(metric.name()), Some(metric));
+        }
+        assert_eq!(Metric::from_name("flip"), None);
+    }
+}
@@ -0,0 +1,108 @@
+use std::{fmt, io};
+
+/** Everything that can go wrong in the library, with what it went wrong on. */
+#[derive(Debug)]
+pub enum Error {
+    /** Reading or writing `path` failed. */
+    Io { path: String, source: io::Error },
+    /** Malformed input; `source` names the file or argument it came from. */
+    Parse { source: String, message: String },
+    /** A scene object that cannot be rendered, e.g. a sphere of radius zero. */
+    InvalidScene { object: String, message: String },
+    /** A parameter outside of what it may be, e.g. a crop window outside the image. */
+    InvalidArgument { parameter: String, message: String },
+    /** `path` is in a format, or a version of one, that is not supported. */
+    UnsupportedFormat { path: String, format: String },
+}
+
+impl Error {
+    pub fn io(path: &str, source: io::Error) -> Error {
+        Error::Io {
+            path: path.to_string(),
+            source,
+        }
+    }
+
+    pub fn parse(source: &str, message: impl Into<String>) -> Error {
+        Error::Parse {
+            source: source.to_string(),
+            message: message.into(),
+        }
+    }
+
+    pub fn invalid_scene(object: &str, message: impl Into<String>) -> Error {
+        Error::InvalidScene {
+            object: object.to_string(),
+            message: message.into(),
+        }
+    }
+
+    pub fn invalid_argument(parameter: &str, message: impl Into<String>) -> Error {
+        Error::InvalidArgument {
+            parameter: parameter.to_string(),
+            message: message.into(),
+        }
+    }
+
+    pub fn unsupported_format(path: &str, format: impl Into<String>) -> Error {
+        Error::UnsupportedFormat {
+            path: path.to_string(),
+            format: format.into(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "{path}: {source}"),
+            Error::Parse { source, message } => write!(f, "{source}: {message}"),
+            Error::InvalidScene { object, message } => write!(f, "invalid {object}: {message}"),
+            Error::InvalidArgument { parameter, message } => {
+                write!(f, "invalid {parameter}: {message}")
+            }
+            Error::UnsupportedFormat { path, format } => {
+                write!(f, "{path}: unsupported format {format}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/** Fails with an invalid-scene error unless `condition` holds. */
+pub(crate) fn ensure(
+    condition: bool,
+    object: &str,
+    message: impl Into<String>,
+) -> Result<(), Error> {
+    if condition {
+        Ok(())
+    } else {
+        Err(Error::invalid_scene(object, message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let error = Error::io("/tmp/fig.ppm", io::Error::from(io::ErrorKind::NotFound));
+        assert!(error.to_string().starts_with("/tmp/fig.ppm: "));
+        assert!(std::error::Error::source(&error).is_some());
+        let error = Error::invalid_scene("sphere at (0, 0, -1)", "radius is 0");
+        assert_eq!(
+            error.to_string(),
+            "invalid sphere at (0, 0, -1): radius is 0"
+        );
+    }
+}
@@ -0,0 +1,635 @@
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, ErrorKind, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    aov::{Aov, AovSample},
+    color::Color,
+    error::Error,
+    image::{Exr, Image},
+    vec::Vec,
+};
+
+/** A pixel accumulates the sum of its samples for the image, and the running mean and
+ * squared deviations of their luminance (Welford's algorithm) for its variance.
+ */
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Pixel {
+    pub sum: Vec,
+    pub samples: u32,
+    pub luminance: f32,
+    pub m2: f32,
+}
+
+impl Pixel {
+    pub fn mean(&self) -> Vec {
+        if self.samples == 0 {
+            Vec::new()
+        } else {
+            self.sum / self.samples as f32
+        }
+    }
+
+    pub fn add(&mut self, value: Vec) {
+        self.sum += value;
+        self.samples += 1;
+        let luminance = value.luminance();
+        let delta = luminance - self.luminance;
+        self.luminance += delta / self.samples as f32;
+        self.m2 += delta * (luminance - self.luminance);
+    }
+
+    /** Sample variance of the luminance. */
+    pub fn variance(&self) -> f32 {
+        if self.samples < 2 {
+            0.
+        } else {
+            self.m2 / (self.samples - 1) as f32
+        }
+    }
+
+    /** Squared standard error of the mean luminance, relative to the mean itself. The
+     * small constant keeps black pixels from dominating.
+     */
+    pub fn relative_variance(&self) -> f32 {
+        if self.samples < 2 {
+            return f32::INFINITY;
+        }
+        self.variance() / self.samples as f32 / (self.luminance * self.luminance + 1e-4)
+    }
+}
+
+/* The per-pixel sums of an AOV, or the first sample for AOVs that are not averaged. */
+#[derive(Debug, Clone)]
+struct Layer {
+    aov: Aov,
+    values: std::vec::Vec<Vec>,
+}
+
+/** A sub-rectangle of an image, rows counting from the bottom. */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Window {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/** What the samples of a checkpoint were rendered with besides the film. A resume must
+ * carry on with the same, or the film would mix samples of different estimators.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Provenance {
+    pub seed: u64,
+    /** The integrator as `Method::from_name` reads it. */
+    pub integrator: String,
+    pub roulette_depth: Option<u32>,
+}
+
+impl fmt::Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "seed {}, integrator {} and ", self.seed, self.integrator)?;
+        match self.roulette_depth {
+            Some(depth) => write!(f, "roulette depth {depth}"),
+            None => write!(f, "roulette off"),
+        }
+    }
+}
+
+/** The HDR accumulation buffer of a render. Pixel (i, j) is column i of row j,
+ * rows counting from the bottom of the image like `Image::plot`. A cropped film only
+ * holds a window of the full image; its pixel (i, j) is pixel (x + i, y + j) of the
+ * full image.
+ */
+#[derive(Debug, Clone)]
+pub struct Film {
+    width: usize,
+    height: usize,
+    x: usize,
+    y: usize,
+    full_width: usize,
+    full_height: usize,
+    passes: u32,
+    pixels: std::vec::Vec<Pixel>,
+    layers: std::vec::Vec<Layer>,
+}
+
+impl Film {
+    const MAGIC: &'static [u8; 8] = b"RTUSFILM";
+    const VERSION: u32 = 6;
+    /* Magic, version, six sizes, passes, seed, roulette depth and the length of the
+     * integrator name, which follows; then sum, samples, luminance and m2.
+     */
+    const HEADER_BYTES: u64 = 8 + 4 + 6 * 8 + 4 + 8 + 8 + 4;
+    /* Longer than any integrator name, with its distance. */
+    const MAX_INTEGRATOR_BYTES: u32 = 64;
+    const PIXEL_BYTES: u64 = 6 * 4;
+
+    pub fn new(width: usize, height: usize) -> Film {
+        let window = Window {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        };
+        Film::with_window(width, height, window)
+    }
+
+    /** A film for a window of a larger image; the window must lie inside the image. */
+    pub fn cropped(full_width: usize, full_height: usize, window: Window) -> Result<Film, Error> {
+        let inside = |start: usize, size: usize, full: usize| {
+            start.checked_add(size).is_some_and(|end| end <= full)
+        };
+        if !inside(window.x, window.width, full_width)
+            || !inside(window.y, window.height, full_height)
+            || window.width.checked_mul(window.height).is_none()
+        {
+            return Err(Error::invalid_argument(
+                "crop window",
+                format!(
+                    "{}x{} at ({}, {}) is outside the {full_width}x{full_height} image",
+                    window.width, window.height, window.x, window.y
+                ),
+            ));
+        }
+        Ok(Film::with_window(full_width, full_height, window))
+    }
+
+    fn with_window(full_width: usize, full_height: usize, window: Window) -> Film {
+        Film {
+            width: window.width,
+            height: window.height,
+            x: window.x,
+            y: window.y,
+            full_width,
+            full_height,
+            passes: 0,
+            pixels: vec![Pixel::default(); window.width * window.height],
+            layers: std::vec::Vec::new(),
+        }
+    }
+
+    /** Makes the film record an AOV too; it should be added before rendering starts. */
+    pub fn add_layer(&mut self, aov: Aov) {
+        if !self.aovs().contains(&aov) {
+            self.layers.push(Layer {
+                aov,
+                values: vec![Vec::new(); self.pixels.len()],
+            });
+        }
+    }
+
+    pub fn has_aovs(&self) -> bool {
+        !self.layers.is_empty()
+    }
+
+    pub fn aovs(&self) -> std::vec::Vec<Aov> {
+        self.layers.iter().map(|layer| layer.aov).collect()
+    }
+
+    pub fn window(&self) -> Window {
+        Window {
+            x: self.x,
+            y: self.y,
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    pub fn full_width(&self) -> usize {
+        self.full_width
+    }
+
+    pub fn full_height(&self) -> usize {
+        self.full_height
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /** The number of completed passes over the whole image. */
+    pub fn passes(&self) -> u32 {
+        self.passes
+    }
+
+    pub fn finish_pass(&mut self) {
+        self.passes += 1;
+    }
+
+    pub fn pixel(&self, i: usize, j: usize) -> &Pixel {
+        &self.pixels[j * self.width + i]
+    }
+
+    pub fn pixel_mut(&mut self, i: usize, j: usize) -> &mut Pixel {
+        &mut self.pixels[j * self.width + i]
+    }
+
+    pub fn add_sample(&mut self, i: usize, j: usize, value: Vec) {
+        self.pixels[j * self.width + i].add(value);
+    }
+
+    pub fn add_sample_aovs(&mut self, i: usize, j: usize, value: Vec, aovs: &AovSample) {
+        let index = j * self.width + i;
+        let first = self.pixels[index].samples == 0;
+        for layer in self.layers.iter_mut() {
+            if layer.aov.is_averaged() {
+                layer.values[index] += aovs.get(layer.aov);
+            } else if first {
+                layer.values[index] = aovs.get(layer.aov);
+            }
+        }
+        self.pixels[index].add(value);
+    }
+
+    /** The value of an AOV at pixel (i, j), zero if the film does not record it. */
+    pub fn aov(&self, aov: Aov, i: usize, j: usize) -> Vec {
+        let index = j * self.width + i;
+        let samples = self.pixels[index].samples;
+        match self.layers.iter().find(|layer| layer.aov == aov) {
+            Some(layer) if aov.is_averaged() && samples > 0 => layer.values[index] / samples as f32,
+            Some(layer) => layer.values[index],
+            None => Vec::new(),
+        }
+    }
+
+    /** Adds the beauty image, or with `Some(aov)` an AOV, as channels of an EXR image of
+     * the film's size.
+     */
+    pub fn add_exr_layer(&self, exr: &mut Exr, aov: Option<Aov>) -> Result<(), Error> {
+        let (prefix, channels) = match aov {
+            Some(aov) => (format!("{}.", aov.name()), aov.channels()),
+            None => (String::new(), &["R", "G", "B"][..]),
+        };
+        for (component, channel) in channels.iter().enumerate() {
+            let mut values = std::vec::Vec::with_capacity(self.pixels.len());
+            for j in 0..self.height {
+                for i in 0..self.width {
+                    let value = match aov {
+                        Some(aov) => self.aov(aov, i, j),
+                        None => self.pixel(i, j).mean(),
+                    };
+                    values.push(value.at(component));
+                }
+            }
+            exr.add_channel(format!("{prefix}{channel}"), values)?;
+        }
+        Ok(())
+    }
+
+    pub fn max_samples(&self) -> u32 {
+        self.pixels
+            .iter()
+            .map(|pixel| pixel.samples)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn total_samples(&self) -> u64 {
+        self.pixels.iter().map(|pixel| pixel.samples as u64).sum()
+    }
+
+    /** Estimated relative error of the whole image, the root of the mean relative
+     * variance over all pixels.
+     */
+    pub fn relative_error(&self) -> f32 {
+        let total: f32 = self.pixels.iter().map(Pixel::relative_variance).sum();
+        (total / self.pixels.len() as f32).sqrt()
+    }
+
+    /** Tone maps the film into an image of the same size. */
+    pub fn develop(&self, image: &mut dyn Image) -> Result<(), Error> {
+        for j in 0..self.height {
+            for i in 0..self.width {
+                image.plot(j, i, Color::from(self.pixel(i, j).mean()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /** Tone maps the film into its window of a full-size image, leaving the rest of the
+     * image as it is.
+     */
+    pub fn composite(&self, image: &mut dyn Image) -> Result<(), Error> {
+        for j in 0..self.height {
+            for i in 0..self.width {
+                image.plot(self.y + j, self.x + i, Color::from(self.pixel(i, j).mean()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /** Plots the number of samples of each pixel as a grey level, white being the most
+     * sampled pixel.
+     */
+    pub fn develop_samples(&self, image: &mut dyn Image) -> Result<(), Error> {
+        let max = self.max_samples().max(1);
+        for j in 0..self.height {
+            for i in 0..self.width {
+                let level = self.pixel(i, j).samples * 255 / max;
+                image.plot(j, i, Color::from([level; 3]))?;
+            }
+        }
+        Ok(())
+    }
+
+    /** Writes AOVs next to the image file `fname`, each to `<stem>.<aov>.exr`, or with
+     * `multilayer` all of them and the image as layers of `<stem>.exr`.
+     */
+    pub fn write_aovs(&self, aovs: &[Aov], fname: &str, multilayer: bool) -> Result<(), Error> {
+        let stem = Path::new(fname).with_extension("");
+        let stem = stem.to_string_lossy();
+        if multilayer {
+            let mut exr = Exr::new(self.width, self.height);
+            self.add_exr_layer(&mut exr, None)?;
+            for &aov in aovs {
+                self.add_exr_layer(&mut exr, Some(aov))?;
+            }
+            return exr.to_file(&format!("{stem}.exr"));
+        }
+        for &aov in aovs {
+            let mut exr = Exr::new(self.width, self.height);
+            self.add_exr_layer(&mut exr, Some(aov))?;
+            exr.to_file(&format!("{stem}.{}.exr", aov.name()))?;
+        }
+        Ok(())
+    }
+
+    /** Writes the film and what it was rendered with. The file is replaced atomically,
+     * so an interrupted save never destroys the previous checkpoint.
+     */
+    pub fn save(&self, fname: &str, provenance: &Provenance) -> Result<(), Error> {
+        let tmp = format!("{fname}.tmp");
+        File::create(&tmp)
+            .and_then(|file| self.write(&mut BufWriter::new(file), provenance))
+            .map_err(|e| Error::io(&tmp, e))?;
+        fs::rename(&tmp, fname).map_err(|e| Error::io(fname, e))
+    }
+
+    fn write(&self, file: &mut impl Write, provenance: &Provenance) -> io::Result<()> {
+        file.write_all(Film::MAGIC)?;
+        file.write_all(&Film::VERSION.to_le_bytes())?;
+        file.write_all(&(self.width as u64).to_le_bytes())?;
+        file.write_all(&(self.height as u64).to_le_bytes())?;
+        for value in [self.x, self.y, self.full_width, self.full_height] {
+            file.write_all(&(value as u64).to_le_bytes())?;
+        }
+        file.write_all(&self.passes.to_le_bytes())?;
+        file.write_all(&provenance.seed.to_le_bytes())?;
+        /* 0 when Russian roulette is off, one more than the depth otherwise. */
+        let roulette_depth = provenance
+            .roulette_depth
+            .map_or(0, |depth| depth as u64 + 1);
+        file.write_all(&roulette_depth.to_le_bytes())?;
+        file.write_all(&(provenance.integrator.len() as u32).to_le_bytes())?;
+        file.write_all(provenance.integrator.as_bytes())?;
+        for pixel in &self.pixels {
+            for i in 0..=2 {
+                file.write_all(&pixel.sum.at(i).to_le_bytes())?;
+            }
+            file.write_all(&pixel.samples.to_le_bytes())?;
+            file.write_all(&pixel.luminance.to_le_bytes())?;
+            file.write_all(&pixel.m2.to_le_bytes())?;
+        }
+        file.write_all(&(self.layers.len() as u32).to_le_bytes())?;
+        for layer in &self.layers {
+            file.write_all(&(layer.aov as u32).to_le_bytes())?;
+            for value in &layer.values {
+                for i in 0..=2 {
+                    file.write_all(&value.at(i).to_le_bytes())?;
+                }
+            }
+        }
+        file.flush()
+    }
+
+    /** Reads a checkpoint written by `save`, returning the film and what it was rendered
+     * with.
+     */
+    pub fn load(fname: &str) -> Result<(Film, Provenance), Error> {
+        let file = File::open(fname).map_err(|e| Error::io(fname, e))?;
+        let mut file = Reader {
+            fname,
+            file: BufReader::new(file),
+        };
+        let mut magic = [0; 8];
+        file.read_exact(&mut magic)?;
+        if &magic != Film::MAGIC {
+            return Err(Error::parse(fname, "not an rtus checkpoint"));
+        }
+        let version = file.u32()?;
+        if version != Film::VERSION {
+            return Err(Error::unsupported_format(
+                fname,
+                format!("checkpoint version {version}"),
+            ));
+        }
+        let width = file.u64()? as usize;
+        let height = file.u64()? as usize;
+        let x = file.u64()? as usize;
+        let y = file.u64()? as usize;
+        let full_width = file.u64()? as usize;
+        let full_height = file.u64()? as usize;
+        let passes = file.u32()?;
+        let seed = file.u64()?;
+        let roulette_depth = match file.u64()? {
+            0 => None,
+            depth => Some(
+                u32::try_from(depth - 1)
+                    .map_err(|_| Error::parse(fname, format!("roulette depth {}", depth - 1)))?,
+            ),
+        };
+        let length = file.u32()?;
+        if length > Film::MAX_INTEGRATOR_BYTES {
+            return Err(Error::parse(
+                fname,
+                format!("integrator name of {length} bytes"),
+            ));
+        }
+        let mut integrator = vec![0; length as usize];
+        file.read_exact(&mut integrator)?;
+        let provenance = Provenance {
+            seed,
+            integrator: String::from_utf8(integrator)
+                .map_err(|_| Error::parse(fname, "integrator name is not UTF-8"))?,
+            roulette_depth,
+        };
+        let window = Window {
+            x,
+            y,
+            width,
+            height,
+        };
+        /* Check the pixels are all there before allocating room for them. */
+        let size = fs::metadata(fname).map_err(|e| Error::io(fname, e))?.len();
+        let pixels = (width as u64)
+            .checked_mul(height as u64)
+            .and_then(|n| n.checked_mul(Film::PIXEL_BYTES))
+            .and_then(|n| n.checked_add(Film::HEADER_BYTES + length as u64));
+        if pixels.is_none_or(|n| n > size) {
+            return Err(Error::parse(
+                fname,
+                format!("truncated checkpoint for {width}x{height} pixels"),
+            ));
+        }
+        let mut film = Film::cropped(full_width, full_height, window)
+            .map_err(|e| Error::parse(fname, e.to_string()))?;
+        film.passes = passes;
+        for pixel in film.pixels.iter_mut() {
+            for i in 0..=2 {
+                *pixel.sum.at_mut(i) = f32::from_bits(file.u32()?);
+            }
+            pixel.samples = file.u32()?;
+            pixel.luminance = f32::from_bits(file.u32()?);
+            pixel.m2 = f32::from_bits(file.u32()?);
+        }
+        let layers = file.u32()?;
+        if layers as usize > Aov::ALL.len() {
+            return Err(Error::parse(fname, format!("{layers} AOV layers")));
+        }
+        for _ in 0..layers {
+            let aov = *Aov::ALL
+                .get(file.u32()? as usize)
+                .ok_or_else(|| Error::parse(fname, "unknown AOV"))?;
+            film.add_layer(aov);
+            let layer = film.layers.last_mut().unwrap();
+            for value in layer.values.iter_mut() {
+                for i in 0..=2 {
+                    *value.at_mut(i) = f32::from_bits(file.u32()?);
+                }
+            }
+        }
+        Ok((film, provenance))
+    }
+}
+
+/* Reads little-endian checkpoint fields, reporting a short file as a parse error. */
+struct Reader<'a, R> {
+    fname: &'a str,
+    file: R,
+}
+
+impl<R: Read> Reader<'_, R> {
+    fn read_exact(&mut self, bytes: &mut [u8]) -> Result<(), Error> {
+        self.file.read_exact(bytes).map_err(|e| match e.kind() {
+            ErrorKind::UnexpectedEof => Error::parse(self.fname, "truncated checkpoint"),
+            _ => Error::io(self.fname, e),
+        })
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let mut bytes = [0; 4];
+        self.read_exact(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        let mut bytes = [0; 8];
+        self.read_exact(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint() {
+        let window = Window {
+            x: 1,
+            y: 2,
+            width: 3,
+            height: 2,
+        };
+        let mut film = Film::cropped(5, 4, window).unwrap();
+        assert!(Film::cropped(3, 4, window).is_err());
+        film.add_layer(Aov::Depth);
+        let mut aovs = AovSample::default();
+        aovs.set(Aov::Depth, Vec::from([2., 0., 0.]));
+        film.add_sample_aovs(2, 1, Vec::from([0.1, 0.2, 0.3]), &aovs);
+        aovs.set(Aov::Depth, Vec::from([3., 0., 0.]));
+        film.add_sample_aovs(2, 1, Vec::from([0.3, 0.2, 0.1]), &aovs);
+        film.finish_pass();
+        let fname = std::env::temp_dir().join("rtus_test_checkpoint.film");
+        let fname = fname.to_str().unwrap();
+        let provenance = Provenance {
+            seed: 42,
+            integrator: "ao:0.5".to_string(),
+            roulette_depth: None,
+        };
+        film.save(fname, &provenance).unwrap();
+
+        let (loaded, loaded_provenance) = Film::load(fname).unwrap();
+        assert_eq!(loaded_provenance, provenance);
+        assert_eq!(
+            (loaded.width(), loaded.height(), loaded.passes()),
+            (3, 2, 1)
+        );
+        assert_eq!(loaded.window(), window);
+        assert_eq!((loaded.full_width(), loaded.full_height()), (5, 4));
+        let pixel = loaded.pixel(2, 1);
+        assert_eq!(pixel.samples, 2);
+        assert_eq!(pixel.sum.x(), 0.1_f32 + 0.3);
+        assert!((pixel.mean().y() - 0.2).abs() < 1e-6);
+        assert_eq!(pixel.m2, film.pixel(2, 1).m2);
+        assert_eq!(loaded.pixel(0, 0).samples, 0);
+        assert_eq!(loaded.aovs(), [Aov::Depth]);
+        assert_eq!(loaded.aov(Aov::Depth, 2, 1).x(), 2.5);
+
+        /* A header claiming more pixels than the file holds. */
+        let mut huge = b"RTUSFILM".to_vec();
+        huge.extend(Film::VERSION.to_le_bytes());
+        for value in [1 << 31, 1 << 31, 0, 0, 1 << 31, 1 << 31] {
+            huge.extend(u64::to_le_bytes(value));
+        }
+        huge.extend([0; 24]);
+        fs::write(fname, &huge).unwrap();
+        assert!(matches!(Film::load(fname), Err(Error::Parse { .. })));
+        /* An integrator name longer than any there is. */
+        let at = huge.len() - 4;
+        huge[at..].copy_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(fname, &huge).unwrap();
+        assert!(matches!(Film::load(fname), Err(Error::Parse { .. })));
+        let far = Window {
+            x: usize::MAX,
+            ..window
+        };
+        assert!(Film::cropped(5, 4, far).is_err());
+
+        fs::write(fname, b"garbage").unwrap();
+        assert!(matches!(Film::load(fname), Err(Error::Parse { .. })));
+        fs::write(fname, b"RTUSFILM\x01\0\0\0").unwrap();
+        assert!(matches!(
+            Film::load(fname),
+            Err(Error::UnsupportedFormat { .. })
+        ));
+    }
+
+    #[test]
+    fn test_relative_error() {
+        let mut film = Film::new(2, 1);
+        assert_eq!(film.relative_error(), f32::INFINITY);
+        for k in 0..4 {
+            film.add_sample(0, 0, Vec::from([0.5, 0.5, 0.5]));
+            film.add_sample(1, 0, Vec::from([1., 1., 1.]) * (k % 2) as f32);
+        }
+        assert_eq!(film.pixel(0, 0).variance(), 0.);
+        assert!((film.pixel(1, 0).variance() - 1. / 3.).abs() < 1e-5);
+        let before = film.relative_error();
+        for k in 0..12 {
+            film.add_sample(0, 0, Vec::from([0.5, 0.5, 0.5]));
+            film.add_sample(1, 0, Vec::from([1., 1., 1.]) * (k % 2) as f32);
+        }
+        /* Four times the samples roughly halve the error. */
+        let ratio = before / film.relative_error();
+        assert!((1.8..2.4).contains(&ratio), "{ratio}");
+    }
+}
@@ -1,15 +1,79 @@
 use std::{
-    fs::File,
-    io::{Error, Write},
+    fs::{self, File},
+    io::{self, stdout, BufWriter, Write},
     path::Path,
 };
 
-use crate::color::Color;
+use crate::{color::Color, error::Error};
 
 pub trait Image {
+    fn width(&self) -> usize;
+
+    fn height(&self) -> usize;
+
+    /** Row x counts from the bottom of the image, column y from the left. */
+    fn plot(&mut self, x: usize, y: usize, color: Color) -> Result<(), Error>;
+
+    /** The color at row x from the bottom and column y, if inside the image. */
+    fn pixel(&self, x: usize, y: usize) -> Option<Color>;
+
     fn to_file(&self, fname: &str) -> Result<(), Error>;
 
-    fn to_stdout(&self);
+    fn to_stdout(&self) -> Result<(), Error>;
+}
+
+/* The extension of a file name in lower case, which is how formats are told apart. */
+pub(crate) fn extension(fname: &str) -> Option<String> {
+    Path::new(fname)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+}
+
+/** A black image to be written to `fname`, a PPM or a PNG as its extension says in any
+ * case.
+ */
+pub fn for_file(fname: &str, width: usize, height: usize) -> Result<Box<dyn Image>, Error> {
+    match extension(fname).as_deref() {
+        Some("ppm") => Ok(Box::new(Ppm::with_size(width, height))),
+        Some("png") => Ok(Box::new(Png::with_size(width, height))),
+        _ => Err(Error::unsupported_format(
+            fname,
+            "image, expected a .ppm or .png file",
+        )),
+    }
+}
+
+fn plot(pixels: &mut [Vec<Color>], x: usize, y: usize, color: Color) -> Result<(), Error> {
+    let (height, width) = (pixels.len(), pixels.first().map_or(0, Vec::len));
+    let pixel = pixels
+        .get_mut(x)
+        .and_then(|row| row.get_mut(y))
+        .ok_or_else(|| {
+            Error::invalid_argument(
+                "pixel",
+                format!("row {x}, column {y} is outside the {width}x{height} image"),
+            )
+        })?;
+    *pixel = color;
+    Ok(())
+}
+
+fn write_file(
+    fname: &str,
+    write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> Result<(), Error> {
+    let file = File::create(Path::new(fname)).map_err(|e| Error::io(fname, e))?;
+    let mut file = BufWriter::new(file);
+    write(&mut file)
+        .and_then(|()| file.flush())
+        .map_err(|e| Error::io(fname, e))
+}
+
+fn write_stdout(write: impl FnOnce(&mut dyn Write) -> io::Result<()>) -> Result<(), Error> {
+    let mut out = stdout().lock();
+    write(&mut out)
+        .and_then(|()| out.flush())
+        .map_err(|e| Error::io("<stdout>", e))
 }
 
 #[derive(Debug)]
@@ -27,6 +91,10 @@ impl Ppm {
     pub fn new() -> Ppm {
         let width = Ppm::DEFAULT_WIDTH;
         let height = (Ppm::DEFAULT_WIDTH as f32 / Ppm::DEFAULT_ASPECT_RATIO) as usize;
+        Ppm::with_size(width, height)
+    }
+
+    pub fn with_size(width: usize, height: usize) -> Ppm {
         Ppm {
             width,
             height,
@@ -35,17 +103,94 @@ impl Ppm {
         }
     }
 
-    pub fn plot(&mut self, x: usize, y: usize, color: Color) {
-        self.pixels[x][y] = color
+    pub fn plot(&mut self, x: usize, y: usize, color: Color) -> Result<(), Error> {
+        plot(&mut self.pixels, x, y, color)
     }
-}
 
-impl Image for Ppm {
-    fn to_file(&self, fname: &str) -> Result<(), Error> {
-        let mut file = File::create(Path::new(fname))?;
+    /** Reads a plain (P3) or binary (P6) PPM file with 8-bit channels. */
+    pub fn from_file(fname: &str) -> Result<Ppm, Error> {
+        let bytes = fs::read(fname).map_err(|e| Error::io(fname, e))?;
+        let truncated = || Error::parse(fname, "truncated PPM file");
+        /* The header is four whitespace-separated fields, with comments from # to the
+         * end of a line; binary data starts after one whitespace character.
+         */
+        let mut fields = std::vec::Vec::new();
+        let mut at = 0;
+        while fields.len() < 4 {
+            match bytes.get(at).ok_or_else(truncated)? {
+                b'#' => {
+                    while bytes.get(at).is_some_and(|&b| b != b'\n') {
+                        at += 1;
+                    }
+                }
+                b if b.is_ascii_whitespace() => at += 1,
+                _ => {
+                    let start = at;
+                    while bytes.get(at).is_some_and(|b| !b.is_ascii_whitespace()) {
+                        at += 1;
+                    }
+                    fields.push(String::from_utf8_lossy(&bytes[start..at]).into_owned());
+                }
+            }
+        }
+        let binary = match fields[0].as_str() {
+            "P3" => false,
+            "P6" => true,
+            magic => {
+                return Err(Error::unsupported_format(
+                    fname,
+                    format!("PPM type {magic}"),
+                ))
+            }
+        };
+        let number = |field: &str| {
+            field
+                .parse::<usize>()
+                .map_err(|_| Error::parse(fname, format!("invalid PPM header field {field}")))
+        };
+        let (width, height, max_color) = (
+            number(&fields[1])?,
+            number(&fields[2])?,
+            number(&fields[3])?,
+        );
+        if max_color == 0 || max_color > 255 {
+            return Err(Error::unsupported_format(
+                fname,
+                format!("PPM with maximum value {max_color}"),
+            ));
+        }
+        let values: std::vec::Vec<u32> = if binary {
+            bytes
+                .get(at + 1..)
+                .ok_or_else(truncated)?
+                .iter()
+                .map(|&b| b as u32)
+                .collect()
+        } else {
+            String::from_utf8_lossy(&bytes[at..])
+                .split_ascii_whitespace()
+                .map(|value| {
+                    value
+                        .parse()
+                        .map_err(|_| Error::parse(fname, format!("invalid PPM value {value}")))
+                })
+                .collect::<Result<_, _>>()?
+        };
+        if values.len() < 3 * width * height {
+            return Err(truncated());
+        }
+        let scale = |value: u32| value.min(max_color as u32) * 255 / max_color as u32;
+        let mut ppm = Ppm::with_size(width, height);
+        for (k, rgb) in values.chunks(3).take(width * height).enumerate() {
+            let color = Color::from([scale(rgb[0]), scale(rgb[1]), scale(rgb[2])]);
+            ppm.plot(height - 1 - k / width, k % width, color)?;
+        }
+        Ok(ppm)
+    }
 
+    fn write(&self, out: &mut dyn Write) -> io::Result<()> {
         write!(
-            file,
+            out,
             "P3\n{:?} {:?}\n{:?}\n",
             self.width, self.height, self.max_color
         )?;
@@ -54,24 +199,933 @@ impl Image for Ppm {
             let j = self.height - j;
             for i in 0..self.width {
                 let color = self.pixels[j][i];
-                writeln!(file, "{color}")?;
+                writeln!(out, "{color}")?;
             }
         }
         Ok(())
     }
+}
+
+impl Default for Ppm {
+    fn default() -> Self {
+        Ppm::new()
+    }
+}
 
-    fn to_stdout(&self) {
-        print!(
-            "P3\n{:?} {:?}\n{:?}\n",
-            self.width, self.height, self.max_color
-        );
+impl Image for Ppm {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn plot(&mut self, x: usize, y: usize, color: Color) -> Result<(), Error> {
+        Ppm::plot(self, x, y, color)
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> Option<Color> {
+        self.pixels.get(x)?.get(y).copied()
+    }
+
+    fn to_file(&self, fname: &str) -> Result<(), Error> {
+        write_file(fname, |out| self.write(out))
+    }
+
+    fn to_stdout(&self) -> Result<(), Error> {
+        write_stdout(|out| self.write(out))
+    }
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/** 8-bit RGB PNG, deflated with stored (uncompressed) blocks only. */
+#[derive(Debug)]
+pub struct Png {
+    pub width: usize,  // x
+    pub height: usize, // y
+    pixels: Vec<Vec<Color>>,
+}
+
+impl Png {
+    pub fn new() -> Png {
+        let ppm = Ppm::new();
+        Png::with_size(ppm.width, ppm.height)
+    }
 
+    pub fn with_size(width: usize, height: usize) -> Png {
+        let ppm = Ppm::with_size(width, height);
+        Png {
+            width: ppm.width,
+            height: ppm.height,
+            pixels: ppm.pixels,
+        }
+    }
+
+    /** Reads a non-interlaced PNG with 8 bits per channel. Grey is read as RGB and
+     * alpha is dropped.
+     */
+    pub fn from_file(fname: &str) -> Result<Png, Error> {
+        let bytes = fs::read(fname).map_err(|e| Error::io(fname, e))?;
+        if !bytes.starts_with(PNG_SIGNATURE) {
+            return Err(Error::parse(fname, "not a PNG file"));
+        }
+        let truncated = || Error::parse(fname, "truncated PNG file");
+        let (mut header, mut data) = (None, Vec::new());
+        let mut at = PNG_SIGNATURE.len();
+        loop {
+            let length = bytes.get(at..at + 4).ok_or_else(truncated)?;
+            let length = u32::from_be_bytes(length.try_into().unwrap()) as usize;
+            let kind = bytes.get(at + 4..at + 8).ok_or_else(truncated)?;
+            let chunk = bytes.get(at + 8..at + 8 + length).ok_or_else(truncated)?;
+            match kind {
+                b"IHDR" => header = Some(chunk),
+                b"IDAT" => data.extend(chunk),
+                b"IEND" => break,
+                _ => {}
+            }
+            at += 12 + length;
+        }
+        let header = header
+            .filter(|header| header.len() == 13)
+            .ok_or_else(|| Error::parse(fname, "missing PNG header"))?;
+        let number = |at: usize| u32::from_be_bytes(header[at..at + 4].try_into().unwrap());
+        let (width, height) = (number(0) as usize, number(4) as usize);
+        let (depth, color_type, interlace) = (header[8], header[9], header[12]);
+        let channels = match color_type {
+            0 => 1,
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 0,
+        };
+        if depth != 8 || channels == 0 || interlace != 0 {
+            return Err(Error::unsupported_format(
+                fname,
+                format!("PNG of bit depth {depth}, color type {color_type}, interlace {interlace}"),
+            ));
+        }
+        let raw = zlib_inflate(&data).map_err(|message| Error::parse(fname, message))?;
+        let stride = channels * width;
+        if raw.len() < height * (stride + 1) {
+            return Err(truncated());
+        }
+
+        let mut png = Png::with_size(width, height);
+        let mut previous = vec![0_u8; stride];
+        for (row, line) in raw.chunks(stride + 1).take(height).enumerate() {
+            let mut current = line[1..].to_vec();
+            for i in 0..stride {
+                /* The bytes to the left, above and above left of this one. */
+                let a = if i < channels {
+                    0
+                } else {
+                    current[i - channels]
+                };
+                let (b, c) = (
+                    previous[i],
+                    if i < channels {
+                        0
+                    } else {
+                        previous[i - channels]
+                    },
+                );
+                let predictor = match line[0] {
+                    0 => 0,
+                    1 => a,
+                    2 => b,
+                    3 => ((a as u16 + b as u16) / 2) as u8,
+                    4 => paeth(a, b, c),
+                    filter => {
+                        return Err(Error::parse(fname, format!("invalid PNG filter {filter}")))
+                    }
+                };
+                current[i] = current[i].wrapping_add(predictor);
+            }
+            for (column, pixel) in current.chunks(channels).enumerate() {
+                let rgb = if channels < 3 {
+                    [pixel[0]; 3]
+                } else {
+                    [pixel[0], pixel[1], pixel[2]]
+                };
+                png.plot(height - 1 - row, column, Color::from(rgb.map(u32::from)))?;
+            }
+            previous = current;
+        }
+        Ok(png)
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity(self.height * (3 * self.width + 1));
         for j in 1..=self.height {
             let j = self.height - j;
-            for i in 0..self.width {
-                let color = self.pixels[j][i];
-                println!("{color}");
+            raw.push(0); // no filter
+            for color in &self.pixels[j] {
+                raw.extend((0..=2).map(|i| color.at(i).min(255) as u8));
+            }
+        }
+
+        let mut ihdr = Vec::new();
+        ihdr.extend((self.width as u32).to_be_bytes());
+        ihdr.extend((self.height as u32).to_be_bytes());
+        ihdr.extend([8, 2, 0, 0, 0]); // 8 bits per channel, RGB
+
+        let mut png = PNG_SIGNATURE.to_vec();
+        write_chunk(&mut png, b"IHDR", &ihdr);
+        write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+        write_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+impl Default for Png {
+    fn default() -> Self {
+        Png::new()
+    }
+}
+
+impl Image for Png {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn plot(&mut self, x: usize, y: usize, color: Color) -> Result<(), Error> {
+        plot(&mut self.pixels, x, y, color)
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> Option<Color> {
+        self.pixels.get(x)?.get(y).copied()
+    }
+
+    fn to_file(&self, fname: &str) -> Result<(), Error> {
+        write_file(fname, |out| out.write_all(&self.encode()))
+    }
+
+    fn to_stdout(&self) -> Result<(), Error> {
+        write_stdout(|out| out.write_all(&self.encode()))
+    }
+}
+
+/** Uncompressed scanline OpenEXR with 32-bit float channels. A multi-layer file names
+ * its channels `<layer>.<channel>`, e.g. `normal.X`; the beauty image is plain `R`, `G`,
+ * `B`. Channel values are stored row by row, rows counting from the bottom like
+ * `Image::plot`.
+ */
+#[derive(Debug)]
+pub struct Exr {
+    pub width: usize,
+    pub height: usize,
+    channels: Vec<(String, Vec<f32>)>,
+}
+
+impl Exr {
+    pub fn new(width: usize, height: usize) -> Exr {
+        Exr {
+            width,
+            height,
+            channels: Vec::new(),
+        }
+    }
+
+    /** Reads an uncompressed scanline EXR with half, float or unsigned int channels,
+     * which includes the files `to_file` writes.
+     */
+    pub fn from_file(fname: &str) -> Result<Exr, Error> {
+        let bytes = fs::read(fname).map_err(|e| Error::io(fname, e))?;
+        let mut cursor = Cursor {
+            fname,
+            bytes: &bytes,
+            at: 0,
+        };
+        if cursor.take(4)? != [0x76, 0x2f, 0x31, 0x01] {
+            return Err(Error::parse(fname, "not an EXR file"));
+        }
+        let version = cursor.take(4)?;
+        /* Long names are fine; tiled, deep and multi-part files are not. */
+        if version[0] != 2 || version[1] & !0x04 != 0 {
+            return Err(Error::unsupported_format(
+                fname,
+                format!("EXR version {} with flags {:#x}", version[0], version[1]),
+            ));
+        }
+
+        let (mut channels, mut window) = (Vec::new(), None);
+        loop {
+            let name = cursor.string()?;
+            if name.is_empty() {
+                break;
+            }
+            let kind = cursor.string()?;
+            let size = usize::try_from(cursor.i32()?).map_err(|_| {
+                Error::parse(fname, format!("negative size of EXR attribute {name}"))
+            })?;
+            let mut value = Cursor {
+                fname,
+                bytes: cursor.take(size)?,
+                at: 0,
+            };
+            match (name.as_str(), kind.as_str()) {
+                ("channels", "chlist") => loop {
+                    let name = value.string()?;
+                    if name.is_empty() {
+                        break;
+                    }
+                    let kind = value.i32()?;
+                    value.take(4)?; // pLinear and reserved
+                    if (value.i32()?, value.i32()?) != (1, 1) {
+                        return Err(Error::unsupported_format(fname, "subsampled EXR channels"));
+                    }
+                    channels.push((name, kind));
+                },
+                ("compression", "compression") if value.take(1)? != [0] => {
+                    return Err(Error::unsupported_format(fname, "compressed EXR"));
+                }
+                ("dataWindow", "box2i") => {
+                    window = Some([value.i32()?, value.i32()?, value.i32()?, value.i32()?]);
+                }
+                _ => {}
+            }
+        }
+        let [x0, y0, x1, y1] = window.ok_or_else(|| Error::parse(fname, "no EXR data window"))?;
+        if x1 < x0 || y1 < y0 {
+            return Err(Error::parse(fname, "empty EXR data window"));
+        }
+        let (width, height) = (
+            (x1 as i64 - x0 as i64 + 1) as usize,
+            (y1 as i64 - y0 as i64 + 1) as usize,
+        );
+        let mut pixel_bytes = 0;
+        for (name, kind) in &channels {
+            pixel_bytes += match kind {
+                0 | 2 => 4,
+                1 => 2,
+                _ => {
+                    return Err(Error::parse(
+                        fname,
+                        format!("EXR channel {name} of unknown type {kind}"),
+                    ))
+                }
+            };
+        }
+        /* Each scanline has an offset, a header and its values; checked before allocating. */
+        let needed = width
+            .checked_mul(pixel_bytes)
+            .and_then(|values| values.checked_add(16))
+            .and_then(|line| line.checked_mul(height));
+        if width.checked_mul(height).is_none()
+            || needed.is_none_or(|needed| needed > bytes.len() - cursor.at)
+        {
+            return Err(Error::parse(
+                fname,
+                format!("EXR data window of {width}x{height} larger than the file"),
+            ));
+        }
+
+        let mut exr = Exr::new(width, height);
+        let mut values = vec![vec![0.; width * height]; channels.len()];
+        for _ in 0..height {
+            let offset = cursor.u64()? as usize;
+            let mut line = Cursor {
+                fname,
+                bytes: bytes.get(offset..).unwrap_or_default(),
+                at: 0,
+            };
+            let y = line.i32()?;
+            line.i32()?; // size of the data
+            if !(y0..=y1).contains(&y) {
+                return Err(Error::parse(
+                    fname,
+                    format!("EXR scanline {y} outside the image"),
+                ));
             }
+            let row = height - 1 - (y - y0) as usize;
+            for ((name, kind), values) in channels.iter().zip(&mut values) {
+                for value in &mut values[row * width..(row + 1) * width] {
+                    *value = match kind {
+                        0 => u32::from_le_bytes(line.take(4)?.try_into().unwrap()) as f32,
+                        1 => half(u16::from_le_bytes(line.take(2)?.try_into().unwrap())),
+                        2 => f32::from_le_bytes(line.take(4)?.try_into().unwrap()),
+                        _ => unreachable!("EXR channel {name} of unknown type {kind}"),
+                    };
+                }
+            }
+        }
+        for ((name, _), values) in channels.into_iter().zip(values) {
+            exr.add_channel(name, values)?;
+        }
+        Ok(exr)
+    }
+
+    /** The names of the channels, in the order they were added or read. */
+    pub fn channel_names(&self) -> impl Iterator<Item = &str> {
+        self.channels.iter().map(|(name, _)| name.as_str())
+    }
+
+    /** The values of a channel, row by row from the bottom. */
+    pub fn channel(&self, name: &str) -> Option<&[f32]> {
+        let (_, values) = self.channels.iter().find(|(known, _)| known == name)?;
+        Some(values)
+    }
+
+    pub fn add_channel(&mut self, name: String, values: Vec<f32>) -> Result<(), Error> {
+        if values.len() != self.width * self.height {
+            return Err(Error::invalid_argument(
+                &format!("EXR channel {name}"),
+                format!(
+                    "{} values for a {}x{} image",
+                    values.len(),
+                    self.width,
+                    self.height
+                ),
+            ));
+        }
+        self.channels.push((name, values));
+        Ok(())
+    }
+
+    pub fn to_file(&self, fname: &str) -> Result<(), Error> {
+        write_file(fname, |out| out.write_all(&self.encode()))
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        /* Readers expect channels in alphabetical order. */
+        let mut channels: Vec<&(String, Vec<f32>)> = self.channels.iter().collect();
+        channels.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut chlist = Vec::new();
+        for (name, _) in &channels {
+            chlist.extend(name.as_bytes());
+            chlist.push(0);
+            chlist.extend(2_i32.to_le_bytes()); // FLOAT
+            chlist.extend([0; 4]); // pLinear and reserved
+            chlist.extend(1_i32.to_le_bytes()); // xSampling
+            chlist.extend(1_i32.to_le_bytes()); // ySampling
+        }
+        chlist.push(0);
+        let mut window = Vec::new();
+        for value in [0, 0, self.width as i32 - 1, self.height as i32 - 1] {
+            window.extend(value.to_le_bytes());
+        }
+
+        let mut exr = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+        let mut attribute = |name: &str, kind: &str, value: &[u8]| {
+            for text in [name, kind] {
+                exr.extend(text.as_bytes());
+                exr.push(0);
+            }
+            exr.extend((value.len() as i32).to_le_bytes());
+            exr.extend(value);
+        };
+        attribute("channels", "chlist", &chlist);
+        attribute("compression", "compression", &[0]);
+        attribute("dataWindow", "box2i", &window);
+        attribute("displayWindow", "box2i", &window);
+        attribute("lineOrder", "lineOrder", &[0]); // increasing y, top to bottom
+        attribute("pixelAspectRatio", "float", &1_f32.to_le_bytes());
+        attribute("screenWindowCenter", "v2f", &[0; 8]);
+        attribute("screenWindowWidth", "float", &1_f32.to_le_bytes());
+        exr.push(0);
+
+        let line_size = 4 * self.width * channels.len();
+        let first = exr.len() + 8 * self.height;
+        for y in 0..self.height {
+            exr.extend(((first + y * (8 + line_size)) as u64).to_le_bytes());
+        }
+        for y in 0..self.height {
+            exr.extend((y as i32).to_le_bytes());
+            exr.extend((line_size as i32).to_le_bytes());
+            let row = self.height - 1 - y;
+            for (_, values) in &channels {
+                for value in &values[row * self.width..(row + 1) * self.width] {
+                    exr.extend(value.to_le_bytes());
+                }
+            }
+        }
+        exr
+    }
+}
+
+/* Reads the little-endian values of EXR files, failing with a parse error at the end. */
+struct Cursor<'a> {
+    fname: &'a str,
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        let bytes = self
+            .at
+            .checked_add(n)
+            .and_then(|end| self.bytes.get(self.at..end))
+            .ok_or_else(|| Error::parse(self.fname, "truncated EXR file"))?;
+        self.at += n;
+        Ok(bytes)
+    }
+
+    fn i32(&mut self) -> Result<i32, Error> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /* A null-terminated string. */
+    fn string(&mut self) -> Result<String, Error> {
+        let length = self.bytes[self.at.min(self.bytes.len())..]
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| Error::parse(self.fname, "truncated EXR file"))?;
+        let string = String::from_utf8_lossy(self.take(length)?).into_owned();
+        self.take(1)?;
+        Ok(string)
+    }
+}
+
+/* Converts a 16-bit float. */
+fn half(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1. } else { 1. };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32 / 1024.;
+    sign * match exponent {
+        0 => mantissa * 2_f32.powi(-14),
+        31 if mantissa == 0. => f32::INFINITY,
+        31 => f32::NAN,
+        _ => (1. + mantissa) * 2_f32.powi(exponent - 15),
+    }
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffff_u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
         }
     }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1_u32, 0_u32);
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend(kind);
+    png.extend(data);
+    let crc = crc32(&png[start..]);
+    png.extend(crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend([1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        zlib.push(if blocks.peek().is_none() { 1 } else { 0 });
+        let len = block.len() as u16;
+        zlib.extend(len.to_le_bytes());
+        zlib.extend((!len).to_le_bytes());
+        zlib.extend(block);
+    }
+    zlib.extend(adler32(data).to_be_bytes());
+    zlib
+}
+
+/* Reads the bits of a deflate stream, least significant first. */
+struct Bits<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl Bits<'_> {
+    fn read(&mut self, count: u32) -> Result<u32, &'static str> {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = self
+                .bytes
+                .get(self.at / 8)
+                .ok_or("truncated deflate stream")?;
+            value |= ((byte >> (self.at % 8)) as u32 & 1) << i;
+            self.at += 1;
+        }
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        self.at = self.at.div_ceil(8) * 8;
+    }
+}
+
+/* A canonical Huffman code given by the code length of each symbol. */
+struct Huffman {
+    /* The number of codes of each length. */
+    counts: [u16; 16],
+    /* The symbols ordered by their codes. */
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0; 16];
+        for length in 1..16 {
+            offsets[length] = offsets[length - 1] + counts[length - 1];
+        }
+        let mut symbols = vec![0; offsets[15] as usize + counts[15] as usize];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u16, &'static str> {
+        /* Codes of the same length are consecutive, starting at `first`. */
+        let (mut code, mut first, mut index) = (0, 0, 0);
+        for &count in &self.counts[1..] {
+            code |= bits.read(1)? as usize;
+            let count = count as usize;
+            if code < first + count {
+                return Ok(self.symbols[index + code - first]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("invalid Huffman code")
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u32; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u32; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/* The order in which the code lengths of the code length code are stored. */
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/* Decompresses a zlib stream (RFC 1950 and 1951) without checking its checksum. */
+fn zlib_inflate(zlib: &[u8]) -> Result<Vec<u8>, &'static str> {
+    if zlib.len() < 2
+        || zlib[0] & 0x0f != 8
+        || !u16::from_be_bytes([zlib[0], zlib[1]]).is_multiple_of(31)
+    {
+        return Err("invalid zlib header");
+    }
+    let mut bits = Bits {
+        bytes: &zlib[2..],
+        at: 0,
+    };
+    let mut out = Vec::new();
+    loop {
+        let last = bits.read(1)? == 1;
+        match bits.read(2)? {
+            0 => {
+                bits.align();
+                let length = bits.read(16)?;
+                if bits.read(16)? != !length & 0xffff {
+                    return Err("invalid stored block length");
+                }
+                for _ in 0..length {
+                    out.push(bits.read(8)? as u8);
+                }
+            }
+            1 => {
+                let mut lengths = [8; 288];
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                inflate_block(
+                    &mut bits,
+                    &mut out,
+                    &Huffman::new(&lengths),
+                    &Huffman::new(&[5; 30]),
+                )?;
+            }
+            2 => {
+                let literals = bits.read(5)? as usize + 257;
+                let distances = bits.read(5)? as usize + 1;
+                let mut lengths = [0; 19];
+                for &symbol in &CODE_LENGTH_ORDER[..bits.read(4)? as usize + 4] {
+                    lengths[symbol] = bits.read(3)? as u8;
+                }
+                let code = Huffman::new(&lengths);
+                let mut lengths = Vec::with_capacity(literals + distances);
+                while lengths.len() < literals + distances {
+                    let (length, repeat) = match code.decode(&mut bits)? {
+                        symbol @ 0..=15 => (symbol as u8, 1),
+                        16 => (
+                            *lengths
+                                .last()
+                                .ok_or("repeated code length without a previous one")?,
+                            3 + bits.read(2)?,
+                        ),
+                        17 => (0, 3 + bits.read(3)?),
+                        _ => (0, 11 + bits.read(7)?),
+                    };
+                    lengths.extend((0..repeat).map(|_| length));
+                }
+                if lengths.len() > literals + distances {
+                    return Err("too many code lengths");
+                }
+                let (literals, distances) = lengths.split_at(literals);
+                inflate_block(
+                    &mut bits,
+                    &mut out,
+                    &Huffman::new(literals),
+                    &Huffman::new(distances),
+                )?;
+            }
+            _ => return Err("invalid deflate block type"),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+/* Decodes the literals and back references of a compressed block. */
+fn inflate_block(
+    bits: &mut Bits,
+    out: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), &'static str> {
+    loop {
+        let symbol = literals.decode(bits)? as usize;
+        if symbol < 256 {
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+        let k = symbol - 257;
+        let base = *LENGTH_BASE.get(k).ok_or("invalid length code")?;
+        let length = base as usize + bits.read(LENGTH_EXTRA[k])? as usize;
+        let k = distances.decode(bits)? as usize;
+        let base = *DISTANCE_BASE.get(k).ok_or("invalid distance code")?;
+        let distance = base as usize + bits.read(DISTANCE_EXTRA[k])? as usize;
+        if distance > out.len() {
+            return Err("back reference before the start");
+        }
+        let start = out.len() - distance;
+        for i in start..start + length {
+            out.push(out[i]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn test_png() {
+        let mut png = Png::new();
+        png.plot(0, 0, Color::from([255, 0, 0])).unwrap();
+        assert!(png.plot(png.height, 0, Color::default()).is_err());
+        let bytes = png.encode();
+        assert_eq!(&bytes[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&bytes[12..16], b"IHDR");
+        assert_eq!(&bytes[bytes.len() - 8..bytes.len() - 4], b"IEND");
+        /* The bottom-left pixel is the first one of the last scanline. */
+        let stride = 3 * png.width + 1;
+        let raw_len = png.height * stride;
+        let idat_end = bytes.len() - 12 - 4 - 4;
+        let last_scanline = idat_end - stride;
+        assert_eq!(&bytes[last_scanline..last_scanline + 4], &[0, 255, 0, 0]);
+        assert!(bytes.len() > raw_len);
+
+        let fname = std::env::temp_dir().join("rtus-test-png.png");
+        let fname = fname.to_str().unwrap();
+        png.to_file(fname).unwrap();
+        let read = Png::from_file(fname).unwrap();
+        assert_eq!((read.width, read.height), (png.width, png.height));
+        assert_eq!(read.pixel(0, 0).map(rgb), Some([255, 0, 0]));
+        fs::remove_file(fname).unwrap();
+    }
+
+    #[test]
+    fn test_inflate() {
+        /* "rtus rtus rtus rtus!" compressed with fixed Huffman codes and back references. */
+        let zlib = [
+            120, 218, 43, 42, 41, 45, 86, 40, 66, 33, 20, 1, 83, 181, 7, 186,
+        ];
+        assert_eq!(zlib_inflate(&zlib).unwrap(), b"rtus rtus rtus rtus!");
+        let data: Vec<u8> = (0..70000).map(|k| (k % 251) as u8).collect();
+        assert_eq!(zlib_inflate(&zlib_stored(&data)).unwrap(), data);
+        assert!(zlib_inflate(&zlib[..8]).is_err());
+        assert!(zlib_inflate(&[0x78, 0x00]).is_err());
+    }
+
+    fn rgb(color: Color) -> [u32; 3] {
+        [color.r(), color.g(), color.b()]
+    }
+
+    #[test]
+    fn test_ppm() {
+        let fname = std::env::temp_dir().join("rtus-test-ppm.ppm");
+        let fname = fname.to_str().unwrap();
+        let mut ppm = Ppm::with_size(2, 3);
+        ppm.plot(0, 1, Color::from([255, 128, 0])).unwrap();
+        ppm.to_file(fname).unwrap();
+        let read = Ppm::from_file(fname).unwrap();
+        assert_eq!((read.width, read.height), (2, 3));
+        assert_eq!(read.pixel(0, 1).map(rgb), Some([255, 128, 0]));
+        assert_eq!(read.pixel(1, 1).map(rgb), Some([0, 0, 0]));
+        assert!(read.pixel(3, 0).is_none());
+
+        /* Binary, with a comment and a maximum value that needs rescaling. */
+        fs::write(fname, b"P6 # made by hand\n1 1\n15\n\x0f\x05\x00").unwrap();
+        let read = Ppm::from_file(fname).unwrap();
+        assert_eq!(read.pixel(0, 0).map(rgb), Some([255, 85, 0]));
+        fs::write(fname, b"P3 2 2 255 0 0 0").unwrap();
+        assert!(matches!(Ppm::from_file(fname), Err(Error::Parse { .. })));
+        fs::write(fname, b"P5 1 1 255 0").unwrap();
+        assert!(matches!(
+            Ppm::from_file(fname),
+            Err(Error::UnsupportedFormat { .. })
+        ));
+        fs::remove_file(fname).unwrap();
+    }
+
+    #[test]
+    fn test_for_file() {
+        let fname = std::env::temp_dir().join("rtus-test-for-file.PNG");
+        let fname = fname.to_str().unwrap();
+        for_file(fname, 2, 1).unwrap().to_file(fname).unwrap();
+        assert_eq!(&fs::read(fname).unwrap()[1..4], b"PNG");
+        fs::remove_file(fname).unwrap();
+        assert_eq!(for_file("fig.ppm", 2, 1).unwrap().width(), 2);
+        assert!(matches!(
+            for_file("fig.jpg", 2, 1),
+            Err(Error::UnsupportedFormat { .. })
+        ));
+        assert!(for_file("fig", 2, 1).is_err());
+    }
+
+    #[test]
+    fn test_exr() {
+        let mut exr = Exr::new(2, 3);
+        exr.add_channel("normal.X".to_string(), vec![0.; 6])
+            .unwrap();
+        assert!(exr.add_channel("uv.U".to_string(), vec![0.; 5]).is_err());
+        let bottom_left_first = (0..6).map(|k| k as f32).collect();
+        exr.add_channel("depth.Z".to_string(), bottom_left_first)
+            .unwrap();
+        let bytes = exr.encode();
+        assert_eq!(&bytes[..4], &[0x76, 0x2f, 0x31, 0x01]);
+        let names = bytes.windows(8).position(|w| w == b"depth.Z\0").unwrap();
+        assert!(names < bytes.windows(9).position(|w| w == b"normal.X\0").unwrap());
+
+        let last = b"screenWindowWidth\0float\0";
+        let header = bytes.windows(last.len()).position(|w| w == last).unwrap() + last.len() + 9;
+        let offset = |y: usize| {
+            let at = header + 8 * y;
+            u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap()) as usize
+        };
+        let line_size = 4 * 2 * 2;
+        assert_eq!(offset(0), header + 8 * 3);
+        assert_eq!(offset(2), offset(1) + 8 + line_size);
+        assert_eq!(bytes.len(), offset(2) + 8 + line_size);
+        /* The top scanline comes first, and the depth channel first within it. */
+        let first_value =
+            f32::from_le_bytes(bytes[offset(0) + 8..offset(0) + 12].try_into().unwrap());
+        assert_eq!(first_value, 4.);
+
+        let fname = std::env::temp_dir().join("rtus-test-exr.exr");
+        let fname = fname.to_str().unwrap();
+        exr.to_file(fname).unwrap();
+        let read = Exr::from_file(fname).unwrap();
+        assert_eq!((read.width, read.height), (2, 3));
+        assert_eq!(
+            read.channel_names().collect::<Vec<_>>(),
+            ["depth.Z", "normal.X"]
+        );
+        assert_eq!(read.channel("depth.Z"), exr.channel("depth.Z"));
+        fs::write(fname, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(matches!(Exr::from_file(fname), Err(Error::Parse { .. })));
+        /* A window wider than i32 holds, and a negative attribute size. */
+        let attribute = b"dataWindow\0box2i\0";
+        let size = bytes
+            .windows(attribute.len())
+            .position(|w| w == attribute)
+            .unwrap()
+            + attribute.len();
+        let mut huge = bytes.clone();
+        huge[size + 4..size + 8].copy_from_slice(&i32::MIN.to_le_bytes());
+        huge[size + 12..size + 16].copy_from_slice(&i32::MAX.to_le_bytes());
+        fs::write(fname, &huge).unwrap();
+        assert!(matches!(Exr::from_file(fname), Err(Error::Parse { .. })));
+        let mut negative = bytes.clone();
+        negative[size..size + 4].copy_from_slice(&(-1_i32).to_le_bytes());
+        fs::write(fname, &negative).unwrap();
+        assert!(matches!(Exr::from_file(fname), Err(Error::Parse { .. })));
+        fs::remove_file(fname).unwrap();
+
+        assert_eq!(half(0x3c00), 1.);
+        assert_eq!(half(0xc000), -2.);
+        assert_eq!(half(0x0001), 2_f32.powi(-24));
+        assert_eq!(half(0x7c00), f32::INFINITY);
+    }
 }
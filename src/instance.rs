@@ -0,0 +1,165 @@
+use crate::{
+    aabb::Aabb,
+    error::{ensure, Error},
+    motion::{motion_bounds, Animation, Motion},
+    ray::{HitRecord, Ray},
+    shape::Shape,
+    transform::Transform,
+};
+
+/** A shape placed in the world by an affine transform. Rays are moved into object
+ * space instead of moving the geometry, so wrapping an `Arc` of a shape instances
+ * it without copying.
+ */
+pub struct Transformed<S: Shape> {
+    shape: S,
+    transform: Transform,
+}
+
+impl<S: Shape> Transformed<S> {
+    pub fn new(shape: S, transform: Transform) -> Result<Transformed<S>, Error> {
+        ensure(
+            transform.is_invertible(),
+            "transformed shape",
+            "the transform scales by 0 and cannot be inverted",
+        )?;
+        Ok(Transformed { shape, transform })
+    }
+
+    pub fn shape(&self) -> &S {
+        &self.shape
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    pub fn to_world<'a>(&self, ray: Ray, record: HitRecord<'a>) -> HitRecord<'a> {
+        to_world(&self.transform, ray, record)
+    }
+}
+
+/** Moves a record found with the object space ray back to the world `ray`. */
+pub fn to_world<'a>(transform: &Transform, ray: Ray, record: HitRecord<'a>) -> HitRecord<'a> {
+    let outward_n = if record.is_front { record.n } else { -record.n };
+    let n = transform.apply_normal(outward_n).to_unit();
+    HitRecord {
+        barycentrics: record.barycentrics,
+        ..HitRecord::new(ray, record.t, n, record.material).with_uv(record.u, record.v)
+    }
+}
+
+impl<S: Shape> Shape for Transformed<S> {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let local_ray = self.transform.inverse().apply_ray(ray);
+        let record = self.shape.hit(local_ray, t_min, t_max)?;
+        Some(self.to_world(ray, record))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let corners = self.shape.bounding_box()?.corners();
+        Some(Aabb::from_points(
+            &corners.map(|p| self.transform.apply_point(p)),
+        ))
+    }
+}
+
+/** A shape following an animation; each ray sees it where it is at the ray's time. */
+pub struct Moving<S: Shape, A: Animation = Motion> {
+    shape: S,
+    motion: A,
+}
+
+impl<S: Shape, A: Animation> Moving<S, A> {
+    pub fn new(shape: S, motion: A) -> Moving<S, A> {
+        Moving { shape, motion }
+    }
+
+    pub fn shape(&self) -> &S {
+        &self.shape
+    }
+
+    pub fn motion(&self) -> &A {
+        &self.motion
+    }
+}
+
+impl<S: Shape, A: Animation> Shape for Moving<S, A> {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let transform = self.motion.at(ray.time);
+        let local_ray = transform.inverse().apply_ray(ray);
+        let record = self.shape.hit(local_ray, t_min, t_max)?;
+        Some(to_world(&transform, ray, record))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(motion_bounds(&self.motion, &self.shape.bounding_box()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        material::{Lambertian, Material},
+        point::Point,
+        shape::Sphere,
+        vec::Vec,
+    };
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new_const([0.5, 0.5, 0.5]))
+    }
+
+    #[test]
+    fn test_instances() {
+        let sphere = Arc::new(Sphere::new(Point::new(), 1., material()).unwrap());
+        let moved = Transformed::new(
+            sphere.clone(),
+            Transform::translate(Vec::from([0., 0., -5.])),
+        )
+        .unwrap();
+        let flat = Transform::scale(Vec::from([1., 1., 0.]));
+        assert!(Transformed::new(sphere.clone(), flat).is_err());
+        let squashed =
+            Transformed::new(sphere, Transform::scale(Vec::from([1., 1., 0.5]))).unwrap();
+
+        let ray = Ray::from(Point::new(), Vec::from([0., 0., -1.]));
+        let record = moved.hit(ray, 0.001, f32::MAX).unwrap();
+        assert!((record.t - 4.).abs() < 1e-5);
+        assert!((record.n.z() - 1.).abs() < 1e-5);
+
+        let ray = Ray::from(Point::from([0., 0., 2.]), Vec::from([0., 0., -1.]));
+        let record = squashed.hit(ray, 0.001, f32::MAX).unwrap();
+        assert!((record.t - 1.5).abs() < 1e-5);
+        assert!((record.p.z() - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_moving() {
+        let sphere = Sphere::new(Point::new(), 1., material()).unwrap();
+        let motion = Motion::linear(
+            0.,
+            Transform::new(),
+            1.,
+            Transform::translate(Vec::from([0., 4., 0.])),
+        )
+        .unwrap();
+        let moving = Moving::new(sphere, motion);
+        let origin = Point::from([0., 2., 5.]);
+        let direct = Vec::from([0., 0., -1.]);
+        assert!(moving
+            .hit(Ray::new(origin, direct, 0.), 0.001, f32::MAX)
+            .is_none());
+        let record = moving
+            .hit(Ray::new(origin, direct, 0.5), 0.001, f32::MAX)
+            .unwrap();
+        assert!((record.t - 4.).abs() < 1e-5);
+
+        let bounds = moving.bounding_box().unwrap();
+        assert!(bounds.contains(Point::from([0., -0.99, 0.])));
+        assert!(bounds.contains(Point::from([0., 4.99, 0.])));
+    }
+}
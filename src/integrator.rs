@@ -0,0 +1,611 @@
+use crate::{
+    material::Lobe,
+    ray::{HitRecord, Ray},
+    rng,
+    scene::Scene,
+    stats::{self, Counter},
+    vec::Vec,
+};
+
+const MAX_DEPTH: u32 = 50;
+const AO_DISTANCE: f32 = 1.;
+const DEPTH_FAR: f32 = 10.;
+
+/** What integrators report the vertices of their estimates to, see `Integrator::trace`. */
+pub type Visit<'a> = dyn FnMut(u32, Option<(usize, &HitRecord)>, Vec) + 'a;
+
+/** An algorithm estimating the light arriving along camera rays. */
+pub trait Integrator {
+    /** The light arriving along `ray`. `visit` sees every vertex of the estimate: its
+     * depth, 0 where the camera ray hits, the hit, None where the ray escaped, and the
+     * light that reaches the camera after that many bounces. Integrators that do not
+     * follow paths report all of their light at depth 0.
+     */
+    fn trace(&self, ray: Ray, scene: &Scene, visit: &mut Visit) -> Vec;
+
+    fn sample(&self, ray: Ray, scene: &Scene) -> Vec {
+        self.trace(ray, scene, &mut |_, _, _| {})
+    }
+}
+
+/** The integrators a render can be asked for by name. */
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Method {
+    #[default]
+    Path,
+    Whitted,
+    AmbientOcclusion {
+        distance: f32,
+    },
+    Direct,
+    Debug(View),
+}
+
+impl Method {
+    pub const NAMES: [&'static str; 8] = [
+        "path",
+        "whitted",
+        "ao",
+        "direct",
+        "normal",
+        "depth",
+        "uv",
+        "barycentrics",
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Method::Path => "path",
+            Method::Whitted => "whitted",
+            Method::AmbientOcclusion { .. } => "ao",
+            Method::Direct => "direct",
+            Method::Debug(View::Normal) => "normal",
+            Method::Debug(View::Depth { .. }) => "depth",
+            Method::Debug(View::Uv) => "uv",
+            Method::Debug(View::Barycentrics) => "barycentrics",
+        }
+    }
+
+    /** The name with the distance, if any, which `from_name` reads back as the same method. */
+    pub fn spec(&self) -> String {
+        match self {
+            Method::AmbientOcclusion { distance } => format!("ao:{distance}"),
+            Method::Debug(View::Depth { far }) => format!("depth:{far}"),
+            _ => self.name().to_string(),
+        }
+    }
+
+    /** Parses a name, which ambient occlusion and depth may follow with a distance:
+     * `ao:<distance>` for how far occluders are looked for, 1 by default, and
+     * `depth:<far>` for the depth shown black, 10 by default.
+     */
+    pub fn from_name(value: &str) -> Option<Method> {
+        let (name, distance) = match value.split_once(':') {
+            Some((name, distance)) => {
+                let distance = distance
+                    .parse()
+                    .ok()
+                    .filter(|distance: &f32| distance.is_finite() && *distance > 0.)?;
+                (name, Some(distance))
+            }
+            None => (value, None),
+        };
+        let method = match name {
+            "ao" => Method::AmbientOcclusion {
+                distance: distance.unwrap_or(AO_DISTANCE),
+            },
+            "depth" => Method::Debug(View::Depth {
+                far: distance.unwrap_or(DEPTH_FAR),
+            }),
+            _ if distance.is_some() => return None,
+            "path" => Method::Path,
+            "whitted" => Method::Whitted,
+            "direct" => Method::Direct,
+            "normal" => Method::Debug(View::Normal),
+            "uv" => Method::Debug(View::Uv),
+            "barycentrics" => Method::Debug(View::Barycentrics),
+            _ => return None,
+        };
+        Some(method)
+    }
+
+    /** The integrator; only path tracing ends paths with Russian roulette. */
+    pub fn integrator(&self, roulette_depth: Option<u32>) -> Box<dyn Integrator> {
+        match *self {
+            Method::Path => Box::new(PathTracer {
+                roulette_depth,
+                sample_lights: true,
+            }),
+            Method::Whitted => Box::new(Whitted),
+            Method::AmbientOcclusion { distance } => Box::new(AmbientOcclusion { distance }),
+            Method::Direct => Box::new(DirectLighting),
+            Method::Debug(view) => Box::new(view),
+        }
+    }
+}
+
+/* Weights a sample drawn with density `pdf` against another strategy that could have
+ * drawn it with density `other`.
+ */
+fn power_heuristic(pdf: f32, other: f32) -> f32 {
+    pdf * pdf / (pdf * pdf + other * other)
+}
+
+/* Samples a point on the light `index`, picked with probability `choice`, and returns
+ * the light it sends along `ray` by way of the hit, zero when something is in between.
+ * With `mis`, the light is weighted against finding it by scattering off the hit.
+ */
+fn sample_light(
+    scene: &Scene,
+    ray: Ray,
+    record: &HitRecord,
+    index: usize,
+    choice: f32,
+    mis: bool,
+) -> Vec {
+    let (object, light) = &scene.lights()[index];
+    let Some((p, _, light_pdf)) = light.sample_from(record.p) else {
+        return Vec::new();
+    };
+    let direct = p - record.p;
+    let Some((f, scatter_pdf)) = record.material.eval(ray, record, direct) else {
+        return Vec::new();
+    };
+    if f.near_zero() {
+        return Vec::new();
+    }
+    stats::count(Counter::ShadowRays);
+    let shadow = Ray::new(record.p, direct, ray.time);
+    match scene.hit_object(shadow, 0.001, 1. + 1e-3) {
+        /* Points on the far side of a sphere are hidden by its near side. */
+        Some((hit, light_record)) if hit == *object && light_record.t > 1. - 1e-3 => {
+            let pdf = choice * light_pdf;
+            let weight = if mis {
+                power_heuristic(pdf, scatter_pdf)
+            } else {
+                1.
+            };
+            light_record.material.emitted(&light_record).scale(f) * (weight / pdf)
+        }
+        _ => Vec::new(),
+    }
+}
+
+/* The density with which sampling a random light would have found the hit of `ray`,
+ * None when the shape hit is not a light.
+ */
+fn light_pdf(scene: &Scene, object: usize, ray: Ray, record: &HitRecord) -> Option<f32> {
+    let lights = scene.lights();
+    let (_, light) = lights.iter().find(|(index, _)| *index == object)?;
+    Some(light.pdf_from(ray.origin, record.p, record.n) / lights.len() as f32)
+}
+
+/* Follows a path from the camera ray through at most `vertices` hits, adding up the
+ * light found at each weighted by the throughput of the path up to it, the product of
+ * the attenuations so far. With `sample_lights`, every vertex also samples a random
+ * light, which arrives one bounce later, and light that paths hit on lights is weighted
+ * against having been sampled. After `roulette_depth` bounces, a path survives each
+ * further one with a probability of its largest throughput component, and survivors
+ * are weighted up by its inverse so that the estimate stays unbiased.
+ */
+fn trace_path(
+    ray: Ray,
+    scene: &Scene,
+    vertices: u32,
+    roulette_depth: Option<u32>,
+    sample_lights: bool,
+    visit: &mut Visit,
+) -> Vec {
+    let (mut ray, mut radiance, mut throughput) = (ray, Vec::new(), Vec::from([1., 1., 1.]));
+    /* The light sampled at the previous vertex, and the density the path left it with. */
+    let (mut sampled, mut scatter_pdf) = (Vec::new(), None);
+    for depth in 0..vertices {
+        if depth > 0 {
+            stats::count(Counter::ScatterRays);
+        }
+        let Some((object, record)) = scene.hit_object(ray, 0.001, f32::MAX) else {
+            let light = sampled + scene.background(ray).scale(throughput);
+            visit(depth, None, light);
+            return radiance + light;
+        };
+        let mut emitted = record.material.emitted(&record);
+        if let Some(scatter_pdf) = scatter_pdf {
+            if let Some(light_pdf) = light_pdf(scene, object, ray, &record) {
+                emitted *= power_heuristic(scatter_pdf, light_pdf);
+            }
+        }
+        let light = sampled + emitted.scale(throughput);
+        visit(depth, Some((object, &record)), light);
+        radiance += light;
+        if depth + 1 == vertices {
+            break;
+        }
+
+        let lights = scene.lights().len();
+        sampled = if sample_lights && lights > 0 {
+            let index = ((rng::random() * lights as f32) as usize).min(lights - 1);
+            let choice = 1. / lights as f32;
+            sample_light(scene, ray, &record, index, choice, true).scale(throughput)
+        } else {
+            Vec::new()
+        };
+        /* A path ending here still gets the light its last vertex sampled. */
+        let Some((attenuation, scattered)) = record.material.scatter(ray, record) else {
+            visit(depth + 1, None, sampled);
+            return radiance + sampled;
+        };
+        scatter_pdf = if sample_lights {
+            let eval = record.material.eval(ray, &record, scattered.direct);
+            eval.map(|(_, pdf)| pdf)
+        } else {
+            None
+        };
+        throughput = throughput.scale(attenuation);
+        if roulette_depth.is_some_and(|roulette_depth| depth + 1 >= roulette_depth) {
+            let survival = throughput.x().max(throughput.y()).max(throughput.z());
+            if survival < 1. {
+                if rng::random() >= survival {
+                    stats::count(Counter::RouletteTerminations);
+                    visit(depth + 1, None, sampled);
+                    return radiance + sampled;
+                }
+                throughput /= survival;
+            }
+        }
+        ray = scattered;
+    }
+    radiance
+}
+
+/** Unidirectional path tracing. With `sample_lights`, each vertex is lit by next event
+ * estimation, a shadow ray towards a random point on a random light, combined with the
+ * light paths find by scattering through multiple importance sampling with the power
+ * heuristic. Without it, paths only find light by hitting it.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathTracer {
+    pub roulette_depth: Option<u32>,
+    pub sample_lights: bool,
+}
+
+impl Integrator for PathTracer {
+    fn trace(&self, ray: Ray, scene: &Scene, visit: &mut Visit) -> Vec {
+        let sample_lights = self.sample_lights;
+        trace_path(
+            ray,
+            scene,
+            MAX_DEPTH,
+            self.roulette_depth,
+            sample_lights,
+            visit,
+        )
+    }
+}
+
+/** Light that reaches the camera after at most one bounce: what camera rays hit, and
+ * the light arriving at their hits, from lights and the background, sampled as the path
+ * tracer samples it.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DirectLighting;
+
+impl Integrator for DirectLighting {
+    fn trace(&self, ray: Ray, scene: &Scene, visit: &mut Visit) -> Vec {
+        trace_path(ray, scene, 2, None, true, visit)
+    }
+}
+
+/** Whitted-style ray tracing: rays follow mirrors and glass until they hit a diffuse
+ * surface, which is lit by a shadow ray towards every light and an ambient term, its
+ * albedo times the background in the direction of its normal. Light bouncing between
+ * diffuse surfaces is missing.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Whitted;
+
+impl Integrator for Whitted {
+    fn trace(&self, ray: Ray, scene: &Scene, visit: &mut Visit) -> Vec {
+        let (mut ray, mut radiance, mut throughput) = (ray, Vec::new(), Vec::from([1., 1., 1.]));
+        let mut first = None;
+        for depth in 0..MAX_DEPTH {
+            if depth > 0 {
+                stats::count(Counter::ScatterRays);
+            }
+            let Some((object, record)) = scene.hit_object(ray, 0.001, f32::MAX) else {
+                radiance += scene.background(ray).scale(throughput);
+                break;
+            };
+            if depth == 0 {
+                first = Some((object, record));
+            }
+            radiance += record.material.emitted(&record).scale(throughput);
+            if record.material.lobe(&record) == Lobe::Diffuse {
+                let normal = Ray::new(record.p, record.n, ray.time);
+                let mut light = record
+                    .material
+                    .albedo(&record)
+                    .scale(scene.background(normal));
+                for index in 0..scene.lights().len() {
+                    light += sample_light(scene, ray, &record, index, 1., false);
+                }
+                radiance += light.scale(throughput);
+                break;
+            }
+            let Some((attenuation, scattered)) = record.material.scatter(ray, record) else {
+                break;
+            };
+            throughput = throughput.scale(attenuation);
+            ray = scattered;
+        }
+        visit(
+            0,
+            first.as_ref().map(|(object, record)| (*object, record)),
+            radiance,
+        );
+        radiance
+    }
+}
+
+/** How much of the hemisphere above what camera rays hit is open, weighted by the
+ * cosine to the normal: white where no other surface is within `distance`, black where
+ * the hit is enclosed. Rays that escape are black.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AmbientOcclusion {
+    pub distance: f32,
+}
+
+impl Integrator for AmbientOcclusion {
+    fn trace(&self, ray: Ray, scene: &Scene, visit: &mut Visit) -> Vec {
+        let Some((object, record)) = scene.hit_object(ray, 0.001, f32::MAX) else {
+            visit(0, None, Vec::new());
+            return Vec::new();
+        };
+        let direct = record.n + Vec::new_rand_unit_sphere().to_unit();
+        let direct = if direct.near_zero() { record.n } else { direct };
+        stats::count(Counter::ShadowRays);
+        let occluder = Ray::new(record.p, direct.to_unit(), ray.time);
+        let open = match scene.hit(occluder, 0.001, self.distance) {
+            Some(_) => 0.,
+            None => 1.,
+        };
+        let value = Vec::from([open; 3]);
+        visit(0, Some((object, &record)), value);
+        value
+    }
+}
+
+/** Shows a property of what camera rays hit instead of light; rays that escape are
+ * black. Normals are the outward ones, mapped from [-1, 1] to [0, 1]; depth fades from
+ * white at the camera to black at `far`; u and v repeat every unit; barycentrics are
+ * those of vertices 0, 1 and 2 of the triangles of meshes and black on other shapes.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum View {
+    Normal,
+    Depth { far: f32 },
+    Uv,
+    Barycentrics,
+}
+
+impl Integrator for View {
+    fn trace(&self, ray: Ray, scene: &Scene, visit: &mut Visit) -> Vec {
+        let hit = scene.hit_object(ray, 0.001, f32::MAX);
+        let value = match &hit {
+            None => Vec::new(),
+            Some((_, record)) => match *self {
+                View::Normal => {
+                    let n = if record.is_front { record.n } else { -record.n };
+                    0.5 * (n + Vec::from([1., 1., 1.]))
+                }
+                View::Depth { far } => {
+                    let depth = record.t * ray.direct.len();
+                    Vec::from([(1. - depth / far).max(0.); 3])
+                }
+                View::Uv => {
+                    Vec::from([record.u - record.u.floor(), record.v - record.v.floor(), 0.])
+                }
+                View::Barycentrics => record
+                    .barycentrics
+                    .map_or(Vec::new(), |(u, v)| Vec::from([1. - u - v, u, v])),
+            },
+        };
+        visit(
+            0,
+            hit.as_ref().map(|(object, record)| (*object, record)),
+            value,
+        );
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        builder::SceneBuilder, material::Lambertian, point::Point, scene::Background,
+        scenes::Builtin, transform::Transform,
+    };
+
+    const SIZE: usize = 16;
+
+    /* The mean of `spp` samples of each pixel of a small image of the scene. */
+    fn render(
+        integrator: &dyn Integrator,
+        builtin: Builtin,
+        spp: u32,
+        seed: u64,
+    ) -> std::vec::Vec<Vec> {
+        let scene = builtin.scene(0).unwrap();
+        let camera = builtin.camera(1.);
+        let mut pixels = std::vec::Vec::new();
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let mut sum = Vec::new();
+                for index in 0..spp {
+                    rng::seed(rng::hash(&[seed, x as u64, y as u64, index as u64]));
+                    let u = (x as f32 + rng::random()) / SIZE as f32;
+                    let v = (y as f32 + rng::random()) / SIZE as f32;
+                    sum += integrator.sample(camera.get_ray(u, v), &scene);
+                }
+                pixels.push(sum / spp as f32);
+            }
+        }
+        pixels
+    }
+
+    fn mean(pixels: &[Vec]) -> f32 {
+        let sum: f32 = pixels.iter().map(|p| p.x() + p.y() + p.z()).sum();
+        sum / (3 * pixels.len()) as f32
+    }
+
+    fn rmse(a: &[Vec], b: &[Vec]) -> f32 {
+        let sum: f32 = a.iter().zip(b).map(|(a, b)| (*a - *b) * (*a - *b)).sum();
+        (sum / (3 * a.len()) as f32).sqrt()
+    }
+
+    #[test]
+    fn test_methods() {
+        for name in Method::NAMES {
+            assert_eq!(Method::from_name(name).unwrap().name(), name);
+        }
+        for name in ["path", "ao:0.3", "depth:2.5", "uv"] {
+            let method = Method::from_name(name).unwrap();
+            assert_eq!(Method::from_name(&method.spec()), Some(method));
+        }
+        assert_eq!(Method::from_name("path"), Some(Method::default()));
+        assert_eq!(
+            Method::from_name("ao:50"),
+            Some(Method::AmbientOcclusion { distance: 50. })
+        );
+        assert_eq!(
+            Method::from_name("depth:2.5"),
+            Some(Method::Debug(View::Depth { far: 2.5 }))
+        );
+        assert_eq!(Method::from_name("ao:-1"), None);
+        assert_eq!(Method::from_name("path:3"), None);
+        assert_eq!(Method::from_name("bidirectional"), None);
+    }
+
+    #[test]
+    fn test_light_sampling() {
+        /* Sampling the light of the Cornell box gives the image that finding it by chance
+         * gives, with far less noise. Pixels seeing the light itself are left out, their
+         * noise is that of the jitter across its edges whatever the integrator.
+         */
+        let path = |sample_lights| PathTracer {
+            roulette_depth: Some(5),
+            sample_lights,
+        };
+        let reference = render(&path(true), Builtin::CornellBox, 64, 1);
+        let unlit: std::vec::Vec<bool> = reference.iter().map(|p| p.y() < 1.).collect();
+        let unlit = |pixels: std::vec::Vec<Vec>| {
+            let pixels = pixels.into_iter().zip(&unlit);
+            pixels
+                .filter(|(_, unlit)| **unlit)
+                .map(|(p, _)| p)
+                .collect::<std::vec::Vec<_>>()
+        };
+        let render = |integrator: &dyn Integrator, spp, seed| {
+            unlit(render(integrator, Builtin::CornellBox, spp, seed))
+        };
+        let reference = unlit(reference);
+        let (nee, nee_again) = (render(&path(true), 16, 2), render(&path(true), 16, 3));
+        let (bsdf, bsdf_again) = (render(&path(false), 16, 2), render(&path(false), 16, 3));
+        let converged = render(&path(false), 128, 4);
+        for image in [&nee, &converged] {
+            let error = mean(image) / mean(&reference) - 1.;
+            assert!(error.abs() < 0.05, "{error}");
+        }
+        let (noise, bsdf_noise) = (rmse(&nee, &nee_again), rmse(&bsdf, &bsdf_again));
+        assert!(noise < 0.3 * bsdf_noise, "{noise} {bsdf_noise}");
+
+        /* Without glass or mirrors, both estimate the light after at most one bounce. */
+        let direct = render(&DirectLighting, 64, 4);
+        let whitted = render(&Whitted, 64, 5);
+        let error = mean(&whitted) / mean(&direct) - 1.;
+        assert!(error.abs() < 0.05, "{error}");
+        let share = mean(&direct) / mean(&reference);
+        assert!((0.4..0.8).contains(&share), "{share}");
+    }
+
+    #[test]
+    fn test_sphere_light() {
+        /* Straight below a sphere light of radiance L, radius r and distance d, a white
+         * diffuse floor reflects L (r / d)^2. Sampling the visible cone of the sphere
+         * finds it every time, so samples barely vary.
+         */
+        let scene = SceneBuilder::new()
+            .material("white", Lambertian::new_const([1.; 3]))
+            .light("light", Vec::from([4., 4., 4.]))
+            .plane(Point::new(), Vec::from([0., 1., 0.]), "white")
+            .sphere(Point::from([0., 2., 0.]), 0.5, "light")
+            .background(Background::Constant(Vec::new()))
+            .build()
+            .unwrap();
+        let ray = Ray::from(Point::from([0.1, 1., 0.1]), Vec::from([-0.1, -1., -0.1]));
+        rng::seed(3);
+        let n = 4096;
+        let samples: std::vec::Vec<f32> = (0..n)
+            .map(|_| DirectLighting.sample(ray, &scene).x())
+            .collect();
+        let mean = samples.iter().sum::<f32>() / n as f32;
+        let expected = 4. * (0.5_f32 / 2.).powi(2);
+        assert!((mean / expected - 1.).abs() < 0.02, "{mean} {expected}");
+        let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / n as f32;
+        /* Drawing points over the whole sphere instead, half of them hidden, gives 1.6. */
+        assert!(variance.sqrt() < 0.1 * mean, "{variance}");
+    }
+
+    #[test]
+    fn test_ambient_occlusion() {
+        let grey = Lambertian::new_const([0.5; 3]);
+        let scene = SceneBuilder::new()
+            .material("grey", grey)
+            .plane(Point::new(), Vec::from([0., 1., 0.]), "grey")
+            .plane(Point::from([0., 1., 0.]), Vec::from([0., -1., 0.]), "grey")
+            .build()
+            .unwrap();
+        let ray = Ray::from(Point::from([0., 0.5, 0.]), Vec::from([0., -1., 1.]));
+        for (distance, open) in [(0.5, 1.), (f32::MAX, 0.)] {
+            let ao = AmbientOcclusion { distance };
+            for _ in 0..16 {
+                assert_eq!(ao.sample(ray, &scene).x(), open);
+            }
+        }
+        let up = Ray::from(Point::from([0., 2., 0.]), Vec::from([0., 1., 0.]));
+        assert_eq!(AmbientOcclusion { distance: 1. }.sample(up, &scene).x(), 0.);
+    }
+
+    #[test]
+    fn test_views() {
+        let vertices = vec![
+            Point::from([-1., -1., 0.]),
+            Point::from([1., -1., 0.]),
+            Point::from([0., 1., 0.]),
+        ];
+        let scene = SceneBuilder::new()
+            .material("grey", Lambertian::new_const([0.5; 3]))
+            .sphere(Point::from([0., 0., -1.]), 0.5, "grey")
+            .mesh("triangle", vertices, vec![[0, 1, 2]], "grey")
+            .instance("triangle", Transform::translate(Vec::from([0., 0., 2.])))
+            .build()
+            .unwrap();
+        let forward = Ray::from(Point::new(), Vec::from([0., 0., -2.]));
+        let backward = Ray::from(Point::new(), Vec::from([0., 0., 1.]));
+        let sample = |view: View, ray| view.sample(ray, &scene);
+
+        let normal = sample(View::Normal, forward);
+        assert_eq!((normal.x(), normal.y(), normal.z()), (0.5, 0.5, 1.));
+        assert_eq!(sample(View::Depth { far: 2. }, forward).x(), 0.75);
+        assert_eq!(sample(View::Depth { far: 0.25 }, forward).x(), 0.);
+        assert!(sample(View::Barycentrics, forward).near_zero());
+        let barycentrics = sample(View::Barycentrics, backward);
+        assert_eq!(barycentrics.x() + barycentrics.y() + barycentrics.z(), 1.);
+        assert_eq!(barycentrics.z(), 0.5);
+        let uv = sample(View::Uv, forward);
+        assert!((uv - Vec::from([0.25, 0.5, 0.])).len() < 1e-6, "{uv}");
+        let up = Ray::from(Point::new(), Vec::from([0., 1., 0.]));
+        assert!(sample(View::Uv, up).near_zero());
+    }
+}
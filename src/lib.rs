@@ -0,0 +1,75 @@
+//! A small CPU path tracer.
+//!
+//! Build a [`Scene`] out of shapes and materials, directly or from named resources with
+//! a [`SceneBuilder`], point a [`Camera`] at it and
+//! [`render()`] it as the [`Settings`] say into a [`Film`], the HDR buffer a render
+//! accumulates. An [`integrator`] estimates the light of every sample, by default a path
+//! tracer sampling the scene's lights. A film develops into any of the image writers,
+//! [`Ppm`] and [`Png`], or goes into an [`Exr`] together with its AOVs. Whatever can
+//! fail returns an [`Error`] saying what failed and on which file, object or parameter.
+//! [`diff`] compares renders with each other or with a reference.
+//!
+//! ```
+//! use std::sync::Arc;
+//!
+//! use rtus::{
+//!     material::Lambertian, point::Point, render, shape::Sphere, Camera, Image, Png, Scene,
+//!     Settings,
+//! };
+//!
+//! let grey = Arc::new(Lambertian::new_const([0.5, 0.5, 0.5]));
+//! let scene = Scene::new().with(Sphere::new(Point::from([0., 0., -1.]), 0.5, grey)?);
+//! let settings = Settings {
+//!     width: 32,
+//!     height: 18,
+//!     spp: 4,
+//!     ..Settings::default()
+//! };
+//! let film = render(&scene, &Camera::new(), &settings)?;
+//! let mut image = Png::with_size(film.width(), film.height());
+//! film.develop(&mut image)?;
+//! # assert_eq!(film.pixel(16, 9).samples, 4);
+//! # Ok::<(), rtus::Error>(())
+//! ```
+
+pub mod aabb;
+pub mod aov;
+pub mod builder;
+pub mod camera;
+pub mod color;
+pub mod csg;
+pub mod curve;
+pub mod denoise;
+pub mod diff;
+pub mod error;
+pub mod film;
+pub mod image;
+pub mod instance;
+pub mod integrator;
+pub mod material;
+pub mod medium;
+pub mod mesh;
+pub mod motion;
+pub mod planar;
+pub mod point;
+pub mod quadric;
+pub mod ray;
+pub mod render;
+pub mod rng;
+pub mod scene;
+pub mod scenes;
+pub mod sdf;
+pub mod shape;
+pub mod stats;
+pub mod texture;
+pub mod transform;
+pub mod vec;
+pub mod volume;
+
+pub use builder::SceneBuilder;
+pub use camera::Camera;
+pub use error::Error;
+pub use film::Film;
+pub use image::{Exr, Image, Png, Ppm};
+pub use render::{render, render_film, Settings};
+pub use scene::Scene;
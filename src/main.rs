@@ -1,89 +1,159 @@
-use camera::Camera;
-use color::Color;
-use image::{Image, Ppm};
-use indicatif::ProgressBar;
-use point::Point;
-use ray::Ray;
-use scene::Scene;
-use shape::Sphere;
-use vec::Vec;
+use std::fs;
 
-mod camera;
-mod color;
-mod image;
-mod material;
-mod point;
-mod ray;
-mod scene;
-mod shape;
-mod vec;
+use rtus::{
+    aov::Aov,
+    denoise::Denoiser,
+    diff::LinearImage,
+    image,
+    render::{self, render_sequence, Adaptive, Output, Sequence},
+    render_film,
+    stats::Stats,
+    Error, Image, Ppm, Settings,
+};
 
-const SAMPLES_PER_PIXEL: u32 = 100;
-const MAX_DEPTH: u32 = 50;
+use cli::{Args, Command, DiffArgs};
 
-fn sample(ray: Ray, scene: &Scene, depth: u32) -> Vec {
-    if depth >= MAX_DEPTH {
-        return Vec::new();
-    }
-    scene
-        .hit(ray, 0.001, f32::MAX)
-        .map(|record| {
-            if let Some((attenuation, scattered_ray)) = record.material.scatter(ray, record) {
-                sample(scattered_ray, scene, depth + 1).scale(attenuation)
-            } else {
-                Vec::from([0., 0., 0.])
+mod cli;
+
+fn main() {
+    let command = Command::parse(std::env::args().skip(1)).unwrap_or_else(|msg| {
+        eprintln!("{msg}\n{}", cli::USAGE);
+        std::process::exit(2);
+    });
+    match command {
+        Command::Render(args) => {
+            if let Err(error) = run(&args) {
+                eprintln!("error: {error}");
+                std::process::exit(1);
+            }
+        }
+        /* Like cmp: 1 when the images differ too much, 2 when they cannot be compared. */
+        Command::Diff(args) => match diff(&args) {
+            Ok(true) => {}
+            Ok(false) => std::process::exit(1),
+            Err(error) => {
+                eprintln!("error: {error}");
+                std::process::exit(2);
             }
-        })
-        .unwrap_or({
-            let unit_direct = ray.direct.to_unit();
-            let t = 0.5 * (unit_direct.y() + 1.);
-            (1. - t) * Vec::from([1., 1., 1.]) + t * Vec::from([0.5, 0.7, 1.])
-        })
+        },
+    }
 }
 
-fn main() {
-    let camera = Camera::new();
-    let mut image = Ppm::new();
-    let mut scene = Scene::new();
+/* Prints the metrics and writes the heatmap; false when the metric is worse than the
+ * threshold.
+ */
+fn diff(args: &DiffArgs) -> Result<bool, Error> {
+    let image = LinearImage::from_file(&args.image)?;
+    let reference = LinearImage::from_file(&args.reference)?;
+    let metrics = image.compare(&reference)?;
+    println!("{metrics}");
+    if let Some(fname) = &args.heatmap {
+        let mut heatmap = image::for_file(fname, image.width, image.height)?;
+        let largest = image.heatmap(&reference, heatmap.as_mut())?;
+        heatmap.to_file(fname)?;
+        println!("{fname}: red is an error of {largest:.6}");
+    }
+    let Some(threshold) = args.threshold else {
+        return Ok(true);
+    };
+    let (metric, value) = (args.metric, metrics.get(args.metric));
+    let within = metric.is_within(value, threshold);
+    if !within {
+        println!(
+            "{} {value} is worse than the threshold {threshold}",
+            metric.name()
+        );
+    }
+    Ok(within)
+}
 
-    scene.push(Sphere::new(
-        Point::from([0., 0., -1.]),
-        0.5,
-        &material::CENTER_MATERIAL,
-    ));
-    scene.push(Sphere::new(
-        Point::from([0., -100.5, -1.]),
-        100.,
-        &material::GROUND_MATERIAL,
-    ));
-    scene.push(Sphere::new(
-        Point::from([-1., 0., -1.]),
-        -0.4,
-        &material::LEFT_MATERIAL,
-    ));
-    scene.push(Sphere::new(
-        Point::from([1., 0., -1.]),
-        0.5,
-        &material::RIGHT_MATERIAL,
-    ));
+fn run(args: &Args) -> Result<(), Error> {
+    let unlimited = args.time.is_some() || args.target_error.is_some() || args.adaptive.is_some();
+    let adaptive = args.adaptive.map(|threshold| {
+        let default = Adaptive::default();
+        Adaptive {
+            threshold,
+            min_spp: args.min_spp.unwrap_or(default.min_spp),
+            max_spp: args.max_spp.unwrap_or(default.max_spp),
+        }
+    });
+    let mut settings = Settings {
+        spp: args.spp.unwrap_or(if unlimited { u32::MAX } else { 100 }),
+        seed: args.seed,
+        time_budget: args.time,
+        target_error: args.target_error,
+        adaptive,
+        checkpoint: args.checkpoint.clone().or(args.resume.clone()),
+        checkpoint_every: args.checkpoint_every,
+        integrator: args.integrator,
+        roulette_depth: args.roulette_depth,
+        ..Settings::default()
+    };
 
-    let bar = ProgressBar::new(image.height as u64);
-    for j in 0..image.height {
-        for i in 0..image.width {
-            let color_vec: Vec = (0..SAMPLES_PER_PIXEL)
-                .map(|_| {
-                    let u = (i as f32 + rand::random::<f32>()) / (image.width as f32 - 1.);
-                    let v = (j as f32 + rand::random::<f32>()) / (image.height as f32 - 1.);
-                    sample(camera.get_ray(u, v), &scene, 0)
-                })
-                .sum();
+    let (width, height) = (settings.width, settings.height);
+    if let Some(crop) = args.crop {
+        settings.window = Some(crop.window(width, height)?);
+    }
+    /* A resumed film keeps the AOVs it was started with. */
+    settings.aovs = args.aovs.clone();
+    if args.denoise.is_some() {
+        settings.aovs.extend([Aov::Albedo, Aov::Normal, Aov::Depth]);
+    }
+    let output = Output {
+        denoiser: args.denoise.map(|strength| Denoiser {
+            strength,
+            ..Denoiser::default()
+        }),
+        composite: args.composite,
+        aovs: args.aovs.clone(),
+        multilayer: args.multilayer,
+    };
+    let scene = args.builtin.scene(settings.seed)?;
 
-            let color = Color::from(color_vec / (SAMPLES_PER_PIXEL as f32));
-            image.plot(j, i, color);
+    let Some(frames) = args.frames.clone() else {
+        let fname = args.output.as_deref().unwrap_or("/tmp/fig.ppm");
+        /* An unknown format fails before the render rather than after it. */
+        image::for_file(fname, 0, 0)?;
+        let camera = args.builtin.camera(width as f32 / height as f32);
+        let mut film = match &args.resume {
+            Some(checkpoint) => render::resume(checkpoint, &settings, &scene)?,
+            None => settings.film()?,
+        };
+        let report = render_film(&scene, &camera, &settings, &mut film)?;
+        output.write(&film, fname)?;
+        println!("{fname}: {report}");
+        if let Some(fname) = &args.sample_map {
+            let mut map = Ppm::with_size(film.width(), film.height());
+            film.develop_samples(&mut map)?;
+            map.to_file(fname)?;
         }
-        bar.inc(1);
-    }
+        return write_stats(args, &report.stats);
+    };
+
+    let sequence = Sequence {
+        animation: args.builtin.turntable(),
+        frames,
+        fps: args.fps,
+        dir: args.output.clone().unwrap_or(".".to_string()),
+    };
+    let stats = render_sequence(
+        &scene,
+        &sequence,
+        &settings,
+        &output,
+        |fname, report| match report {
+            Some(report) => println!("{fname}: {report}"),
+            None => println!("skipping {fname}, already rendered"),
+        },
+    )?;
+    write_stats(args, &stats)
+}
 
-    image.to_file("/tmp/fig.ppm").expect("to_file err");
-    bar.finish();
+/* Prints the summary of the statistics and writes them where `--stats` says. */
+fn write_stats(args: &Args, stats: &Stats) -> Result<(), Error> {
+    println!("{stats}");
+    match &args.stats {
+        Some(fname) => fs::write(fname, stats.to_json() + "\n").map_err(|e| Error::io(fname, e)),
+        None => Ok(()),
+    }
 }
@@ -1,16 +1,47 @@
+use std::{f32::consts::PI, sync::Arc};
+
 use crate::{
     ray::{HitRecord, Ray},
+    rng,
+    texture::Texture,
     vec::Vec,
 };
 
-pub static CENTER_MATERIAL: Lambertian = Lambertian::new_const([0.1, 0.2, 0.5]);
-pub static GROUND_MATERIAL: Lambertian = Lambertian::new_const([0.8, 0.8, 0.]);
-// pub static LEFT_MATERIAL: Metal = Metal::new_const([0.8, 0.8, 0.8], 0.3);
-pub static LEFT_MATERIAL: Dielectric = Dielectric::new_const(1.5);
-pub static RIGHT_MATERIAL: Metal = Metal::new_const([0.8, 0.6, 0.2], 0.);
+/** The kind of scattering a material does, which the per-lobe AOVs split light by. */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lobe {
+    Diffuse,
+    Specular,
+}
 
-pub trait Material {
+/** Shapes own their material through an `Arc<dyn Material>`, so one material can be
+ * shared by many shapes and created at runtime; hit records borrow it from the shape.
+ */
+pub trait Material: Send + Sync {
     fn scatter(&self, ray: Ray, record: HitRecord) -> Option<(Vec, Ray)>;
+
+    /** How much of the light arriving from `direct` the material scatters back along
+     * `ray`, per solid angle and including the cosine term, together with the density
+     * `scatter` draws `direct` with. Their ratio is the attenuation `scatter` returns.
+     * None for specular materials, whose scattering cannot be evaluated for a given
+     * direction, and for materials that do not scatter.
+     */
+    fn eval(&self, _ray: Ray, _record: &HitRecord, _direct: Vec) -> Option<(Vec, f32)> {
+        None
+    }
+
+    fn emitted(&self, _record: &HitRecord) -> Vec {
+        Vec::new()
+    }
+
+    /** The color of the surface under white light, for AOVs and denoising. */
+    fn albedo(&self, _record: &HitRecord) -> Vec {
+        Vec::new()
+    }
+
+    fn lobe(&self, _record: &HitRecord) -> Lobe {
+        Lobe::Diffuse
+    }
 }
 
 pub struct Lambertian {
@@ -18,17 +49,31 @@ pub struct Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _: Ray, record: HitRecord) -> Option<(Vec, Ray)> {
-        let emit = record.n + Vec::new_rand_unit_sphere();
+    /* A normal plus a random unit vector is distributed by the cosine to the normal. */
+    fn scatter(&self, ray: Ray, record: HitRecord) -> Option<(Vec, Ray)> {
+        let emit = record.n + Vec::new_rand_unit_sphere().to_unit();
         if emit.near_zero() {
             None
         } else {
-            Some((self.albedo, Ray::from(record.p, emit)))
+            Some((self.albedo, Ray::new(record.p, emit, ray.time)))
         }
     }
+
+    fn eval(&self, _ray: Ray, record: &HitRecord, direct: Vec) -> Option<(Vec, f32)> {
+        let cos_theta = (record.n * direct.to_unit()).max(0.);
+        Some((self.albedo * (cos_theta / PI), cos_theta / PI))
+    }
+
+    fn albedo(&self, _record: &HitRecord) -> Vec {
+        self.albedo
+    }
 }
 
 impl Lambertian {
+    pub fn new(albedo: Vec) -> Self {
+        Lambertian { albedo }
+    }
+
     pub const fn new_const(coeff: [f32; 3]) -> Self {
         Lambertian {
             albedo: Vec::new_const(coeff),
@@ -36,12 +81,45 @@ impl Lambertian {
     }
 }
 
+/** A Lambertian surface whose albedo comes from a texture. */
+pub struct Textured {
+    texture: Arc<dyn Texture>,
+}
+
+impl Textured {
+    pub fn new(texture: Arc<dyn Texture>) -> Textured {
+        Textured { texture }
+    }
+
+    fn at(&self, record: &HitRecord) -> Lambertian {
+        Lambertian::new(self.texture.value(record))
+    }
+}
+
+impl Material for Textured {
+    fn scatter(&self, ray: Ray, record: HitRecord) -> Option<(Vec, Ray)> {
+        self.at(&record).scatter(ray, record)
+    }
+
+    fn eval(&self, ray: Ray, record: &HitRecord, direct: Vec) -> Option<(Vec, f32)> {
+        self.at(record).eval(ray, record, direct)
+    }
+
+    fn albedo(&self, record: &HitRecord) -> Vec {
+        self.texture.value(record)
+    }
+}
+
 pub struct Metal {
     albedo: Vec,
     fuzz: f32,
 }
 
 impl Metal {
+    pub fn new(albedo: Vec, fuzz: f32) -> Metal {
+        Metal { albedo, fuzz }
+    }
+
     pub const fn new_const(coeff: [f32; 3], fuzz: f32) -> Metal {
         Metal {
             albedo: Vec::new_const(coeff),
@@ -57,9 +135,17 @@ impl Material for Metal {
         if emit * record.n < 0. {
             None
         } else {
-            Some((self.albedo, Ray::from(record.p, emit)))
+            Some((self.albedo, Ray::new(record.p, emit, ray.time)))
         }
     }
+
+    fn albedo(&self, _record: &HitRecord) -> Vec {
+        self.albedo
+    }
+
+    fn lobe(&self, _record: &HitRecord) -> Lobe {
+        Lobe::Specular
+    }
 }
 
 pub struct Dielectric {
@@ -89,12 +175,172 @@ impl Material for Dielectric {
         let sin_theta = f32::sqrt(1. - cos_theta * cos_theta);
 
         let emit = if refract_ratio * sin_theta > 1.
-            || Self::reflectance(cos_theta, refract_ratio) > rand::random()
+            || Self::reflectance(cos_theta, refract_ratio) > rng::random()
         {
             Vec::reflect(ray.direct.to_unit(), record.n)
         } else {
             Vec::refract(ray.direct.to_unit(), record.n, refract_ratio)
         };
-        Some((Vec::from([1., 1., 1.]), Ray::from(record.p, emit)))
+        Some((Vec::from([1., 1., 1.]), Ray::new(record.p, emit, ray.time)))
+    }
+
+    fn albedo(&self, _record: &HitRecord) -> Vec {
+        Vec::from([1., 1., 1.])
+    }
+
+    fn lobe(&self, _record: &HitRecord) -> Lobe {
+        Lobe::Specular
+    }
+}
+
+/** An emitter; only the front face, the side the outward normal points to, emits light. */
+pub struct DiffuseLight {
+    emit: Vec,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Vec) -> DiffuseLight {
+        DiffuseLight { emit }
+    }
+
+    pub const fn new_const(coeff: [f32; 3]) -> DiffuseLight {
+        DiffuseLight {
+            emit: Vec::new_const(coeff),
+        }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _: Ray, _: HitRecord) -> Option<(Vec, Ray)> {
+        None
+    }
+
+    fn emitted(&self, record: &HitRecord) -> Vec {
+        if record.is_front {
+            self.emit
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+/** A material whose parameters change over time, typically driven by curves, e.g.
+ * `Varying::new(move |time| Lambertian::new(albedo.at(time)))`. It is evaluated
+ * at the time each ray samples.
+ */
+pub struct Varying<F> {
+    material_at: F,
+}
+
+impl<M: Material, F: Fn(f32) -> M + Send + Sync> Varying<F> {
+    pub fn new(material_at: F) -> Varying<F> {
+        Varying { material_at }
+    }
+}
+
+impl<M: Material, F: Fn(f32) -> M + Send + Sync> Material for Varying<F> {
+    fn scatter(&self, ray: Ray, record: HitRecord) -> Option<(Vec, Ray)> {
+        (self.material_at)(ray.time).scatter(ray, record)
+    }
+
+    fn eval(&self, ray: Ray, record: &HitRecord, direct: Vec) -> Option<(Vec, f32)> {
+        (self.material_at)(ray.time).eval(ray, record, direct)
+    }
+
+    fn emitted(&self, record: &HitRecord) -> Vec {
+        (self.material_at)(record.time).emitted(record)
+    }
+
+    fn albedo(&self, record: &HitRecord) -> Vec {
+        (self.material_at)(record.time).albedo(record)
+    }
+
+    fn lobe(&self, record: &HitRecord) -> Lobe {
+        (self.material_at)(record.time).lobe(record)
+    }
+}
+
+/** Phase functions of participating media are exposed as materials, so a scattering
+ * event inside a medium is handled by the integrator exactly like a surface hit.
+ */
+pub struct Isotropic {
+    albedo: Vec,
+}
+
+impl Isotropic {
+    pub const fn new_const(coeff: [f32; 3]) -> Isotropic {
+        Isotropic {
+            albedo: Vec::new_const(coeff),
+        }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, ray: Ray, record: HitRecord) -> Option<(Vec, Ray)> {
+        let emit = Vec::new_rand_unit_sphere().to_unit();
+        Some((self.albedo, Ray::new(record.p, emit, ray.time)))
+    }
+
+    fn eval(&self, _ray: Ray, _record: &HitRecord, _direct: Vec) -> Option<(Vec, f32)> {
+        Some((self.albedo / (4. * PI), 1. / (4. * PI)))
+    }
+
+    fn albedo(&self, _record: &HitRecord) -> Vec {
+        self.albedo
+    }
+}
+
+pub struct HenyeyGreenstein {
+    albedo: Vec,
+    g: f32,
+}
+
+impl HenyeyGreenstein {
+    pub const fn new_const(coeff: [f32; 3], g: f32) -> HenyeyGreenstein {
+        HenyeyGreenstein {
+            albedo: Vec::new_const(coeff),
+            g,
+        }
+    }
+
+    pub fn phase(&self, cos_theta: f32) -> f32 {
+        let denom = 1. + self.g * self.g - 2. * self.g * cos_theta;
+        (1. - self.g * self.g) / (4. * PI * denom * denom.sqrt())
+    }
+
+    /* Samples the cosine between the incoming and the scattered direction,
+     * positive g favouring forward scattering.
+     */
+    fn sample_cos_theta(&self) -> f32 {
+        let u = rng::random();
+        if self.g.abs() < 1e-3 {
+            1. - 2. * u
+        } else {
+            let sq = (1. - self.g * self.g) / (1. + self.g - 2. * self.g * u);
+            ((1. + self.g * self.g - sq * sq) / (2. * self.g)).clamp(-1., 1.)
+        }
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn scatter(&self, ray: Ray, record: HitRecord) -> Option<(Vec, Ray)> {
+        let forward = ray.direct.to_unit();
+        let (tangent, bitangent) = forward.orthonormal_basis();
+        let cos_theta = self.sample_cos_theta();
+        let sin_theta = f32::sqrt(f32::max(0., 1. - cos_theta * cos_theta));
+        let phi = 2. * PI * rng::random();
+        let emit = sin_theta * phi.cos() * tangent
+            + sin_theta * phi.sin() * bitangent
+            + cos_theta * forward;
+        Some((self.albedo, Ray::new(record.p, emit, ray.time)))
+    }
+
+    fn eval(&self, ray: Ray, _record: &HitRecord, direct: Vec) -> Option<(Vec, f32)> {
+        let phase = self.phase(ray.direct.to_unit() * direct.to_unit());
+        Some((self.albedo * phase, phase))
+    }
+
+    fn albedo(&self, _record: &HitRecord) -> Vec {
+        self.albedo
     }
 }
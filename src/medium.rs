@@ -0,0 +1,181 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    material::Material,
+    ray::{HitRecord, Ray},
+    rng,
+    shape::Shape,
+};
+
+/** A homogeneous medium, described by its extinction coefficient (density).
+ * The fraction of light surviving a segment of length d is exp(-density * d).
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct Medium {
+    density: f32,
+}
+
+impl Medium {
+    pub fn new(density: f32) -> Medium {
+        Medium { density }
+    }
+
+    pub fn density(&self) -> f32 {
+        self.density
+    }
+
+    /** Samples a free-flight distance proportionally to the transmittance. */
+    pub fn sample_distance(&self) -> f32 {
+        -(1. - rng::random()).ln() / self.density
+    }
+
+    pub fn transmittance(&self, distance: f32) -> f32 {
+        (-self.density * distance).exp()
+    }
+
+    /* Returns the parameter of a scattering event on `ray` within [t_min, t_max], if any. */
+    fn sample_hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<f32> {
+        let ray_len = ray.direct.len();
+        let distance = self.sample_distance();
+        let t = t_min + distance / ray_len;
+        if t < t_max {
+            Some(t)
+        } else {
+            None
+        }
+    }
+}
+
+/** A medium of constant density filling the inside of a closed boundary shape. */
+pub struct ConstantMedium<S: Shape> {
+    boundary: S,
+    medium: Medium,
+    phase: Arc<dyn Material>,
+}
+
+impl<S: Shape> ConstantMedium<S> {
+    pub fn new(boundary: S, density: f32, phase: Arc<dyn Material>) -> ConstantMedium<S> {
+        ConstantMedium {
+            boundary,
+            medium: Medium::new(density),
+            phase,
+        }
+    }
+}
+
+impl<S: Shape> Shape for ConstantMedium<S> {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let enter = self.boundary.hit(ray, f32::MIN, f32::MAX)?.t;
+        let exit = self.boundary.hit(ray, enter + 0.0001, f32::MAX)?.t;
+
+        let enter = f32::max(enter, t_min);
+        let exit = f32::min(exit, t_max);
+        if enter >= exit {
+            return None;
+        }
+        let t = self.medium.sample_hit(ray, enter, exit)?;
+        Some(HitRecord::new(
+            ray,
+            t,
+            -ray.direct.to_unit(),
+            self.phase.as_ref(),
+        ))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+}
+
+/** A global homogeneous medium filling the whole scene. */
+pub struct Fog {
+    medium: Medium,
+    phase: Arc<dyn Material>,
+}
+
+impl Fog {
+    pub fn new(density: f32, phase: Arc<dyn Material>) -> Fog {
+        Fog {
+            medium: Medium::new(density),
+            phase,
+        }
+    }
+
+    pub fn transmittance(&self, distance: f32) -> f32 {
+        self.medium.transmittance(distance)
+    }
+
+    /** Returns a scattering event in front of the closest surface at t_max, if any. */
+    pub fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let t = self.medium.sample_hit(ray, t_min, t_max)?;
+        Some(HitRecord::new(
+            ray,
+            t,
+            -ray.direct.to_unit(),
+            self.phase.as_ref(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::{HenyeyGreenstein, Isotropic},
+        point::Point,
+        shape::Sphere,
+        vec::Vec,
+    };
+
+    fn phase() -> Arc<dyn Material> {
+        Arc::new(Isotropic::new_const([1., 1., 1.]))
+    }
+
+    #[test]
+    fn test_free_flight_mean() {
+        let medium = Medium::new(2.);
+        let n = 100000;
+        let mean = (0..n).map(|_| medium.sample_distance()).sum::<f32>() / n as f32;
+        assert!((mean - 0.5).abs() < 0.01);
+        assert_eq!(medium.transmittance(0.), 1.);
+        assert!((medium.transmittance(0.5) - (-1_f32).exp()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_constant_medium() {
+        let ray = Ray::from(Point::from([0., 0., -5.]), Vec::from([0., 0., 1.]));
+
+        let dense = ConstantMedium::new(
+            Sphere::new(Point::new(), 1., phase()).unwrap(),
+            1e6,
+            phase(),
+        );
+        let record = dense.hit(ray, 0.001, f32::MAX).unwrap();
+        assert!((record.t - 4.).abs() < 1e-3);
+
+        let empty =
+            ConstantMedium::new(Sphere::new(Point::new(), 1., phase()).unwrap(), 0., phase());
+        assert!(empty.hit(ray, 0.001, f32::MAX).is_none());
+
+        let inside = Ray::from(Point::new(), Vec::from([0., 0., 1.]));
+        let record = dense.hit(inside, 0.001, f32::MAX).unwrap();
+        assert!(record.t < 1e-3 + 0.001);
+    }
+
+    #[test]
+    fn test_henyey_greenstein_mean_cosine() {
+        let phase = HenyeyGreenstein::new_const([1., 1., 1.], 0.7);
+        let forward = Vec::from([0., 1., 0.]);
+        let ray = Ray::from(Point::new(), forward);
+        let n = 100000;
+        let mean = (0..n)
+            .map(|_| {
+                let record = HitRecord::new(ray, 0., -forward, &phase);
+                phase.scatter(ray, record).unwrap().1.direct * forward
+            })
+            .sum::<f32>()
+            / n as f32;
+        assert!((mean - 0.7).abs() < 0.01);
+    }
+}
@@ -0,0 +1,224 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    aabb::Aabb,
+    error::{ensure, Error},
+    material::Material,
+    point::Point,
+    ray::{HitRecord, Ray},
+    shape::Shape,
+    stats::{self, Counter},
+};
+
+/** An indexed triangle mesh. Triangles are wound counter-clockwise around their outward normal. */
+pub struct Mesh {
+    vertices: Vec<Point>,
+    triangles: Vec<[usize; 3]>,
+    material: Arc<dyn Material>,
+}
+
+impl Mesh {
+    pub fn new(
+        vertices: Vec<Point>,
+        triangles: Vec<[usize; 3]>,
+        material: Arc<dyn Material>,
+    ) -> Result<Mesh, Error> {
+        for (index, vertex) in vertices.iter().enumerate() {
+            ensure(
+                vertex.is_finite(),
+                "mesh",
+                format!("vertex {index} is {vertex}"),
+            )?;
+        }
+        for (index, triangle) in triangles.iter().enumerate() {
+            let object = format!("mesh triangle {index}");
+            ensure(
+                triangle.iter().all(|&i| i < vertices.len()),
+                &object,
+                format!("{triangle:?} indexes past {} vertices", vertices.len()),
+            )?;
+            let [a, b, c] = triangle.map(|i| vertices[i]);
+            ensure(
+                !(b - a).cross(&(c - a)).near_zero(),
+                &object,
+                format!("{triangle:?} is degenerate"),
+            )?;
+        }
+        Ok(Mesh {
+            vertices,
+            triangles,
+            material,
+        })
+    }
+
+    pub fn vertices(&self) -> &[Point] {
+        &self.vertices
+    }
+
+    pub fn triangles(&self) -> &[[usize; 3]] {
+        &self.triangles
+    }
+
+    /** Whether the mesh bounds a volume: every edge is shared by exactly two triangles
+     * that run along it in opposite directions, and the triangles wind around their
+     * outward normals, enclosing a positive volume.
+     */
+    pub fn is_closed(&self) -> bool {
+        let mut edges = HashMap::new();
+        for triangle in &self.triangles {
+            for k in 0..3 {
+                *edges
+                    .entry((triangle[k], triangle[(k + 1) % 3]))
+                    .or_insert(0) += 1;
+            }
+        }
+        let watertight = edges
+            .iter()
+            .all(|(&(a, b), &count)| count == 1 && edges.get(&(b, a)) == Some(&1));
+        let Some(&origin) = self.vertices.first() else {
+            return false;
+        };
+        let volume: f32 = self
+            .triangles
+            .iter()
+            .map(|triangle| {
+                let [a, b, c] = triangle.map(|i| self.vertices[i] - origin);
+                a * b.cross(&c)
+            })
+            .sum();
+        watertight && volume > 0.
+    }
+
+    /* Möller-Trumbore intersection; returns t and the barycentrics of vertices 1 and 2. */
+    fn hit_triangle(
+        &self,
+        index: usize,
+        ray: Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<(f32, f32, f32)> {
+        let [a, b, c] = self.triangles[index].map(|i| self.vertices[i]);
+        let e1 = b - a;
+        let e2 = c - a;
+        let p = ray.direct.cross(&e2);
+        let det = e1 * p;
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let inv_det = 1. / det;
+        let s = ray.origin - a;
+        let u = (s * p) * inv_det;
+        if !(0. ..=1.).contains(&u) {
+            return None;
+        }
+        let q = s.cross(&e1);
+        let v = (ray.direct * q) * inv_det;
+        if v < 0. || u + v > 1. {
+            return None;
+        }
+        let t = (e2 * q) * inv_det;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        Some((t, u, v))
+    }
+}
+
+impl Shape for Mesh {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let mut closest: Option<(usize, f32, f32, f32)> = None;
+        let mut t_max = t_max;
+        stats::add(Counter::IntersectionTests, self.triangles.len() as u64);
+        for index in 0..self.triangles.len() {
+            if let Some((t, u, v)) = self.hit_triangle(index, ray, t_min, t_max) {
+                t_max = t;
+                closest = Some((index, t, u, v));
+            }
+        }
+        let (index, t, u, v) = closest?;
+        let [a, b, c] = self.triangles[index].map(|i| self.vertices[i]);
+        let n = (b - a).cross(&(c - a)).to_unit();
+        Some(
+            HitRecord::new(ray, t, n, self.material.as_ref())
+                .with_uv(u, v)
+                .with_barycentrics(u, v),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        if self.vertices.is_empty() {
+            None
+        } else {
+            Some(Aabb::from_points(&self.vertices).pad())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::Lambertian, vec::Vec};
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new_const([0.5, 0.5, 0.5]))
+    }
+
+    #[test]
+    fn test_hit() {
+        let mesh = Mesh::new(
+            vec![
+                Point::from([-1., -1., -2.]),
+                Point::from([1., -1., -2.]),
+                Point::from([0., 1., -2.]),
+                Point::from([0., -1., -1.]),
+            ],
+            vec![[0, 1, 2], [0, 1, 3]],
+            material(),
+        )
+        .unwrap();
+        let ray = Ray::from(Point::new(), Vec::from([0., 0., -1.]));
+        let record = mesh.hit(ray, 0.001, f32::MAX).unwrap();
+        assert_eq!(record.t, 2.);
+        assert!(record.is_front);
+        assert_eq!(record.n.z(), 1.);
+
+        let ray = Ray::from(Point::new(), Vec::from([0., 1., -0.1]));
+        assert!(mesh.hit(ray, 0.001, f32::MAX).is_none());
+        assert!(!mesh.is_closed());
+    }
+
+    #[test]
+    fn test_is_closed() {
+        let vertices = vec![
+            Point::new(),
+            Point::from([1., 0., 0.]),
+            Point::from([0., 1., 0.]),
+            Point::from([0., 0., 1.]),
+        ];
+        let tetrahedron = [[0, 2, 1], [0, 1, 3], [0, 3, 2], [1, 2, 3]];
+        let mesh = |triangles: &[[usize; 3]]| {
+            Mesh::new(vertices.clone(), triangles.to_vec(), material()).unwrap()
+        };
+        assert!(mesh(&tetrahedron).is_closed());
+        assert!(!mesh(&tetrahedron[..3]).is_closed());
+        let inside_out = tetrahedron.map(|[a, b, c]| [a, c, b]);
+        assert!(!mesh(&inside_out).is_closed());
+    }
+
+    #[test]
+    fn test_invalid() {
+        let vertices = vec![
+            Point::from([0., 0., 0.]),
+            Point::from([1., 0., 0.]),
+            Point::from([2., 0., 0.]),
+        ];
+        let error = Mesh::new(vertices.clone(), vec![[0, 1, 2]], material())
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "invalid mesh triangle 0: [0, 1, 2] is degenerate"
+        );
+        assert!(Mesh::new(vertices, vec![[0, 1, 3]], material()).is_err());
+    }
+}
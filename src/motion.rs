@@ -0,0 +1,518 @@
+use crate::{
+    aabb::Aabb,
+    curve::Curve,
+    error::{ensure, Error},
+    point::Point,
+    transform::{Matrix4, Transform},
+    vec::Vec,
+};
+
+#[derive(Debug, Clone, Copy)]
+struct Quaternion {
+    v: Vec,
+    w: f32,
+}
+
+impl Quaternion {
+    /* From a pure rotation matrix, after Shoemake. */
+    fn from_matrix(m: &Matrix4) -> Quaternion {
+        let trace = m.at(0, 0) + m.at(1, 1) + m.at(2, 2);
+        if trace > 0. {
+            let s = (trace + 1.).sqrt();
+            let w = s / 2.;
+            let s = 0.5 / s;
+            Quaternion {
+                v: Vec::from([
+                    (m.at(2, 1) - m.at(1, 2)) * s,
+                    (m.at(0, 2) - m.at(2, 0)) * s,
+                    (m.at(1, 0) - m.at(0, 1)) * s,
+                ]),
+                w,
+            }
+        } else {
+            let mut i = 0;
+            if m.at(1, 1) > m.at(0, 0) {
+                i = 1;
+            }
+            if m.at(2, 2) > m.at(i, i) {
+                i = 2;
+            }
+            let (j, k) = ((i + 1) % 3, (i + 2) % 3);
+            let s = (m.at(i, i) - (m.at(j, j) + m.at(k, k)) + 1.).sqrt();
+            let mut v = Vec::new();
+            *v.at_mut(i) = s * 0.5;
+            let s = if s != 0. { 0.5 / s } else { s };
+            *v.at_mut(j) = (m.at(j, i) + m.at(i, j)) * s;
+            *v.at_mut(k) = (m.at(k, i) + m.at(i, k)) * s;
+            Quaternion {
+                v,
+                w: (m.at(k, j) - m.at(j, k)) * s,
+            }
+        }
+    }
+
+    fn to_matrix(self) -> Matrix4 {
+        let (x, y, z, w) = (self.v.x(), self.v.y(), self.v.z(), self.w);
+        Matrix4::from([
+            [
+                1. - 2. * (y * y + z * z),
+                2. * (x * y - z * w),
+                2. * (x * z + y * w),
+                0.,
+            ],
+            [
+                2. * (x * y + z * w),
+                1. - 2. * (x * x + z * z),
+                2. * (y * z - x * w),
+                0.,
+            ],
+            [
+                2. * (x * z - y * w),
+                2. * (y * z + x * w),
+                1. - 2. * (x * x + y * y),
+                0.,
+            ],
+            [0., 0., 0., 1.],
+        ])
+    }
+
+    fn dot(&self, rhs: &Quaternion) -> f32 {
+        self.v * rhs.v + self.w * rhs.w
+    }
+
+    fn normalize(self) -> Quaternion {
+        let len = self.dot(&self).sqrt();
+        Quaternion {
+            v: self.v / len,
+            w: self.w / len,
+        }
+    }
+
+    /* A bound on the angular speed of the rotation while slerp goes from self to rhs
+     * as t goes from 0 to 1. Slerp turns by 2 theta at an even pace, and the normalized
+     * lerp used for nearby rotations never faster than 4 tan(theta / 2) >= 2 theta.
+     */
+    fn max_turn(&self, rhs: &Quaternion) -> f32 {
+        let theta = self.dot(rhs).abs().clamp(0., 1.).acos();
+        4. * (theta / 2.).tan()
+    }
+
+    fn slerp(&self, rhs: &Quaternion, t: f32) -> Quaternion {
+        /* Take the short way round. */
+        let rhs = if self.dot(rhs) < 0. {
+            Quaternion {
+                v: -rhs.v,
+                w: -rhs.w,
+            }
+        } else {
+            *rhs
+        };
+        let cos = self.dot(&rhs);
+        if cos > 0.9995 {
+            return Quaternion {
+                v: (1. - t) * self.v + t * rhs.v,
+                w: (1. - t) * self.w + t * rhs.w,
+            }
+            .normalize();
+        }
+        let theta = cos.clamp(-1., 1.).acos();
+        let (a, b) = (((1. - t) * theta).sin(), (t * theta).sin());
+        let sin = theta.sin();
+        Quaternion {
+            v: (a * self.v + b * rhs.v) / sin,
+            w: (a * self.w + b * rhs.w) / sin,
+        }
+    }
+}
+
+/* A transform decomposed into translation, rotation and scale, as in pbrt,
+ * so that keyframes can be interpolated without shearing.
+ */
+#[derive(Debug, Clone, Copy)]
+struct Keyframe {
+    time: f32,
+    translate: Vec,
+    rotate: Quaternion,
+    scale: Matrix4,
+}
+
+impl Keyframe {
+    fn new(time: f32, transform: &Transform) -> Keyframe {
+        let m = transform.matrix();
+        let translate = Vec::from([m.at(0, 3), m.at(1, 3), m.at(2, 3)]);
+
+        let mut linear = [[0.; 4]; 4];
+        for (i, row) in linear.iter_mut().enumerate().take(3) {
+            for (j, x) in row.iter_mut().enumerate().take(3) {
+                *x = m.at(i, j);
+            }
+        }
+        linear[3][3] = 1.;
+        let linear = Matrix4::from(linear);
+
+        /* Polar decomposition: average the matrix with its inverse transpose until it is a rotation. */
+        let mut rotation = linear;
+        for _ in 0..100 {
+            let Some(inv) = rotation.transpose().inverse() else {
+                break;
+            };
+            let mut next = [[0.; 4]; 4];
+            let mut delta: f32 = 0.;
+            for (i, row) in next.iter_mut().enumerate() {
+                for (j, x) in row.iter_mut().enumerate() {
+                    *x = 0.5 * (rotation.at(i, j) + inv.at(i, j));
+                    delta = delta.max((*x - rotation.at(i, j)).abs());
+                }
+            }
+            rotation = Matrix4::from(next);
+            if delta < 1e-6 {
+                break;
+            }
+        }
+        Keyframe {
+            time,
+            translate,
+            rotate: Quaternion::from_matrix(&rotation).normalize(),
+            scale: rotation.transpose() * linear,
+        }
+    }
+
+    fn lerp(&self, rhs: &Keyframe, t: f32) -> Transform {
+        let translate = (1. - t) * self.translate + t * rhs.translate;
+        let rotate = self.rotate.slerp(&rhs.rotate, t).to_matrix();
+        let mut scale = [[0.; 4]; 4];
+        for (i, row) in scale.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = (1. - t) * self.scale.at(i, j) + t * rhs.scale.at(i, j);
+            }
+        }
+        let linear = rotate * Matrix4::from(scale);
+        Transform::from_matrix(Transform::translate(translate).matrix() * linear)
+            .unwrap_or_default()
+    }
+}
+
+/** A transform changing over time. */
+pub trait Animation {
+    fn at(&self, time: f32) -> Transform;
+
+    /** The times at which the animation changes course, in ascending order. */
+    fn key_times(&self) -> std::vec::Vec<f32>;
+
+    /** An upper bound on the speed of any point within `radius` of the origin, in
+     * distance per unit of time, while the animation runs from `t0` to `t1`.
+     */
+    fn max_speed(&self, t0: f32, t1: f32, radius: f32) -> f32;
+}
+
+/** Covers `aabb` over the whole animation. Each stretch between key times is sampled
+ * at regular steps; in between, a point is never further from its position at the
+ * nearest step than half a step at the animation's top speed, so the samples are
+ * padded by that much.
+ */
+pub fn motion_bounds(animation: &dyn Animation, aabb: &Aabb) -> Aabb {
+    const STEPS: usize = 64;
+    let radius = aabb
+        .corners()
+        .iter()
+        .map(|&p| (p - Point::new()).len())
+        .fold(0., f32::max);
+    let key_times = animation.key_times();
+    let transform = animation.at(key_times[0]);
+    let mut corners: std::vec::Vec<_> = aabb.corners().map(|p| transform.apply_point(p)).to_vec();
+    let mut margin: f32 = 0.;
+    for stretch in key_times.windows(2) {
+        let (t0, t1) = (stretch[0], stretch[1]);
+        let step = (t1 - t0) / STEPS as f32;
+        for i in 1..=STEPS {
+            let transform = animation.at(t0 + step * i as f32);
+            corners.extend(aabb.corners().map(|p| transform.apply_point(p)));
+        }
+        margin = margin.max(animation.max_speed(t0, t1, radius) * step / 2.);
+    }
+    let bounds = Aabb::from_points(&corners);
+    Aabb::from(
+        bounds.min - Vec::from([margin; 3]),
+        bounds.max + Vec::from([margin; 3]),
+    )
+}
+
+/** Keyframed transforms. Between keyframes the translation and scale are
+ * interpolated linearly and the rotation spherically; outside them it holds still.
+ */
+#[derive(Debug, Clone)]
+pub struct Motion {
+    keyframes: std::vec::Vec<Keyframe>,
+}
+
+impl Motion {
+    /** Fails unless there is a keyframe and every transform can be inverted. */
+    pub fn keyframed(keyframes: std::vec::Vec<(f32, Transform)>) -> Result<Motion, Error> {
+        ensure(!keyframes.is_empty(), "motion", "it has no keyframes")?;
+        for (time, transform) in &keyframes {
+            ensure(
+                transform.is_invertible(),
+                "motion",
+                format!("the transform at {time} scales by 0 and cannot be inverted"),
+            )?;
+        }
+        let mut keyframes: std::vec::Vec<Keyframe> = keyframes
+            .iter()
+            .map(|(time, transform)| Keyframe::new(*time, transform))
+            .collect();
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Ok(Motion { keyframes })
+    }
+
+    pub fn linear(time0: f32, from: Transform, time1: f32, to: Transform) -> Result<Motion, Error> {
+        Motion::keyframed(vec![(time0, from), (time1, to)])
+    }
+}
+
+impl Animation for Motion {
+    fn at(&self, time: f32) -> Transform {
+        let index = self.keyframes.partition_point(|key| key.time <= time);
+        if index == 0 {
+            return self.keyframes[0].lerp(&self.keyframes[0], 0.);
+        }
+        if index == self.keyframes.len() {
+            let last = &self.keyframes[index - 1];
+            return last.lerp(last, 0.);
+        }
+        let (a, b) = (&self.keyframes[index - 1], &self.keyframes[index]);
+        a.lerp(b, (time - a.time) / (b.time - a.time))
+    }
+
+    fn key_times(&self) -> std::vec::Vec<f32> {
+        self.keyframes.iter().map(|key| key.time).collect()
+    }
+
+    /* A point x moves as p = t + R S x, with t and S linear in time; its speed is at
+     * most |t'| + w |S x| + |S' x| for the angular speed w of R.
+     */
+    fn max_speed(&self, t0: f32, t1: f32, radius: f32) -> f32 {
+        self.keyframes
+            .windows(2)
+            .filter(|pair| pair[0].time < t1 && t0 < pair[1].time)
+            .map(|pair| {
+                let (a, b) = (&pair[0], &pair[1]);
+                let translate = (b.translate - a.translate).len();
+                let scale =
+                    frobenius(|i, j| a.scale.at(i, j)).max(frobenius(|i, j| b.scale.at(i, j)));
+                let stretch = frobenius(|i, j| b.scale.at(i, j) - a.scale.at(i, j));
+                let turn = a.rotate.max_turn(&b.rotate);
+                (translate + (turn * scale + stretch) * radius) / (b.time - a.time)
+            })
+            .fold(0., f32::max)
+    }
+}
+
+/* The Frobenius norm of a 3x3 matrix given by its entries, a bound on how much it
+ * stretches vectors.
+ */
+fn frobenius(m: impl Fn(usize, usize) -> f32) -> f32 {
+    (0..3)
+        .flat_map(|i| (0..3).map(move |j| (i, j)))
+        .map(|(i, j)| m(i, j) * m(i, j))
+        .sum::<f32>()
+        .sqrt()
+}
+
+/** A transform driven by curves: scale, then rotation by Euler angles in degrees
+ * around x, y and z in that order, then translation.
+ */
+#[derive(Debug, Clone)]
+pub struct TransformCurves {
+    pub translate: Curve<Vec>,
+    pub rotate: Curve<Vec>,
+    pub scale: Curve<Vec>,
+}
+
+impl TransformCurves {
+    pub fn new() -> TransformCurves {
+        TransformCurves::default()
+    }
+}
+
+impl Default for TransformCurves {
+    fn default() -> Self {
+        TransformCurves {
+            translate: Curve::constant(Vec::new()),
+            rotate: Curve::constant(Vec::new()),
+            scale: Curve::constant(Vec::from([1., 1., 1.])),
+        }
+    }
+}
+
+impl Animation for TransformCurves {
+    fn at(&self, time: f32) -> Transform {
+        let rotate = self.rotate.at(time);
+        Transform::scale(self.scale.at(time))
+            .then(&Transform::rotate(Vec::from([1., 0., 0.]), rotate.x()))
+            .then(&Transform::rotate(Vec::from([0., 1., 0.]), rotate.y()))
+            .then(&Transform::rotate(Vec::from([0., 0., 1.]), rotate.z()))
+            .then(&Transform::translate(self.translate.at(time)))
+    }
+
+    fn key_times(&self) -> std::vec::Vec<f32> {
+        let mut times: std::vec::Vec<f32> = [&self.translate, &self.rotate, &self.scale]
+            .iter()
+            .flat_map(|curve| curve.keys().iter().map(|key| key.time))
+            .collect();
+        times.sort_by(f32::total_cmp);
+        times.dedup();
+        times
+    }
+
+    /* With Euler angles a, b, c the rotation turns at most as fast as |a'| + |b'| + |c'|,
+     * which is at most sqrt(3) times the speed of the rotate curve.
+     */
+    fn max_speed(&self, t0: f32, t1: f32, radius: f32) -> f32 {
+        let (_, translate) = curve_bounds(&self.translate, t0, t1);
+        let (_, turn) = curve_bounds(&self.rotate, t0, t1);
+        let (scale, stretch) = curve_bounds(&self.scale, t0, t1);
+        translate + (f32::sqrt(3.) * turn.to_radians() * scale + stretch) * radius
+    }
+}
+
+/* Bounds on the length of a vector curve and of its derivative over [t0, t1], from
+ * the control values of the segments overlapping it.
+ */
+fn curve_bounds(curve: &Curve<Vec>, t0: f32, t1: f32) -> (f32, f32) {
+    let keys = curve.keys();
+    let mut value = curve.at(t0).len().max(curve.at(t1).len());
+    let mut speed: f32 = 0.;
+    for i in 0..keys.len() - 1 {
+        let (a, b) = (keys[i].time, keys[i + 1].time);
+        if b <= t0 || t1 <= a || b <= a {
+            continue;
+        }
+        let p = curve.control_values(i);
+        for pair in p.windows(2) {
+            value = value.max(pair[0].len()).max(pair[1].len());
+            speed = speed.max(3. * (pair[1] - pair[0]).len() / (b - a));
+        }
+    }
+    (value, speed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::point::Point;
+
+    fn assert_near(a: Point, b: Point) {
+        assert!((a - b).len() < 1e-4, "{a} != {b}");
+    }
+
+    #[test]
+    fn test_linear() {
+        let motion = Motion::linear(
+            0.,
+            Transform::new(),
+            1.,
+            Transform::translate(Vec::from([2., 0., 0.])),
+        )
+        .unwrap();
+        let p = Point::from([0., 1., 0.]);
+        assert_near(motion.at(0.5).apply_point(p), Point::from([1., 1., 0.]));
+        assert_near(motion.at(-1.).apply_point(p), p);
+        assert_near(motion.at(2.).apply_point(p), Point::from([2., 1., 0.]));
+    }
+
+    #[test]
+    fn test_keyframed_rotation() {
+        let axis = Vec::from([0., 0., 1.]);
+        let scale = Transform::scale(Vec::from([2., 2., 2.]));
+        let motion = Motion::keyframed(vec![
+            (0., scale),
+            (1., scale.then(&Transform::rotate(axis, 90.))),
+            (2., scale.then(&Transform::rotate(axis, 180.))),
+        ])
+        .unwrap();
+        let p = Point::from([1., 0., 0.]);
+        let half = f32::sqrt(2.);
+        assert_near(motion.at(0.5).apply_point(p), Point::from([half, half, 0.]));
+        assert_near(motion.at(1.).apply_point(p), Point::from([0., 2., 0.]));
+        assert_near(
+            motion.at(1.5).apply_point(p),
+            Point::from([-half, half, 0.]),
+        );
+
+        let bounds = motion_bounds(&motion, &Aabb::from(p, p));
+        assert!(bounds.contains(Point::from([half, half, 0.])));
+        assert!(bounds.contains(Point::from([-2., 0., 0.])));
+
+        assert!(Motion::keyframed(vec![]).is_err());
+        let flat = Transform::scale(Vec::from([1., 0., 1.]));
+        assert!(Motion::linear(0., scale, 1., flat).is_err());
+    }
+
+    /* Checks that the bounds hold the box at many times between the samples. */
+    fn assert_covers(animation: &dyn Animation, aabb: &Aabb, t0: f32, t1: f32) {
+        let bounds = motion_bounds(animation, aabb);
+        for i in 0..=10_000 {
+            let transform = animation.at(t0 + (t1 - t0) * i as f32 / 10_000.);
+            for p in aabb.corners() {
+                let p = transform.apply_point(p);
+                assert!(bounds.contains(p), "{p} escapes the motion bounds");
+            }
+        }
+    }
+
+    #[test]
+    fn test_motion_bounds() {
+        let axis = Vec::from([0., 1., 1.]);
+        let aabb = Aabb::from(Point::from([2., 0., 0.]), Point::from([3., 1., 0.5]));
+        let motion = Motion::keyframed(vec![
+            (0., Transform::new()),
+            (1., Transform::rotate(axis, 170.)),
+            (
+                1.5,
+                Transform::rotate(axis, 10.).then(&Transform::scale(Vec::from([2., 1., 1.]))),
+            ),
+        ])
+        .unwrap();
+        assert_covers(&motion, &aabb, 0., 1.5);
+
+        use crate::curve::Key;
+        let curves = TransformCurves {
+            rotate: Curve::new(vec![
+                Key::tcb(0., Vec::new(), 0., 0., 0.),
+                Key::tcb(1., Vec::from([0., 90., 270.]), 0., 0., 0.),
+                Key::linear(2., Vec::from([30., 0., 0.])),
+            ])
+            .unwrap(),
+            translate: Curve::new(vec![
+                Key::bezier(
+                    0.,
+                    Vec::new(),
+                    Vec::from([5., 0., 0.]),
+                    Vec::from([-5., 0., 0.]),
+                ),
+                Key::linear(2., Vec::from([0., 0., 1.])),
+            ])
+            .unwrap(),
+            ..TransformCurves::new()
+        };
+        assert_covers(&curves, &aabb, 0., 2.);
+    }
+
+    #[test]
+    fn test_curves() {
+        use crate::curve::Key;
+
+        let curves = TransformCurves {
+            rotate: Curve::new(vec![
+                Key::linear(0., Vec::new()),
+                Key::linear(1., Vec::from([0., 0., 90.])),
+            ])
+            .unwrap(),
+            ..TransformCurves::new()
+        };
+        let p = curves.at(0.5).apply_point(Point::from([1., 0., 0.]));
+        let half = f32::sqrt(0.5);
+        assert_near(p, Point::from([half, half, 0.]));
+        assert_eq!(curves.key_times(), vec![0., 1.]);
+    }
+}
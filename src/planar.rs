@@ -0,0 +1,360 @@
+use std::{f32::consts::PI, sync::Arc};
+
+use crate::{
+    aabb::Aabb,
+    error::{ensure, Error},
+    material::Material,
+    point::Point,
+    ray::{HitRecord, Ray},
+    rng,
+    shape::{Shape, Surface},
+    vec::Vec,
+};
+
+/* Intersects `ray` with the plane through `origin` with unit normal `n`. */
+fn hit_plane(origin: Point, n: Vec, ray: Ray, t_min: f32, t_max: f32) -> Option<f32> {
+    let denom = n * ray.direct;
+    if denom.abs() < 1e-8 {
+        return None;
+    }
+    let t = (n * (origin - ray.origin)) / denom;
+    if t < t_min || t > t_max {
+        None
+    } else {
+        Some(t)
+    }
+}
+
+/** An infinite plane; u and v are the coordinates along an arbitrary tangent frame. */
+pub struct Plane {
+    origin: Point,
+    n: Vec,
+    material: Arc<dyn Material>,
+}
+
+impl Plane {
+    pub fn new(origin: Point, n: Vec, material: Arc<dyn Material>) -> Result<Plane, Error> {
+        let object = format!("plane through {origin}");
+        ensure(origin.is_finite(), &object, "origin is not finite")?;
+        ensure(
+            n.is_finite() && !n.near_zero(),
+            &object,
+            format!("normal is {n}"),
+        )?;
+        Ok(Plane {
+            origin,
+            n: n.to_unit(),
+            material,
+        })
+    }
+}
+
+impl Shape for Plane {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let t = hit_plane(self.origin, self.n, ray, t_min, t_max)?;
+        let (tangent, bitangent) = self.n.orthonormal_basis();
+        let delta = ray.at(t) - self.origin;
+        Some(
+            HitRecord::new(ray, t, self.n, self.material.as_ref())
+                .with_uv(delta * tangent, delta * bitangent),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+/** The parallelogram origin + a * u + b * v for a, b in [0, 1], facing u x v. */
+pub struct Quad {
+    origin: Point,
+    u: Vec,
+    v: Vec,
+    n: Vec,
+    w: Vec,
+    material: Arc<dyn Material>,
+}
+
+impl Quad {
+    pub fn new(origin: Point, u: Vec, v: Vec, material: Arc<dyn Material>) -> Result<Quad, Error> {
+        let object = format!("quad at {origin}");
+        ensure(
+            origin.is_finite() && u.is_finite() && v.is_finite(),
+            &object,
+            "corners are not finite",
+        )?;
+        let n = u.cross(&v);
+        ensure(
+            !n.near_zero(),
+            &object,
+            format!("edges {u} and {v} are parallel"),
+        )?;
+        Ok(Quad {
+            origin,
+            u,
+            v,
+            n: n.to_unit(),
+            w: n / (n * n),
+            material,
+        })
+    }
+}
+
+impl Shape for Quad {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let t = hit_plane(self.origin, self.n, ray, t_min, t_max)?;
+        let delta = ray.at(t) - self.origin;
+        let alpha = self.w * delta.cross(&self.v);
+        let beta = self.w * self.u.cross(&delta);
+        if !(0. ..=1.).contains(&alpha) || !(0. ..=1.).contains(&beta) {
+            return None;
+        }
+        Some(HitRecord::new(ray, t, self.n, self.material.as_ref()).with_uv(alpha, beta))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let corners = [
+            self.origin,
+            self.origin + self.u,
+            self.origin + self.v,
+            self.origin + self.u + self.v,
+        ];
+        Some(Aabb::from_points(&corners).pad())
+    }
+}
+
+impl Surface for Quad {
+    fn area(&self) -> f32 {
+        self.u.cross(&self.v).len()
+    }
+
+    fn sample(&self) -> (Point, Vec) {
+        let p = self.origin + rng::random() * self.u + rng::random() * self.v;
+        (p, self.n)
+    }
+}
+
+/** A disk around `center` facing `n`. As in pbrt, u is the angle around the normal
+ * and v runs from the rim (0) to the center (1).
+ */
+pub struct Disk {
+    center: Point,
+    n: Vec,
+    radius: f32,
+    material: Arc<dyn Material>,
+}
+
+impl Disk {
+    pub fn new(
+        center: Point,
+        n: Vec,
+        radius: f32,
+        material: Arc<dyn Material>,
+    ) -> Result<Disk, Error> {
+        let object = format!("disk at {center}");
+        ensure(center.is_finite(), &object, "center is not finite")?;
+        ensure(
+            n.is_finite() && !n.near_zero(),
+            &object,
+            format!("normal is {n}"),
+        )?;
+        ensure(
+            radius.is_finite() && radius > 0.,
+            &object,
+            format!("radius is {radius}"),
+        )?;
+        Ok(Disk {
+            center,
+            n: n.to_unit(),
+            radius,
+            material,
+        })
+    }
+}
+
+impl Shape for Disk {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let t = hit_plane(self.center, self.n, ray, t_min, t_max)?;
+        let delta = ray.at(t) - self.center;
+        let r = delta.len();
+        if r > self.radius {
+            return None;
+        }
+        let (tangent, bitangent) = self.n.orthonormal_basis();
+        let phi = f32::atan2(delta * bitangent, delta * tangent);
+        let phi = if phi < 0. { phi + 2. * PI } else { phi };
+        let (u, v) = (phi / (2. * PI), 1. - r / self.radius);
+        Some(HitRecord::new(ray, t, self.n, self.material.as_ref()).with_uv(u, v))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let extent = Vec::from(
+            [0, 1, 2]
+                .map(|i| self.radius * f32::sqrt(f32::max(0., 1. - self.n.at(i) * self.n.at(i)))),
+        );
+        Some(Aabb::from(self.center - extent, self.center + extent).pad())
+    }
+}
+
+impl Surface for Disk {
+    fn area(&self) -> f32 {
+        PI * self.radius * self.radius
+    }
+
+    fn sample(&self) -> (Point, Vec) {
+        let (tangent, bitangent) = self.n.orthonormal_basis();
+        let r = self.radius * rng::random().sqrt();
+        let phi = 2. * PI * rng::random();
+        (
+            self.center + r * phi.cos() * tangent + r * phi.sin() * bitangent,
+            self.n,
+        )
+    }
+}
+
+/** A box made of six quads with outward normals. It is axis-aligned when built with
+ * `new`, or spans three arbitrary edges from a corner when built with `oriented`.
+ */
+pub struct Cuboid {
+    faces: [Quad; 6],
+}
+
+impl Cuboid {
+    pub fn new(a: Point, b: Point, material: Arc<dyn Material>) -> Result<Cuboid, Error> {
+        let Aabb { min, max } = Aabb::from_points(&[a, b]);
+        let delta = max - min;
+        Cuboid::oriented(
+            min,
+            [
+                Vec::from([delta.x(), 0., 0.]),
+                Vec::from([0., delta.y(), 0.]),
+                Vec::from([0., 0., delta.z()]),
+            ],
+            material,
+        )
+    }
+
+    /** The edges must form a right-handed frame for the normals to point outwards. */
+    pub fn oriented(
+        corner: Point,
+        edges: [Vec; 3],
+        material: Arc<dyn Material>,
+    ) -> Result<Cuboid, Error> {
+        let [dx, dy, dz] = edges;
+        let opposite = corner + dx + dy + dz;
+        ensure(
+            (dx.cross(&dy) * dz).abs() > 1e-12,
+            &format!("box at {corner}"),
+            format!("edges {dx}, {dy} and {dz} span no volume"),
+        )?;
+        Ok(Cuboid {
+            faces: [
+                Quad::new(corner, dz, dy, material.clone())?,
+                Quad::new(corner, dx, dz, material.clone())?,
+                Quad::new(corner, dy, dx, material.clone())?,
+                Quad::new(opposite, -dy, -dz, material.clone())?,
+                Quad::new(opposite, -dz, -dx, material.clone())?,
+                Quad::new(opposite, -dx, -dy, material)?,
+            ],
+        })
+    }
+}
+
+impl Shape for Cuboid {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let mut hit_point: Option<HitRecord> = None;
+        let mut t_max = t_max;
+        for face in &self.faces {
+            if let Some(hp) = face.hit(ray, t_min, t_max) {
+                t_max = hp.t;
+                hit_point = Some(hp);
+            }
+        }
+        hit_point
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.faces
+            .iter()
+            .filter_map(|face| face.bounding_box())
+            .reduce(|a, b| a.union(&b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new_const([0.5, 0.5, 0.5]))
+    }
+
+    #[test]
+    fn test_quad() {
+        let quad = Quad::new(
+            Point::from([0., 0., -1.]),
+            Vec::from([2., 0., 0.]),
+            Vec::from([0., 4., 0.]),
+            material(),
+        )
+        .unwrap();
+        let ray = Ray::from(Point::from([1., 1., 0.]), Vec::from([0., 0., -1.]));
+        let record = quad.hit(ray, 0.001, f32::MAX).unwrap();
+        assert_eq!(record.t, 1.);
+        assert!(record.is_front);
+        assert_eq!((record.u, record.v), (0.5, 0.25));
+        assert_eq!(quad.area(), 8.);
+
+        let ray = Ray::from(Point::from([3., 1., 0.]), Vec::from([0., 0., -1.]));
+        assert!(quad.hit(ray, 0.001, f32::MAX).is_none());
+    }
+
+    #[test]
+    fn test_cuboid() {
+        let cuboid = Cuboid::new(
+            Point::from([1., 1., 1.]),
+            Point::from([-1., -1., -1.]),
+            material(),
+        )
+        .unwrap();
+        for direct in [[1., 0., 0.], [0., -1., 0.], [0., 0., 1.]] {
+            let direct = Vec::from(direct);
+            let ray = Ray::from(Point::new() - 3. * direct, direct);
+            let record = cuboid.hit(ray, 0.001, f32::MAX).unwrap();
+            assert_eq!(record.t, 2.);
+            assert!(record.is_front);
+            assert_eq!(record.n * direct, -1.);
+
+            let ray = Ray::from(Point::new(), direct);
+            let record = cuboid.hit(ray, 0.001, f32::MAX).unwrap();
+            assert_eq!(record.t, 1.);
+            assert!(!record.is_front);
+        }
+        let aabb = cuboid.bounding_box().unwrap();
+        assert!(aabb.contains(Point::from([0.99, -0.99, 0.99])));
+    }
+
+    #[test]
+    fn test_disk() {
+        let disk = Disk::new(Point::new(), Vec::from([0., 1., 0.]), 2., material()).unwrap();
+        let ray = Ray::from(Point::from([1., 1., 0.]), Vec::from([0., -1., 0.]));
+        let record = disk.hit(ray, 0.001, f32::MAX).unwrap();
+        assert_eq!(record.t, 1.);
+        assert_eq!(record.v, 0.5);
+        let ray = Ray::from(Point::from([2.5, 1., 0.]), Vec::from([0., -1., 0.]));
+        assert!(disk.hit(ray, 0.001, f32::MAX).is_none());
+        let (p, n) = disk.sample();
+        assert!((p - Point::new()).len() <= 2. && p.y() == 0. && n.y() == 1.);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let (x, y) = (Vec::from([1., 0., 0.]), Vec::from([0., 1., 0.]));
+        assert!(Quad::new(Point::new(), x, 2. * x, material()).is_err());
+        assert!(Disk::new(Point::new(), y, -1., material()).is_err());
+        assert!(Plane::new(Point::new(), Vec::new(), material()).is_err());
+        let flat = Cuboid::new(Point::new(), Point::from([1., 0., 1.]), material());
+        assert!(flat.is_err());
+    }
+}
@@ -48,6 +48,16 @@ impl Point {
     pub fn at(&self, index: usize) -> f32 {
         self.coeff[index]
     }
+
+    pub fn is_finite(&self) -> bool {
+        self.coeff.iter().all(|x| x.is_finite())
+    }
+}
+
+impl Default for Point {
+    fn default() -> Self {
+        Point::new()
+    }
 }
 
 impl From<[f32; 3]> for Point {
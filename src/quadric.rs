@@ -0,0 +1,774 @@
+use std::{f32::consts::PI, sync::Arc};
+
+use crate::{
+    aabb::Aabb,
+    error::{ensure, Error},
+    material::Material,
+    point::Point,
+    ray::{HitRecord, Ray},
+    shape::Shape,
+    vec::Vec,
+};
+
+/* Quadrics and the torus in the style of pbrt: they are defined in object space around
+ * the z axis, may be swept partially up to `phi_max` (in degrees) and are placed in
+ * the world with `Transformed`.
+ */
+
+/* Outward normal and uv of a surface point, or None if it is outside the swept part. */
+type SurfacePoint = Option<(Vec, f32, f32)>;
+
+fn phi(p: Point) -> f32 {
+    let phi = f32::atan2(p.y(), p.x());
+    if phi < 0. {
+        phi + 2. * PI
+    } else {
+        phi
+    }
+}
+
+/* Checks the parameters every quadric shares. */
+fn check_radius_and_phi(object: &str, radius: f32, phi_max: f32) -> Result<(), Error> {
+    ensure(
+        radius.is_finite() && radius > 0.,
+        object,
+        format!("radius is {radius}"),
+    )?;
+    ensure(phi_max > 0., object, format!("phi_max is {phi_max}"))
+}
+
+fn check_range(object: &str, z_min: f32, z_max: f32) -> Result<(), Error> {
+    ensure(
+        z_min.is_finite() && z_max.is_finite() && z_min != z_max,
+        object,
+        format!("z range is [{z_min}, {z_max}]"),
+    )
+}
+
+/* Whether a sweep of phi_max radians goes all the way around the z axis. */
+fn is_full_sweep(phi_max: f32) -> bool {
+    phi_max >= 360_f32.to_radians()
+}
+
+/* Real roots of a x^2 + b x + c in ascending order, avoiding cancellation. */
+fn solve_quadratic(a: f64, b: f64, c: f64) -> std::vec::Vec<f64> {
+    if a.abs() < 1e-12 {
+        return if b.abs() < 1e-12 {
+            vec![]
+        } else {
+            vec![-c / b]
+        };
+    }
+    let discriminant = b * b - 4. * a * c;
+    if discriminant < 0. {
+        return vec![];
+    }
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    let (t0, t1) = if q == 0. { (0., 0.) } else { (q / a, c / q) };
+    if t0 < t1 {
+        vec![t0, t1]
+    } else {
+        vec![t1, t0]
+    }
+}
+
+fn eval_polynomial(coeffs: &[f64], x: f64) -> f64 {
+    coeffs.iter().fold(0., |acc, c| acc * x + c)
+}
+
+/** Real roots in [lo, hi] of the polynomial with coefficients from the highest degree down.
+ * Roots are isolated between the critical points, found recursively from the derivative,
+ * and refined by bisection, which stays robust where closed-form quartic formulas lose
+ * all precision.
+ */
+pub fn solve_polynomial(coeffs: &[f64], lo: f64, hi: f64) -> std::vec::Vec<f64> {
+    let coeffs = match coeffs.iter().position(|c| c.abs() > 1e-12) {
+        Some(first) => &coeffs[first..],
+        None => return vec![],
+    };
+    let degree = coeffs.len() - 1;
+    if degree == 0 {
+        return vec![];
+    }
+    if degree == 1 {
+        let root = -coeffs[1] / coeffs[0];
+        return if (lo..=hi).contains(&root) {
+            vec![root]
+        } else {
+            vec![]
+        };
+    }
+    let derivative: std::vec::Vec<f64> = coeffs[..degree]
+        .iter()
+        .enumerate()
+        .map(|(i, c)| c * (degree - i) as f64)
+        .collect();
+    let mut bounds = vec![lo];
+    bounds.extend(solve_polynomial(&derivative, lo, hi));
+    bounds.push(hi);
+
+    let mut roots = vec![];
+    for pair in bounds.windows(2) {
+        let (mut a, mut b) = (pair[0], pair[1]);
+        let (fa, fb) = (eval_polynomial(coeffs, a), eval_polynomial(coeffs, b));
+        if fa == 0. {
+            roots.push(a);
+            continue;
+        }
+        if fa.signum() == fb.signum() {
+            continue;
+        }
+        for _ in 0..100 {
+            let mid = 0.5 * (a + b);
+            if mid == a || mid == b {
+                break;
+            }
+            if eval_polynomial(coeffs, mid).signum() == fa.signum() {
+                a = mid;
+            } else {
+                b = mid;
+            }
+        }
+        roots.push(0.5 * (a + b));
+    }
+    if eval_polynomial(coeffs, hi) == 0. {
+        roots.push(hi);
+    }
+    roots.dedup();
+    roots
+}
+
+/* Picks the closest root in [t_min, t_max] that lands on the swept part of the surface. */
+fn closest_hit(
+    roots: impl IntoIterator<Item = f64>,
+    ray: Ray,
+    t_min: f32,
+    t_max: f32,
+    surface: impl Fn(Point) -> SurfacePoint,
+) -> Option<(f32, Vec, f32, f32)> {
+    roots.into_iter().find_map(|t| {
+        let t = t as f32;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        let (n, u, v) = surface(ray.at(t))?;
+        Some((t, n, u, v))
+    })
+}
+
+/* Hit on the cap at height z, a disk of the given radius facing `facing` along z. */
+fn hit_cap(
+    ray: Ray,
+    t_min: f32,
+    t_max: f32,
+    z: f32,
+    radius: f32,
+    facing: f32,
+    phi_max: f32,
+) -> Option<(f32, Vec, f32, f32)> {
+    if ray.direct.z() == 0. {
+        return None;
+    }
+    let t = (z - ray.origin.z()) / ray.direct.z();
+    if t < t_min || t > t_max {
+        return None;
+    }
+    let p = ray.at(t);
+    let r = f32::hypot(p.x(), p.y());
+    if r > radius || phi(p) > phi_max {
+        return None;
+    }
+    Some((
+        t,
+        Vec::from([0., 0., facing]),
+        phi(p) / phi_max,
+        1. - r / radius,
+    ))
+}
+
+fn to_record<'a>(
+    ray: Ray,
+    hit: Option<(f32, Vec, f32, f32)>,
+    material: &'a dyn Material,
+) -> Option<HitRecord<'a>> {
+    let (t, n, u, v) = hit?;
+    Some(HitRecord::new(ray, t, n.to_unit(), material).with_uv(u, v))
+}
+
+fn nearest(
+    a: Option<(f32, Vec, f32, f32)>,
+    b: Option<(f32, Vec, f32, f32)>,
+) -> Option<(f32, Vec, f32, f32)> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(if a.0 <= b.0 { a } else { b }),
+        (a, b) => a.or(b),
+    }
+}
+
+fn ray_f64(ray: Ray) -> ([f64; 3], [f64; 3]) {
+    (
+        [0, 1, 2].map(|i| ray.origin.at(i) as f64),
+        [0, 1, 2].map(|i| ray.direct.at(i) as f64),
+    )
+}
+
+pub struct Cylinder {
+    radius: f32,
+    z_min: f32,
+    z_max: f32,
+    phi_max: f32,
+    capped: bool,
+    material: Arc<dyn Material>,
+}
+
+impl Cylinder {
+    pub fn new(
+        radius: f32,
+        z_min: f32,
+        z_max: f32,
+        phi_max: f32,
+        capped: bool,
+        material: Arc<dyn Material>,
+    ) -> Result<Cylinder, Error> {
+        check_radius_and_phi("cylinder", radius, phi_max)?;
+        check_range("cylinder", z_min, z_max)?;
+        Ok(Cylinder {
+            radius,
+            z_min: z_min.min(z_max),
+            z_max: z_min.max(z_max),
+            phi_max: phi_max.clamp(0., 360.).to_radians(),
+            capped,
+            material,
+        })
+    }
+
+    /** Whether the cylinder bounds a volume: capped and swept all the way around. */
+    pub fn is_closed(&self) -> bool {
+        self.capped && is_full_sweep(self.phi_max)
+    }
+
+    fn surface(&self, p: Point) -> SurfacePoint {
+        if p.z() < self.z_min || p.z() > self.z_max || phi(p) > self.phi_max {
+            return None;
+        }
+        let n = Vec::from([p.x(), p.y(), 0.]);
+        Some((
+            n,
+            phi(p) / self.phi_max,
+            (p.z() - self.z_min) / (self.z_max - self.z_min),
+        ))
+    }
+}
+
+impl Shape for Cylinder {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let ([ox, oy, _], [dx, dy, _]) = ray_f64(ray);
+        let r = self.radius as f64;
+        let a = dx * dx + dy * dy;
+        let b = 2. * (dx * ox + dy * oy);
+        let c = ox * ox + oy * oy - r * r;
+        let mut hit = closest_hit(solve_quadratic(a, b, c), ray, t_min, t_max, |p| {
+            self.surface(p)
+        });
+        if self.capped {
+            let t_max = hit.map_or(t_max, |h| h.0);
+            hit = nearest(
+                hit,
+                hit_cap(
+                    ray,
+                    t_min,
+                    t_max,
+                    self.z_min,
+                    self.radius,
+                    -1.,
+                    self.phi_max,
+                ),
+            );
+            hit = nearest(
+                hit,
+                hit_cap(ray, t_min, t_max, self.z_max, self.radius, 1., self.phi_max),
+            );
+        }
+        to_record(ray, hit, self.material.as_ref())
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::from(
+            Point::from([-self.radius, -self.radius, self.z_min]),
+            Point::from([self.radius, self.radius, self.z_max]),
+        ))
+    }
+}
+
+/** A cone with its base of `radius` at z = 0 and its apex at z = height. */
+pub struct Cone {
+    radius: f32,
+    height: f32,
+    phi_max: f32,
+    capped: bool,
+    material: Arc<dyn Material>,
+}
+
+impl Cone {
+    pub fn new(
+        radius: f32,
+        height: f32,
+        phi_max: f32,
+        capped: bool,
+        material: Arc<dyn Material>,
+    ) -> Result<Cone, Error> {
+        check_radius_and_phi("cone", radius, phi_max)?;
+        ensure(
+            height.is_finite() && height > 0.,
+            "cone",
+            format!("height is {height}"),
+        )?;
+        Ok(Cone {
+            radius,
+            height,
+            phi_max: phi_max.clamp(0., 360.).to_radians(),
+            capped,
+            material,
+        })
+    }
+
+    /** Whether the cone bounds a volume: capped and swept all the way around. */
+    pub fn is_closed(&self) -> bool {
+        self.capped && is_full_sweep(self.phi_max)
+    }
+
+    fn surface(&self, p: Point) -> SurfacePoint {
+        if p.z() < 0. || p.z() > self.height || phi(p) > self.phi_max {
+            return None;
+        }
+        let k = (self.radius / self.height).powi(2);
+        let n = Vec::from([p.x(), p.y(), -k * (p.z() - self.height)]);
+        Some((n, phi(p) / self.phi_max, p.z() / self.height))
+    }
+}
+
+impl Shape for Cone {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let ([ox, oy, oz], [dx, dy, dz]) = ray_f64(ray);
+        let k = (self.radius as f64 / self.height as f64).powi(2);
+        let h = self.height as f64;
+        let a = dx * dx + dy * dy - k * dz * dz;
+        let b = 2. * (dx * ox + dy * oy - k * dz * (oz - h));
+        let c = ox * ox + oy * oy - k * (oz - h) * (oz - h);
+        let mut hit = closest_hit(solve_quadratic(a, b, c), ray, t_min, t_max, |p| {
+            self.surface(p)
+        });
+        if self.capped {
+            let t_max = hit.map_or(t_max, |h| h.0);
+            hit = nearest(
+                hit,
+                hit_cap(ray, t_min, t_max, 0., self.radius, -1., self.phi_max),
+            );
+        }
+        to_record(ray, hit, self.material.as_ref())
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::from(
+            Point::from([-self.radius, -self.radius, 0.]),
+            Point::from([self.radius, self.radius, self.height]),
+        ))
+    }
+}
+
+/** The paraboloid z = z_max * (x^2 + y^2) / radius^2, clipped to [z_min, z_max]. */
+pub struct Paraboloid {
+    radius: f32,
+    z_min: f32,
+    z_max: f32,
+    phi_max: f32,
+    material: Arc<dyn Material>,
+}
+
+impl Paraboloid {
+    pub fn new(
+        radius: f32,
+        z_min: f32,
+        z_max: f32,
+        phi_max: f32,
+        material: Arc<dyn Material>,
+    ) -> Result<Paraboloid, Error> {
+        check_radius_and_phi("paraboloid", radius, phi_max)?;
+        check_range("paraboloid", z_min, z_max)?;
+        Ok(Paraboloid {
+            radius,
+            z_min: z_min.min(z_max),
+            z_max: z_min.max(z_max),
+            phi_max: phi_max.clamp(0., 360.).to_radians(),
+            material,
+        })
+    }
+
+    fn surface(&self, p: Point) -> SurfacePoint {
+        if p.z() < self.z_min || p.z() > self.z_max || phi(p) > self.phi_max {
+            return None;
+        }
+        let k = self.z_max / (self.radius * self.radius);
+        let n = Vec::from([2. * k * p.x(), 2. * k * p.y(), -1.]);
+        Some((
+            n,
+            phi(p) / self.phi_max,
+            (p.z() - self.z_min) / (self.z_max - self.z_min),
+        ))
+    }
+}
+
+impl Shape for Paraboloid {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let ([ox, oy, oz], [dx, dy, dz]) = ray_f64(ray);
+        let k = self.z_max as f64 / (self.radius as f64 * self.radius as f64);
+        let a = k * (dx * dx + dy * dy);
+        let b = 2. * k * (dx * ox + dy * oy) - dz;
+        let c = k * (ox * ox + oy * oy) - oz;
+        let hit = closest_hit(solve_quadratic(a, b, c), ray, t_min, t_max, |p| {
+            self.surface(p)
+        });
+        to_record(ray, hit, self.material.as_ref())
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::from(
+            Point::from([-self.radius, -self.radius, self.z_min]),
+            Point::from([self.radius, self.radius, self.z_max]),
+        ))
+    }
+}
+
+/** The hyperboloid swept by rotating the segment p1-p2 around the z axis. */
+pub struct Hyperboloid {
+    p1: Point,
+    p2: Point,
+    r_max: f32,
+    ah: f64,
+    ch: f64,
+    phi_max: f32,
+    material: Arc<dyn Material>,
+}
+
+impl Hyperboloid {
+    pub fn new(
+        p1: Point,
+        p2: Point,
+        phi_max: f32,
+        material: Arc<dyn Material>,
+    ) -> Result<Hyperboloid, Error> {
+        let (p1, p2) = if p1.z() > p2.z() { (p2, p1) } else { (p1, p2) };
+        let r_max = f32::max(f32::hypot(p1.x(), p1.y()), f32::hypot(p2.x(), p2.y()));
+        check_radius_and_phi("hyperboloid", r_max, phi_max)?;
+        check_range("hyperboloid", p1.z(), p2.z())?;
+
+        /* Solve for the implicit form ah (x^2 + y^2) - ch z^2 = 1, moving along the
+         * line until a point with well-conditioned coefficients is found.
+         */
+        let [p1x, p1y, p1z] = [p1.x() as f64, p1.y() as f64, p1.z() as f64];
+        let [p2x, p2y, p2z] = [p2.x() as f64, p2.y() as f64, p2.z() as f64];
+        let (mut ah, mut ch);
+        let mut pp = [p1x, p1y, p1z];
+        let mut tries = 0;
+        loop {
+            tries += 1;
+            ensure(
+                tries <= 64,
+                "hyperboloid",
+                format!("no implicit form through {p1} and {p2}"),
+            )?;
+            pp = [0, 1, 2].map(|i| pp[i] + 2. * ([p2x, p2y, p2z][i] - [p1x, p1y, p1z][i]));
+            let xy1 = pp[0] * pp[0] + pp[1] * pp[1];
+            let xy2 = p2x * p2x + p2y * p2y;
+            ah = (1. / xy1 - (pp[2] * pp[2]) / (xy1 * p2z * p2z))
+                / (1. - (xy2 * pp[2] * pp[2]) / (xy1 * p2z * p2z));
+            ch = (ah * xy2 - 1.) / (p2z * p2z);
+            if ah.is_finite() && ch.is_finite() {
+                break;
+            }
+        }
+        Ok(Hyperboloid {
+            p1,
+            p2,
+            r_max,
+            ah,
+            ch,
+            phi_max: phi_max.clamp(0., 360.).to_radians(),
+            material,
+        })
+    }
+
+    fn surface(&self, p: Point) -> SurfacePoint {
+        if p.z() < self.p1.z() || p.z() > self.p2.z() {
+            return None;
+        }
+        let v = (p.z() - self.p1.z()) / (self.p2.z() - self.p1.z());
+        let pr = self.p1 * (1. - v) + (self.p2 * v - Point::new());
+        let phi = f32::atan2(
+            pr.x() * p.y() - p.x() * pr.y(),
+            p.x() * pr.x() + p.y() * pr.y(),
+        );
+        let phi = if phi < 0. { phi + 2. * PI } else { phi };
+        if phi > self.phi_max {
+            return None;
+        }
+        let (ah, ch) = (self.ah as f32, self.ch as f32);
+        Some((
+            Vec::from([ah * p.x(), ah * p.y(), -ch * p.z()]),
+            phi / self.phi_max,
+            v,
+        ))
+    }
+}
+
+impl Shape for Hyperboloid {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let ([ox, oy, oz], [dx, dy, dz]) = ray_f64(ray);
+        let (ah, ch) = (self.ah, self.ch);
+        let a = ah * (dx * dx + dy * dy) - ch * dz * dz;
+        let b = 2. * (ah * (dx * ox + dy * oy) - ch * dz * oz);
+        let c = ah * (ox * ox + oy * oy) - ch * oz * oz - 1.;
+        let hit = closest_hit(solve_quadratic(a, b, c), ray, t_min, t_max, |p| {
+            self.surface(p)
+        });
+        to_record(ray, hit, self.material.as_ref())
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::from(
+            Point::from([-self.r_max, -self.r_max, self.p1.z()]),
+            Point::from([self.r_max, self.r_max, self.p2.z()]),
+        ))
+    }
+}
+
+/** A torus around the z axis: a tube of `minor_radius` around a circle of `major_radius`. */
+pub struct Torus {
+    major_radius: f32,
+    minor_radius: f32,
+    phi_max: f32,
+    material: Arc<dyn Material>,
+}
+
+impl Torus {
+    pub fn new(
+        major_radius: f32,
+        minor_radius: f32,
+        phi_max: f32,
+        material: Arc<dyn Material>,
+    ) -> Result<Torus, Error> {
+        check_radius_and_phi("torus", minor_radius, phi_max)?;
+        ensure(
+            major_radius.is_finite() && major_radius > 0.,
+            "torus",
+            format!("major radius is {major_radius}"),
+        )?;
+        Ok(Torus {
+            major_radius,
+            minor_radius,
+            phi_max: phi_max.clamp(0., 360.).to_radians(),
+            material,
+        })
+    }
+
+    /** Whether the torus bounds a volume: swept all the way around. */
+    pub fn is_closed(&self) -> bool {
+        is_full_sweep(self.phi_max)
+    }
+
+    fn surface(&self, p: Point) -> SurfacePoint {
+        if phi(p) > self.phi_max {
+            return None;
+        }
+        let r = f32::hypot(p.x(), p.y());
+        let theta = f32::atan2(p.z(), r - self.major_radius);
+        let theta = if theta < 0. { theta + 2. * PI } else { theta };
+        let scale = 1. - self.major_radius / r;
+        let n = Vec::from([p.x() * scale, p.y() * scale, p.z()]);
+        Some((n, phi(p) / self.phi_max, theta / (2. * PI)))
+    }
+}
+
+impl Shape for Torus {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        /* Work with a unit direction for better conditioning of the quartic. */
+        let len = ray.direct.len();
+        let unit_ray = Ray::new(ray.origin, ray.direct / len, ray.time);
+        let ([ox, oy, oz], [dx, dy, dz]) = ray_f64(unit_ray);
+        let big_r2 = (self.major_radius as f64).powi(2);
+        let small_r2 = (self.minor_radius as f64).powi(2);
+
+        let od = ox * dx + oy * dy + oz * dz;
+        let e = ox * ox + oy * oy + oz * oz + big_r2 - small_r2;
+        let coeffs = [
+            1.,
+            4. * od,
+            4. * od * od + 2. * e - 4. * big_r2 * (dx * dx + dy * dy),
+            4. * od * e - 8. * big_r2 * (ox * dx + oy * dy),
+            e * e - 4. * big_r2 * (ox * ox + oy * oy),
+        ];
+
+        /* Roots beyond the bounding sphere around the origin cannot be on the torus. */
+        let reach =
+            (ox * ox + oy * oy + oz * oz).sqrt() + (self.major_radius + self.minor_radius) as f64;
+        let lo = (t_min as f64 * len as f64).max(-reach);
+        let hi = (t_max as f64 * len as f64).min(reach);
+        if lo > hi {
+            return None;
+        }
+        let roots = solve_polynomial(&coeffs, lo, hi)
+            .into_iter()
+            .map(|t| t / len as f64);
+        let hit = closest_hit(roots, ray, t_min, t_max, |p| self.surface(p));
+        to_record(ray, hit, self.material.as_ref())
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = self.major_radius + self.minor_radius;
+        Some(Aabb::from(
+            Point::from([-r, -r, -self.minor_radius]),
+            Point::from([r, r, self.minor_radius]),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new_const([0.5, 0.5, 0.5]))
+    }
+
+    /* Shoots rays at sampled surface points along the inward normal and checks
+     * the hit position, normal and uv against the parametrization.
+     */
+    fn check_samples(shape: &dyn Shape, param: impl Fn(f32, f32) -> (Point, Vec)) {
+        for i in 1..20 {
+            for j in 1..20 {
+                let (u, v) = (i as f32 / 20., j as f32 / 20.);
+                let (p, n) = param(u, v);
+                let n = n.to_unit();
+                let ray = Ray::from(p + 0.01 * n, -n);
+                let record = shape
+                    .hit(ray, 0.001, f32::MAX)
+                    .unwrap_or_else(|| panic!("missed at {u} {v}"));
+                assert!(
+                    (record.p - p).len() < 1e-3,
+                    "{} != {p} at {u} {v}",
+                    record.p
+                );
+                assert!(record.is_front);
+                assert!((record.n * n - 1.).abs() < 1e-3);
+                assert!((record.u - u).abs() < 1e-3 && (record.v - v).abs() < 1e-3);
+            }
+        }
+        let bounds = shape.bounding_box().unwrap();
+        for i in 0..50 {
+            let (p, _) = param(i as f32 / 49., (i * 7 % 50) as f32 / 49.);
+            assert!(bounds.pad().contains(p));
+        }
+    }
+
+    #[test]
+    fn test_polynomial() {
+        let roots = solve_polynomial(&[1., -10., 35., -50., 24.], -100., 100.);
+        assert_eq!(roots.len(), 4);
+        for (root, expected) in roots.iter().zip([1., 2., 3., 4.]) {
+            assert!((root - expected).abs() < 1e-9);
+        }
+        assert!(solve_polynomial(&[1., 0., 1.], -10., 10.).is_empty());
+        assert_eq!(solve_quadratic(1., -3., 2.), vec![1., 2.]);
+    }
+
+    #[test]
+    fn test_cylinder() {
+        let cylinder = Cylinder::new(2., -1., 3., 270., true, material()).unwrap();
+        check_samples(&cylinder, |u, v| {
+            let phi = u * 1.5 * PI;
+            (
+                Point::from([2. * phi.cos(), 2. * phi.sin(), -1. + 4. * v]),
+                Vec::from([phi.cos(), phi.sin(), 0.]),
+            )
+        });
+        let ray = Ray::from(Point::from([0.5, 0.5, 5.]), Vec::from([0., 0., -1.]));
+        assert_eq!(cylinder.hit(ray, 0.001, f32::MAX).unwrap().t, 2.);
+        let ray = Ray::from(Point::from([0.5, -0.5, 5.]), Vec::from([0., 0., -1.]));
+        assert!(cylinder.hit(ray, 0.001, f32::MAX).is_none());
+    }
+
+    #[test]
+    fn test_cone() {
+        let cone = Cone::new(1., 2., 360., true, material()).unwrap();
+        check_samples(&cone, |u, v| {
+            let phi = u * 2. * PI;
+            let r = 1. - v;
+            (
+                Point::from([r * phi.cos(), r * phi.sin(), 2. * v]),
+                Vec::from([phi.cos(), phi.sin(), 0.5]),
+            )
+        });
+        let ray = Ray::from(Point::from([0.2, 0.2, -1.]), Vec::from([0., 0., 1.]));
+        assert_eq!(cone.hit(ray, 0.001, f32::MAX).unwrap().t, 1.);
+    }
+
+    #[test]
+    fn test_paraboloid() {
+        let paraboloid = Paraboloid::new(1., 0.5, 2., 360., material()).unwrap();
+        check_samples(&paraboloid, |u, v| {
+            let phi = u * 2. * PI;
+            let z = 0.5 + 1.5 * v;
+            let r = (z / 2.).sqrt();
+            (
+                Point::from([r * phi.cos(), r * phi.sin(), z]),
+                Vec::from([4. * r * phi.cos(), 4. * r * phi.sin(), -1.]),
+            )
+        });
+    }
+
+    #[test]
+    fn test_hyperboloid() {
+        let p1 = Point::from([1., -1., -1.]);
+        let p2 = Point::from([1., 1., 1.]);
+        let hyperboloid = Hyperboloid::new(p1, p2, 360., material()).unwrap();
+        check_samples(&hyperboloid, |u, v| {
+            let phi = u * 2. * PI;
+            let pr = [1., -1. + 2. * v, -1. + 2. * v];
+            let (sin, cos) = phi.sin_cos();
+            let p = Point::from([pr[0] * cos - pr[1] * sin, pr[0] * sin + pr[1] * cos, pr[2]]);
+            // x^2 + y^2 - z^2 = 1
+            (p, Vec::from([p.x(), p.y(), -p.z()]))
+        });
+    }
+
+    #[test]
+    fn test_degenerate_hyperboloid() {
+        let p = Point::from([1., 0., 1.]);
+        assert!(Hyperboloid::new(p, p, 360., material()).is_err());
+        let q = Point::from([1., 1., 0.]);
+        assert!(Hyperboloid::new(Point::from([1., 0., -1.]), q, 360., material()).is_err());
+    }
+
+    #[test]
+    fn test_torus() {
+        let torus = Torus::new(2., 0.5, 180., material()).unwrap();
+        check_samples(&torus, |u, v| {
+            let phi = u * PI;
+            let theta = v * 2. * PI;
+            let r = 2. + 0.5 * theta.cos();
+            (
+                Point::from([r * phi.cos(), r * phi.sin(), 0.5 * theta.sin()]),
+                Vec::from([
+                    theta.cos() * phi.cos(),
+                    theta.cos() * phi.sin(),
+                    theta.sin(),
+                ]),
+            )
+        });
+        let ray = Ray::from(Point::from([-5., 0.1, 0.]), Vec::from([1., 0., 0.]));
+        assert!((torus.hit(ray, 0.001, f32::MAX).unwrap().t - 2.5).abs() < 1e-2);
+        let ray = Ray::from(Point::from([-5., -0.1, 0.]), Vec::from([1., 0., 0.]));
+        assert!(torus.hit(ray, 0.001, f32::MAX).is_none());
+    }
+}
@@ -1,18 +1,28 @@
 use crate::{material::Material, point::Point, vec::Vec};
 
-/* The struct for rays
+/** The struct for rays
  * A ray can be represented by giving its origin and its direction vector.
  * P is said to be on the Ray {origin, direct} iff exists t, P = origin + t * direct.
+ * Rays also carry the instant within the shutter interval they sample.
  */
 #[derive(Debug, Clone, Copy)]
 pub struct Ray {
     pub origin: Point,
     pub direct: Vec,
+    pub time: f32,
 }
 
 impl Ray {
     pub fn from(origin: Point, direct: Vec) -> Ray {
-        Ray { origin, direct }
+        Ray::new(origin, direct, 0.)
+    }
+
+    pub fn new(origin: Point, direct: Vec, time: f32) -> Ray {
+        Ray {
+            origin,
+            direct,
+            time,
+        }
     }
 
     pub fn at(&self, t: f32) -> Point {
@@ -20,16 +30,24 @@ impl Ray {
     }
 }
 
+/** Where a ray hit a shape; the material is borrowed from the shape that was hit.
+ * Triangles also record the barycentric coordinates of vertices 1 and 2 of the hit.
+ */
+#[derive(Clone, Copy)]
 pub struct HitRecord<'a> {
     pub t: f32,
     pub p: Point,
     pub n: Vec,
     pub material: &'a dyn Material,
     pub is_front: bool,
+    pub u: f32,
+    pub v: f32,
+    pub time: f32,
+    pub barycentrics: Option<(f32, f32)>,
 }
 
 impl<'a> HitRecord<'a> {
-    pub fn new(ray: Ray, t: f32, outward_n: Vec, material: &'a dyn Material) -> HitRecord {
+    pub fn new(ray: Ray, t: f32, outward_n: Vec, material: &'a dyn Material) -> HitRecord<'a> {
         let p = ray.at(t);
         let is_front = (ray.direct * outward_n) < 0.;
         let n = if is_front { outward_n } else { -outward_n };
@@ -39,6 +57,21 @@ impl<'a> HitRecord<'a> {
             n,
             is_front,
             material,
+            u: 0.,
+            v: 0.,
+            time: ray.time,
+            barycentrics: None,
+        }
+    }
+
+    pub fn with_uv(self, u: f32, v: f32) -> HitRecord<'a> {
+        HitRecord { u, v, ..self }
+    }
+
+    pub fn with_barycentrics(self, u: f32, v: f32) -> HitRecord<'a> {
+        HitRecord {
+            barycentrics: Some((u, v)),
+            ..self
         }
     }
 }
@@ -0,0 +1,867 @@
+use std::{
+    fmt, fs,
+    ops::RangeInclusive,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use indicatif::ProgressBar;
+
+use crate::{
+    aov::{Aov, AovSample},
+    camera::{Camera, CameraAnimation},
+    denoise::Denoiser,
+    error::Error,
+    film::{Film, Provenance, Window},
+    image,
+    integrator::{Integrator, Method},
+    material::Lobe,
+    point::Point,
+    ray::Ray,
+    rng,
+    scene::Scene,
+    stats::{self, Counter, Stats},
+    vec::Vec,
+};
+
+/* Fewer passes give too unreliable a variance to stop on. */
+const MIN_ERROR_PASSES: u32 = 4;
+/* Adaptive sampling decides per square tile of pixels, which averages out the noise of
+ * the per-pixel variance estimates.
+ */
+const TILE: usize = 8;
+
+/* Like `Integrator::sample`, also filling in the AOVs of the first hit, and splitting
+ * the light leaving it into the direct light of the second vertex and the indirect
+ * light of all later ones. The returned value is exactly the one `sample` would return.
+ */
+fn sample_aovs(integrator: &dyn Integrator, ray: Ray, scene: &Scene, aovs: &mut AovSample) -> Vec {
+    let (mut lobe, mut direct, mut indirect) = (None, Vec::new(), Vec::new());
+    let value = integrator.trace(ray, scene, &mut |depth, hit, light| match (depth, hit) {
+        (0, Some((object, record))) => {
+            let material = record.material;
+            aovs.set(Aov::Depth, Vec::from([record.t * ray.direct.len(), 0., 0.]));
+            aovs.set(
+                Aov::Normal,
+                if record.is_front { record.n } else { -record.n },
+            );
+            aovs.set(Aov::Albedo, material.albedo(record));
+            aovs.set(Aov::Position, record.p - Point::new());
+            aovs.set(Aov::Uv, Vec::from([record.u, record.v, 0.]));
+            aovs.set(
+                Aov::MaterialId,
+                Vec::from([scene.material_id(object) as f32, 0., 0.]),
+            );
+            aovs.set(Aov::ObjectId, Vec::from([object as f32 + 1., 0., 0.]));
+            lobe = Some(material.lobe(record));
+        }
+        (0, None) => {}
+        (1, _) => direct = light,
+        _ => indirect += light,
+    });
+    let (direct_aov, indirect_aov) = match lobe {
+        Some(Lobe::Diffuse) => (Aov::DiffuseDirect, Aov::DiffuseIndirect),
+        Some(Lobe::Specular) => (Aov::SpecularDirect, Aov::SpecularIndirect),
+        None => return value,
+    };
+    aovs.set(direct_aov, direct);
+    aovs.set(indirect_aov, indirect);
+    value
+}
+
+/** How to render an image. Rendering stops at `spp` passes, or earlier once the time
+ * budget would be exceeded by another pass or the estimated relative error drops to the
+ * target. The integrator is the scene's unless `integrator` is given, path tracing if
+ * neither chooses. Paths end after at most 50 bounces, and with `roulette_depth` they
+ * may end at random after that many bounces if they carry little light.
+ */
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub width: usize,
+    pub height: usize,
+    /** Only render this window of the image. */
+    pub window: Option<Window>,
+    pub aovs: std::vec::Vec<Aov>,
+    pub spp: u32,
+    pub seed: u64,
+    pub time_budget: Option<Duration>,
+    pub target_error: Option<f32>,
+    pub adaptive: Option<Adaptive>,
+    /** Where to save the film every `checkpoint_every` passes, if anywhere. */
+    pub checkpoint: Option<String>,
+    pub checkpoint_every: u32,
+    pub integrator: Option<Method>,
+    pub roulette_depth: Option<u32>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            width: 400,
+            height: 225,
+            window: None,
+            aovs: std::vec::Vec::new(),
+            spp: 100,
+            seed: 0,
+            time_budget: None,
+            target_error: None,
+            adaptive: None,
+            checkpoint: None,
+            checkpoint_every: 10,
+            integrator: None,
+            roulette_depth: Some(5),
+        }
+    }
+}
+
+impl Settings {
+    /** The integrator a render of the scene uses. */
+    pub fn method(&self, scene: &Scene) -> Method {
+        self.integrator.or(scene.integrator()).unwrap_or_default()
+    }
+
+    /** What checkpoints of a render of the scene record it was rendered with. */
+    pub fn provenance(&self, scene: &Scene) -> Provenance {
+        Provenance {
+            seed: self.seed,
+            integrator: self.method(scene).spec(),
+            roulette_depth: self.roulette_depth,
+        }
+    }
+
+    /** A new film of the image size, window and AOVs of the settings. */
+    pub fn film(&self) -> Result<Film, Error> {
+        if self.width == 0 || self.height == 0 {
+            return Err(Error::invalid_argument(
+                "image size",
+                format!("{}x{} is empty", self.width, self.height),
+            ));
+        }
+        let mut film = match self.window {
+            Some(window) => Film::cropped(self.width, self.height, window)?,
+            None => Film::new(self.width, self.height),
+        };
+        for &aov in &self.aovs {
+            film.add_layer(aov);
+        }
+        Ok(film)
+    }
+}
+
+/** After `min_spp` passes over the whole image, further passes only sample the tiles
+ * whose relative error is still above `threshold`, and no pixel beyond `max_spp`.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Adaptive {
+    pub threshold: f32,
+    pub min_spp: u32,
+    pub max_spp: u32,
+}
+
+impl Default for Adaptive {
+    fn default() -> Self {
+        Adaptive {
+            threshold: 0.05,
+            min_spp: 16,
+            max_spp: 1024,
+        }
+    }
+}
+
+impl Adaptive {
+    /* Marks the tiles of the film that need more samples, in the order of `Tiles::index`. */
+    fn active_tiles(&self, film: &Film) -> std::vec::Vec<bool> {
+        let tiles = Tiles::of(film);
+        let mut total = vec![0.; tiles.columns * tiles.rows];
+        let mut count = vec![0; total.len()];
+        let mut below_max = vec![false; total.len()];
+        for j in 0..film.height() {
+            for i in 0..film.width() {
+                let index = tiles.index(i, j);
+                let pixel = film.pixel(i, j);
+                total[index] += pixel.relative_variance();
+                count[index] += 1;
+                below_max[index] |= pixel.samples < self.max_spp;
+            }
+        }
+        (0..total.len())
+            .map(|index| {
+                film.passes() < self.min_spp
+                    || below_max[index]
+                        && total[index] / count[index] as f32 > self.threshold * self.threshold
+            })
+            .collect()
+    }
+}
+
+/* The TILE x TILE tiles of the full image that a film overlaps, row by row. Laying them
+ * out over the full image rather than the window keeps a crop's pixels grouped as in
+ * the full render, so the tiles a crop covers whole are sampled exactly like there.
+ */
+struct Tiles {
+    window: Window,
+    columns: usize,
+    rows: usize,
+}
+
+impl Tiles {
+    fn of(film: &Film) -> Tiles {
+        let window = film.window();
+        Tiles {
+            window,
+            columns: (window.x + window.width).div_ceil(TILE) - window.x / TILE,
+            rows: (window.y + window.height).div_ceil(TILE) - window.y / TILE,
+        }
+    }
+
+    /* The tile of film pixel (i, j). */
+    fn index(&self, i: usize, j: usize) -> usize {
+        let Window { x, y, .. } = self.window;
+        ((y + j) / TILE - y / TILE) * self.columns + (x + i) / TILE - x / TILE
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stop {
+    Samples,
+    TimeBudget,
+    TargetError,
+    Converged,
+}
+
+/** What a render achieved; `elapsed` only counts this run, not earlier runs of a resumed
+ * film.
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct Report {
+    pub spp: u32,
+    pub average_spp: f32,
+    pub elapsed: Duration,
+    pub relative_error: f32,
+    pub stop: Stop,
+    pub stats: Stats,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self.stop {
+            Stop::Samples => "sample count reached",
+            Stop::TimeBudget => "time budget reached",
+            Stop::TargetError => "target error reached",
+            Stop::Converged => "all tiles below the error threshold",
+        };
+        write!(
+            f,
+            "{} passes, {:.1} spp on average in {:.1}s, relative error {:.4} ({reason})",
+            self.spp,
+            self.average_spp,
+            self.elapsed.as_secs_f32(),
+            self.relative_error
+        )
+    }
+}
+
+/* Takes the next sample of pixel (i, j), drawing all randomness from the stream of that
+ * pixel and sample index. Streams and jitter are those of the full image, so a cropped
+ * film gets exactly the samples of the same pixels of an uncropped one.
+ */
+fn sample_pixel(
+    camera: &Camera,
+    scene: &Scene,
+    integrator: &dyn Integrator,
+    film: &mut Film,
+    seed: u64,
+    (i, j): (usize, usize),
+) {
+    let index = film.pixel(i, j).samples;
+    let window = film.window();
+    let (x, y) = (window.x + i, window.y + j);
+    rng::seed(rng::hash(&[seed, x as u64, y as u64, index as u64]));
+    let u = (x as f32 + rng::random()) / (film.full_width() as f32 - 1.);
+    let v = (y as f32 + rng::random()) / (film.full_height() as f32 - 1.);
+    let ray = camera.get_ray(u, v);
+    stats::count(Counter::CameraRays);
+    if !film.has_aovs() {
+        film.add_sample(i, j, integrator.sample(ray, scene));
+    } else {
+        let mut aovs = AovSample::default();
+        let value = sample_aovs(integrator, ray, scene, &mut aovs);
+        film.add_sample_aovs(i, j, value, &aovs);
+    }
+}
+
+/** Renders an image as `settings` describe it into a new film. */
+pub fn render(scene: &Scene, camera: &Camera, settings: &Settings) -> Result<Film, Error> {
+    let mut film = settings.film()?;
+    render_film(scene, camera, settings, &mut film)?;
+    Ok(film)
+}
+
+/* Whether one more pass, as long as the average of the `done` passes so far, would
+ * end after `budget`. At least one pass is always made.
+ */
+fn over_budget(budget: Duration, elapsed: Duration, done: u32) -> bool {
+    done > 0 && elapsed + elapsed / done > budget
+}
+
+/** Renders progressively, one sample per pixel per pass over the whole image, until one
+ * of the stopping criteria of `settings` is met. A film loaded from a checkpoint continues
+ * where it stopped and ends up identical to an uninterrupted render with the same seed,
+ * integrator and roulette depth, which `Settings::provenance` gives to compare.
+ */
+pub fn render_film(
+    scene: &Scene,
+    camera: &Camera,
+    settings: &Settings,
+    film: &mut Film,
+) -> Result<Report, Error> {
+    let length = settings
+        .adaptive
+        .map_or(settings.spp, |adaptive| adaptive.max_spp.min(settings.spp));
+    let bar = if length == u32::MAX {
+        ProgressBar::new_spinner()
+    } else {
+        ProgressBar::new(length as u64)
+    };
+    bar.set_position(film.passes().min(length) as u64);
+    let start = Instant::now();
+    stats::take(Duration::ZERO);
+    let first = film.passes();
+    let integrator = settings.method(scene).integrator(settings.roulette_depth);
+    let provenance = settings.provenance(scene);
+    let stop = loop {
+        if film.passes() >= settings.spp {
+            break Stop::Samples;
+        }
+        if let Some(target) = settings.target_error {
+            if film.passes() >= MIN_ERROR_PASSES && film.relative_error() <= target {
+                break Stop::TargetError;
+            }
+        }
+        if let Some(budget) = settings.time_budget {
+            if over_budget(budget, start.elapsed(), film.passes() - first) {
+                break Stop::TimeBudget;
+            }
+        }
+
+        let active = settings
+            .adaptive
+            .map(|adaptive| (adaptive, adaptive.active_tiles(film)));
+        if let Some((_, tiles)) = &active {
+            if !tiles.contains(&true) {
+                break Stop::Converged;
+            }
+        }
+        let layout = Tiles::of(film);
+        for j in 0..film.height() {
+            for i in 0..film.width() {
+                if let Some((adaptive, tiles)) = &active {
+                    if !tiles[layout.index(i, j)] || film.pixel(i, j).samples >= adaptive.max_spp {
+                        continue;
+                    }
+                }
+                let integrator = integrator.as_ref();
+                sample_pixel(camera, scene, integrator, film, settings.seed, (i, j));
+            }
+        }
+        film.finish_pass();
+        bar.inc(1);
+
+        if let Some(fname) = &settings.checkpoint {
+            if film
+                .passes()
+                .is_multiple_of(settings.checkpoint_every.max(1))
+            {
+                film.save(fname, &provenance)?;
+            }
+        }
+    };
+    if let Some(fname) = &settings.checkpoint {
+        film.save(fname, &provenance)?;
+    }
+    bar.finish_and_clear();
+    let elapsed = start.elapsed();
+    Ok(Report {
+        spp: film.passes(),
+        average_spp: film.total_samples() as f32 / (film.width() * film.height()) as f32,
+        elapsed,
+        relative_error: film.relative_error(),
+        stop,
+        stats: stats::take(elapsed),
+    })
+}
+
+/** Loads the checkpoint `fname` to continue rendering `scene` with `settings`. The
+ * checkpoint must have been started with the image size, window, seed, integrator and
+ * roulette depth of the settings, or the render would not end up as an uninterrupted
+ * one does; otherwise this is an `Error::InvalidArgument`.
+ */
+pub fn resume(fname: &str, settings: &Settings, scene: &Scene) -> Result<Film, Error> {
+    let (film, provenance) = Film::load(fname)?;
+    let window = settings.window.unwrap_or(Window {
+        x: 0,
+        y: 0,
+        width: settings.width,
+        height: settings.height,
+    });
+    if (film.full_width(), film.full_height(), film.window())
+        != (settings.width, settings.height, window)
+    {
+        return Err(Error::invalid_argument(
+            "checkpoint",
+            format!("{fname} does not match the image size and crop"),
+        ));
+    }
+    let expected = settings.provenance(scene);
+    if provenance != expected {
+        return Err(Error::invalid_argument(
+            "checkpoint",
+            format!("{fname} was rendered with {provenance}, not {expected}"),
+        ));
+    }
+    Ok(film)
+}
+
+/** How the film of a render is written: denoised or not, as an image of its window or
+ * composited into a black full-size one, and with which AOVs next to it.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct Output {
+    pub denoiser: Option<Denoiser>,
+    pub composite: bool,
+    /** Written as EXR files named after the image, or as the layers of one. */
+    pub aovs: std::vec::Vec<Aov>,
+    pub multilayer: bool,
+}
+
+impl Output {
+    /** Writes the AOVs and then the image, in the format the extension of `fname` names.
+     * The image is replaced whole, so once it exists, everything has been written.
+     */
+    pub fn write(&self, film: &Film, fname: &str) -> Result<(), Error> {
+        let (width, height) = if self.composite {
+            (film.full_width(), film.full_height())
+        } else {
+            (film.width(), film.height())
+        };
+        let mut image = image::for_file(fname, width, height)?;
+        let denoised = self.denoiser.map(|denoiser| denoiser.denoise(film));
+        let developed = denoised.as_ref().unwrap_or(film);
+        if self.composite {
+            developed.composite(image.as_mut())?;
+        } else {
+            developed.develop(image.as_mut())?;
+        }
+        film.write_aovs(&self.aovs, fname, self.multilayer)?;
+        let tmp = format!("{fname}.tmp");
+        image.to_file(&tmp)?;
+        fs::rename(&tmp, fname).map_err(|e| Error::io(fname, e))
+    }
+}
+
+/** The frames of an animation rendered as an image sequence, `fps` frames a second, each
+ * written to `dir/frame_NNNN.png`.
+ */
+pub struct Sequence {
+    pub animation: CameraAnimation,
+    pub frames: RangeInclusive<u32>,
+    pub fps: f32,
+    pub dir: String,
+}
+
+/** Renders the frames of `sequence` that are not there yet; skipping the frames whose PNG
+ * exists is how an interrupted sequence resumes. `done` is told of every frame, with its
+ * report unless it was skipped. Returns the statistics summed over the frames rendered.
+ */
+pub fn render_sequence(
+    scene: &Scene,
+    sequence: &Sequence,
+    settings: &Settings,
+    output: &Output,
+    mut done: impl FnMut(&str, Option<&Report>),
+) -> Result<Stats, Error> {
+    let aspect = settings.width as f32 / settings.height as f32;
+    let mut stats = Stats::default();
+    for frame in sequence.frames.clone() {
+        let fname = format!("{}/frame_{frame:04}.png", sequence.dir);
+        if Path::new(&fname).exists() {
+            done(&fname, None);
+            continue;
+        }
+        let camera = sequence
+            .animation
+            .at(frame as f32 / sequence.fps, 0., aspect);
+        let mut film = settings.film()?;
+        let report = render_film(scene, &camera, settings, &mut film)?;
+        output.write(&film, &fname)?;
+        done(&fname, Some(&report));
+        stats += report.stats;
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        material::{Lambertian, Material},
+        scenes::Builtin,
+        shape::Sphere,
+    };
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new_const([0.5, 0.5, 0.5]))
+    }
+
+    #[test]
+    fn test_resume() {
+        let mut scene = Scene::new();
+        scene.push(Sphere::new(Point::from([0., 0., -1.]), 0.5, material()).unwrap());
+        let camera = Camera::new();
+        let fname = std::env::temp_dir().join("rtus_test_resume.film");
+        let fname = fname.to_str().unwrap();
+
+        let settings = Settings {
+            width: 16,
+            height: 9,
+            spp: 4,
+            seed: 7,
+            ..Settings::default()
+        };
+        let mut uninterrupted = settings.film().unwrap();
+        render_film(&scene, &camera, &settings, &mut uninterrupted).unwrap();
+
+        let interrupted = Settings {
+            spp: 2,
+            checkpoint: Some(fname.to_string()),
+            ..settings
+        };
+        render_film(
+            &scene,
+            &camera,
+            &interrupted,
+            &mut interrupted.film().unwrap(),
+        )
+        .unwrap();
+        let settings = Settings {
+            spp: 4,
+            ..interrupted
+        };
+        /* Anything that changes the samples is refused. */
+        let refused = [
+            Settings {
+                seed: 8,
+                ..settings.clone()
+            },
+            Settings {
+                integrator: Some(Method::Whitted),
+                ..settings.clone()
+            },
+            Settings {
+                roulette_depth: None,
+                ..settings.clone()
+            },
+            Settings {
+                width: 32,
+                ..settings.clone()
+            },
+        ];
+        for other in &refused {
+            assert!(matches!(
+                resume(fname, other, &scene),
+                Err(Error::InvalidArgument { .. })
+            ));
+        }
+        let mut resumed = resume(fname, &settings, &scene).unwrap();
+        assert_eq!(resumed.passes(), 2);
+        render_film(&scene, &camera, &settings, &mut resumed).unwrap();
+
+        for j in 0..9 {
+            for i in 0..16 {
+                let (a, b) = (uninterrupted.pixel(i, j), resumed.pixel(i, j));
+                assert_eq!(a.samples, b.samples);
+                assert_eq!(a.sum.x().to_bits(), b.sum.x().to_bits());
+                assert_eq!(a.sum.z().to_bits(), b.sum.z().to_bits());
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_sequence() {
+        let dir = std::env::temp_dir().join("rtus_test_render_sequence");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let existing = dir.join("frame_0001.png");
+        fs::write(&existing, "rendered before").unwrap();
+
+        let builtin = Builtin::Spheres;
+        let sequence = Sequence {
+            animation: builtin.turntable(),
+            frames: 0..=2,
+            fps: 24.,
+            dir: dir.to_str().unwrap().to_string(),
+        };
+        let settings = Settings {
+            width: 8,
+            height: 4,
+            spp: 1,
+            ..Settings::default()
+        };
+        let mut rendered = std::vec::Vec::new();
+        let stats = render_sequence(
+            &builtin.scene(0).unwrap(),
+            &sequence,
+            &settings,
+            &Output::default(),
+            |fname, report| rendered.push((fname.to_string(), report.is_some())),
+        )
+        .unwrap();
+        let frames: std::vec::Vec<_> = rendered.iter().map(|(_, r)| *r).collect();
+        assert_eq!(frames, [true, false, true]);
+        if stats::ENABLED {
+            assert_eq!(stats.camera_rays, 2 * 8 * 4);
+        }
+        assert_eq!(fs::read(&existing).unwrap(), b"rendered before");
+        for frame in [0, 2] {
+            let fname = dir.join(format!("frame_{frame:04}.png"));
+            assert!(fname.exists());
+            assert!(!dir.join(format!("frame_{frame:04}.png.tmp")).exists());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_stopping() {
+        let mut scene = Scene::new();
+        scene.push(Sphere::new(Point::from([0., 0., -1.]), 0.5, material()).unwrap());
+        let camera = Camera::new();
+
+        let settings = Settings {
+            spp: u32::MAX,
+            target_error: Some(0.2),
+            ..Settings::default()
+        };
+        let mut film = Film::new(16, 9);
+        let report = render_film(&scene, &camera, &settings, &mut film).unwrap();
+        assert_eq!(report.stop, Stop::TargetError);
+        assert!(report.relative_error <= 0.2);
+        assert!(report.spp >= MIN_ERROR_PASSES && report.spp < 1000);
+
+        let settings = Settings {
+            spp: u32::MAX,
+            time_budget: Some(Duration::from_millis(50)),
+            ..Settings::default()
+        };
+        let report = render_film(&scene, &camera, &settings, &mut Film::new(16, 9)).unwrap();
+        assert_eq!(report.stop, Stop::TimeBudget);
+        assert!(report.spp >= 1);
+
+        let ms = Duration::from_millis;
+        assert!(!over_budget(ms(50), ms(200), 0));
+        assert!(!over_budget(ms(50), ms(30), 3));
+        assert!(over_budget(ms(50), ms(40), 3));
+        assert!(over_budget(ms(50), ms(60), 1));
+
+        let settings = Settings { spp: 3, ..settings };
+        let report = render_film(&scene, &camera, &settings, &mut Film::new(16, 9)).unwrap();
+        assert_eq!((report.stop, report.spp), (Stop::Samples, 3));
+    }
+
+    #[test]
+    #[cfg(feature = "stats")]
+    fn test_stats() {
+        let mut scene = Scene::new();
+        scene.push(Sphere::new(Point::from([0., 0., -1.]), 0.5, material()).unwrap());
+        scene.push(Sphere::new(Point::from([0., -100.5, -1.]), 100., material()).unwrap());
+        let settings = Settings {
+            spp: 3,
+            ..Settings::default()
+        };
+        let mut film = Film::new(16, 9);
+        let stats = render_film(&scene, &Camera::new(), &settings, &mut film)
+            .unwrap()
+            .stats;
+        assert_eq!(stats.camera_rays, 16 * 9 * 3);
+        /* Every ray is tested against both spheres, and rays hitting the ground bounce. */
+        assert_eq!(stats.intersection_tests, 2 * stats.rays());
+        assert!(stats.scatter_rays > 0 && stats.average_path_length() > 1.);
+        assert_eq!(stats.shadow_rays, 0);
+
+        /* The counts of a render do not include those of earlier ones. */
+        let again = render_film(&scene, &Camera::new(), &settings, &mut Film::new(16, 9))
+            .unwrap()
+            .stats;
+        assert_eq!(again.rays(), stats.rays());
+    }
+
+    /* The mean colors of the blocks of block x block pixels, row by row. */
+    fn blocks(film: &Film, block: usize) -> std::vec::Vec<Vec> {
+        let mut means = std::vec::Vec::new();
+        for y in (0..film.height() / block * block).step_by(block) {
+            for x in (0..film.width() / block * block).step_by(block) {
+                let mut sum = Vec::new();
+                for j in y..y + block {
+                    for i in x..x + block {
+                        sum += film.pixel(i, j).mean();
+                    }
+                }
+                means.push(sum / (block * block) as f32);
+            }
+        }
+        means
+    }
+
+    #[test]
+    fn test_roulette() {
+        /* Renders of the four spheres with and without roulette differ by noise only:
+         * about 0.0005 in the mean and 0.008 over blocks. Not weighting survivors up
+         * makes the mean 0.012 darker.
+         */
+        let scene = Builtin::Spheres.scene(0).unwrap();
+        let render = |roulette_depth, seed| {
+            let settings = Settings {
+                width: 48,
+                height: 27,
+                spp: 64,
+                seed,
+                roulette_depth,
+                ..Settings::default()
+            };
+            let mut film = settings.film().unwrap();
+            let report = render_film(&scene, &Camera::new(), &settings, &mut film).unwrap();
+            (blocks(&film, 3), report.stats)
+        };
+        let (without, without_stats) = render(None, 1);
+        let (with, with_stats) = render(Some(3), 2);
+        let n = with.len() as f32;
+        let difference = (with.iter().copied().sum::<Vec>() - without.iter().copied().sum()) / n;
+        assert!(
+            (0..3).all(|c| difference.at(c).abs() < 0.003),
+            "{difference}"
+        );
+        let squared: f32 = with
+            .iter()
+            .zip(&without)
+            .map(|(&a, &b)| (a - b) * (a - b))
+            .sum();
+        assert!((squared / (3. * n)).sqrt() < 0.012);
+        if stats::ENABLED {
+            assert!(with_stats.roulette_terminations > 0);
+            assert!(with_stats.scatter_rays < without_stats.scatter_rays);
+            assert_eq!(without_stats.roulette_terminations, 0);
+        }
+    }
+
+    #[test]
+    fn test_adaptive() {
+        /* A sphere seen from close up only has noise along its silhouette. */
+        let mut scene = Scene::new();
+        scene.push(Sphere::new(Point::from([0., 0., -1.]), 0.5, material()).unwrap());
+        let camera = Camera::new();
+        let adaptive = Adaptive {
+            threshold: 0.02,
+            min_spp: 8,
+            max_spp: 64,
+        };
+        let settings = Settings {
+            spp: u32::MAX,
+            adaptive: Some(adaptive),
+            ..Settings::default()
+        };
+        let mut film = Film::new(64, 36);
+        let report = render_film(&scene, &camera, &settings, &mut film).unwrap();
+        assert_eq!(report.stop, Stop::Converged);
+        assert!(report.average_spp > 8. && report.average_spp < 64.);
+        let samples: std::vec::Vec<u32> = (0..36)
+            .flat_map(|j| (0..64).map(move |i| (i, j)))
+            .map(|(i, j)| film.pixel(i, j).samples)
+            .collect();
+        assert!(samples.iter().all(|n| (8..=64).contains(n)));
+        assert!(samples.contains(&8) && film.max_samples() > 8);
+
+        /* Tiles a crop covers whole are refined exactly as in the full render. */
+        let window = Window {
+            x: 12,
+            y: 4,
+            width: 24,
+            height: 20,
+        };
+        let mut crop = Film::cropped(64, 36, window).unwrap();
+        render_film(&scene, &camera, &settings, &mut crop).unwrap();
+        for y in 8..24 {
+            for x in 16..32 {
+                let (a, b) = (film.pixel(x, y), crop.pixel(x - window.x, y - window.y));
+                assert_eq!(a.samples, b.samples);
+                assert_eq!(a.sum.x().to_bits(), b.sum.x().to_bits());
+            }
+        }
+    }
+
+    #[test]
+    fn test_crop() {
+        let mut scene = Scene::new();
+        scene.push(Sphere::new(Point::from([0., 0., -1.]), 0.5, material()).unwrap());
+        let camera = Camera::new();
+        let settings = Settings {
+            spp: 2,
+            seed: 5,
+            ..Settings::default()
+        };
+        let mut full = Film::new(16, 9);
+        render_film(&scene, &camera, &settings, &mut full).unwrap();
+        let window = Window {
+            x: 5,
+            y: 2,
+            width: 7,
+            height: 4,
+        };
+        let mut crop = Film::cropped(16, 9, window).unwrap();
+        render_film(&scene, &camera, &settings, &mut crop).unwrap();
+
+        for j in 0..window.height {
+            for i in 0..window.width {
+                let (a, b) = (full.pixel(window.x + i, window.y + j), crop.pixel(i, j));
+                assert_eq!(a.sum.x().to_bits(), b.sum.x().to_bits());
+                assert_eq!(a.sum.y().to_bits(), b.sum.y().to_bits());
+            }
+        }
+    }
+
+    #[test]
+    fn test_aovs() {
+        let mut scene = Scene::new();
+        scene.push(Sphere::new(Point::from([0., 0., -1.]), 0.5, material()).unwrap());
+        scene.set_material_id(0, 3);
+        let camera = Camera::new();
+        let settings = Settings {
+            spp: 3,
+            ..Settings::default()
+        };
+        let mut beauty = Film::new(17, 9);
+        render_film(&scene, &camera, &settings, &mut beauty).unwrap();
+        let mut film = Film::new(17, 9);
+        for aov in Aov::ALL {
+            film.add_layer(aov);
+        }
+        render_film(&scene, &camera, &settings, &mut film).unwrap();
+
+        for (i, j) in [(0, 0), (8, 4), (10, 6)] {
+            let (a, b) = (beauty.pixel(i, j), film.pixel(i, j));
+            assert_eq!(a.sum.x().to_bits(), b.sum.x().to_bits());
+            assert_eq!(a.sum.z().to_bits(), b.sum.z().to_bits());
+        }
+        /* The center pixel looks straight at the sphere. */
+        assert!((film.aov(Aov::Depth, 8, 4).x() - 0.5).abs() < 0.05);
+        assert!(film.aov(Aov::Normal, 8, 4).z() > 0.95);
+        assert_eq!(film.aov(Aov::Albedo, 8, 4).y(), 0.5);
+        assert_eq!(film.aov(Aov::ObjectId, 8, 4).x(), 1.);
+        assert_eq!(film.aov(Aov::MaterialId, 8, 4).x(), 3.);
+        assert_eq!(film.aov(Aov::ObjectId, 0, 0).x(), 0.);
+        assert_eq!(film.aov(Aov::Depth, 0, 0).x(), 0.);
+        let diffuse = film.aov(Aov::DiffuseDirect, 8, 4) + film.aov(Aov::DiffuseIndirect, 8, 4);
+        assert!((diffuse.x() - film.pixel(8, 4).mean().x()).abs() < 1e-5);
+        assert_eq!(film.aov(Aov::SpecularDirect, 8, 4).len(), 0.);
+    }
+}
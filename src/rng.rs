@@ -0,0 +1,117 @@
+use std::cell::Cell;
+
+/* A thread-local PCG32 generator. Renders reseed it for every pixel sample from the
+ * render seed, the pixel and the sample index, so the value of a sample does not depend
+ * on the order, the thread or the pass in which it is taken.
+ */
+
+const MULTIPLIER: u64 = 6364136223846793005;
+const INCREMENT: u64 = 1442695040888963407;
+
+thread_local! {
+    static STATE: Cell<u64> = const { Cell::new(0x853c_49e6_748f_ea9b) };
+}
+
+/* SplitMix64 finalizer, used to turn structured seeds into well-spread states. */
+fn mix(x: u64) -> u64 {
+    let x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    let x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+pub fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0, |acc, &x| mix(acc ^ x))
+}
+
+pub fn seed(seed: u64) {
+    STATE.with(|state| state.set(mix(seed)));
+}
+
+/* Advances a PCG32 state and returns the output for the old one. */
+fn step(state: &mut u64) -> u32 {
+    let old = *state;
+    *state = old.wrapping_mul(MULTIPLIER).wrapping_add(INCREMENT);
+    let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+    xorshifted.rotate_right((old >> 59) as u32)
+}
+
+fn unit(x: u32) -> f32 {
+    (x >> 8) as f32 / (1 << 24) as f32
+}
+
+pub fn next_u32() -> u32 {
+    STATE.with(|state| {
+        let mut current = state.get();
+        let x = step(&mut current);
+        state.set(current);
+        x
+    })
+}
+
+/** Uniform in [0, 1). */
+pub fn random() -> f32 {
+    unit(next_u32())
+}
+
+/** A PCG32 generator of its own, for code that must not disturb the thread-local stream. */
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: mix(seed) }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        step(&mut self.state)
+    }
+
+    /** Uniform in [0, 1). */
+    pub fn random(&mut self) -> f32 {
+        unit(self.next_u32())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reproducible() {
+        seed(hash(&[7, 1, 2]));
+        let a: std::vec::Vec<f32> = (0..8).map(|_| random()).collect();
+        seed(hash(&[7, 1, 3]));
+        let b: std::vec::Vec<f32> = (0..8).map(|_| random()).collect();
+        seed(hash(&[7, 1, 2]));
+        let c: std::vec::Vec<f32> = (0..8).map(|_| random()).collect();
+        assert_eq!(a, c);
+        assert_ne!(a, b);
+        assert!(a.iter().all(|x| (0. ..1.).contains(x)));
+    }
+
+    #[test]
+    fn test_uniform() {
+        seed(0);
+        let n = 100000;
+        let mean = (0..n).map(|_| random()).sum::<f32>() / n as f32;
+        assert!((mean - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_local() {
+        seed(5);
+        let a: std::vec::Vec<f32> = (0..8).map(|_| random()).collect();
+        let mut rng = Rng::new(5);
+        seed(5);
+        let b: std::vec::Vec<f32> = (0..8)
+            .map(|_| {
+                rng.random();
+                random()
+            })
+            .collect();
+        assert_eq!(a, b);
+        assert_eq!(Rng::new(5).random(), a[0]);
+    }
+}
@@ -1,30 +1,148 @@
+use std::sync::Arc;
+
 use crate::{
+    integrator::Method,
+    medium::Fog,
     ray::{HitRecord, Ray},
-    shape::Shape,
+    shape::{Shape, Surface},
+    stats::{self, Counter},
+    vec::Vec,
 };
 
+/** What rays that leave the scene see. */
+#[derive(Debug, Clone, Copy)]
+pub enum Background {
+    /** A gradient from white at the horizon to light blue overhead. */
+    Sky,
+    Constant(Vec),
+}
+
+impl Background {
+    pub fn at(&self, ray: Ray) -> Vec {
+        match *self {
+            Background::Sky => {
+                let unit_direct = ray.direct.to_unit();
+                let t = 0.5 * (unit_direct.y() + 1.);
+                (1. - t) * Vec::from([1., 1., 1.]) + t * Vec::from([0.5, 0.7, 1.])
+            }
+            Background::Constant(color) => color,
+        }
+    }
+}
+
+/** The shapes to render and what surrounds them. Lights are shapes that integrators can
+ * also sample directly; emissive shapes pushed as plain shapes only light the scene
+ * where paths happen to hit them.
+ */
 pub struct Scene {
-    shapes: Vec<Box<dyn Shape>>,
+    shapes: std::vec::Vec<Box<dyn Shape>>,
+    lights: std::vec::Vec<(usize, Arc<dyn Surface>)>,
+    fog: Option<Fog>,
+    background: Background,
+    integrator: Option<Method>,
+    material_ids: std::vec::Vec<usize>,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Scene::new()
+    }
 }
 
 impl Scene {
     pub fn new() -> Scene {
-        Scene { shapes: Vec::new() }
+        Scene {
+            shapes: std::vec::Vec::new(),
+            lights: std::vec::Vec::new(),
+            fog: None,
+            background: Background::Sky,
+            integrator: None,
+            material_ids: std::vec::Vec::new(),
+        }
     }
 
     pub fn push<T: Shape + 'static>(&mut self, obj: T) {
         self.shapes.push(Box::from(obj));
     }
 
-    pub fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let mut hit_point: Option<HitRecord> = None;
+    /** Adds a shape with an emissive material as a light. */
+    pub fn push_light<T: Surface + 'static>(&mut self, light: T) {
+        let light: Arc<dyn Surface> = Arc::new(light);
+        self.lights.push((self.shapes.len(), light.clone()));
+        self.shapes.push(Box::new(light));
+    }
+
+    /** The lights with the index of their shape, as `hit_object` returns it. */
+    pub fn lights(&self) -> &[(usize, Arc<dyn Surface>)] {
+        &self.lights
+    }
+
+    /** Adds a shape, for building scenes in one expression. */
+    pub fn with<T: Shape + 'static>(mut self, obj: T) -> Scene {
+        self.push(obj);
+        self
+    }
+
+    pub fn set_fog(&mut self, fog: Fog) {
+        self.fog = Some(fog);
+    }
+
+    pub fn set_background(&mut self, background: Background) {
+        self.background = background;
+    }
+
+    /** The integrator the scene is meant to be rendered with, unless the settings
+     * choose one.
+     */
+    pub fn set_integrator(&mut self, integrator: Method) {
+        self.integrator = Some(integrator);
+    }
+
+    pub fn integrator(&self) -> Option<Method> {
+        self.integrator
+    }
+
+    /** Numbers the material of an object, by its index as `hit_object` returns it, for
+     * the material ID AOV. `SceneBuilder` numbers materials from 1 in the order they
+     * are registered, so the IDs only change when the scene description does.
+     */
+    pub fn set_material_id(&mut self, object: usize, id: usize) {
+        if self.material_ids.len() <= object {
+            self.material_ids.resize(object + 1, 0);
+        }
+        self.material_ids[object] = id;
+    }
+
+    /** The material ID of an object, 0 if it has none. */
+    pub fn material_id(&self, object: usize) -> usize {
+        self.material_ids.get(object).copied().unwrap_or(0)
+    }
+
+    /** The light arriving along a ray that hits nothing. */
+    pub fn background(&self, ray: Ray) -> Vec {
+        self.background.at(ray)
+    }
+
+    pub fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.hit_object(ray, t_min, t_max).map(|(_, record)| record)
+    }
+
+    /** Like `hit`, also returning the index of the shape in the order it was pushed;
+     * scattering in the fog counts as one more object after all shapes.
+     */
+    pub fn hit_object(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<(usize, HitRecord<'_>)> {
+        let mut hit_point: Option<(usize, HitRecord)> = None;
         let mut t_max = t_max;
-        for obj in &self.shapes {
+        stats::add(Counter::IntersectionTests, self.shapes.len() as u64);
+        for (index, obj) in self.shapes.iter().enumerate() {
             if let Some(hp) = obj.hit(ray, t_min, t_max) {
                 t_max = hp.t;
-                hit_point = Some(hp);
+                hit_point = Some((index, hp));
             }
         }
+        if let Some(fp) = self.fog.as_ref().and_then(|fog| fog.hit(ray, t_min, t_max)) {
+            hit_point = Some((self.shapes.len(), fp));
+        }
         hit_point
     }
 }
@@ -0,0 +1,405 @@
+use std::f32::consts::PI;
+
+use crate::{
+    builder::SceneBuilder,
+    camera::{Camera, CameraAnimation},
+    curve::{Curve, Key},
+    error::Error,
+    instance::Transformed,
+    material::{Dielectric, Lambertian, Metal},
+    planar::Cuboid,
+    point::Point,
+    rng::{self, Rng},
+    scene::{Background, Scene},
+    transform::Transform,
+    vec::Vec,
+};
+
+/** Scenes that come with the renderer, for trying it out and for benchmarks. The
+ * generated ones are drawn from a seed, so a name and a seed always give the same scene.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    /** Three spheres of different materials on a large ground sphere. */
+    Spheres,
+    /** The final scene of "Ray Tracing in One Weekend": hundreds of small random spheres
+     * around three large ones.
+     */
+    RandomSpheres,
+    /** A cube of 8x8x8 spheres with random materials. */
+    SphereGrid,
+    /** The Cornell box: a closed room with red and green side walls, a square light
+     * in the ceiling and two boxes, seen through the open front.
+     */
+    CornellBox,
+    /** A (2, 3) torus knot made of one mesh of a few thousand triangles. */
+    TorusKnot,
+}
+
+/* Where the camera of a scene looks from and at, and its vertical field of view. */
+struct View {
+    from: Point,
+    at: Point,
+    vfov: f32,
+}
+
+impl Builtin {
+    pub const ALL: [Builtin; 5] = [
+        Builtin::Spheres,
+        Builtin::RandomSpheres,
+        Builtin::SphereGrid,
+        Builtin::CornellBox,
+        Builtin::TorusKnot,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Builtin::Spheres => "spheres",
+            Builtin::RandomSpheres => "random-spheres",
+            Builtin::SphereGrid => "sphere-grid",
+            Builtin::CornellBox => "cornell-box",
+            Builtin::TorusKnot => "torus-knot",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Builtin> {
+        Builtin::ALL
+            .into_iter()
+            .find(|builtin| builtin.name() == name)
+    }
+
+    pub fn scene(&self, seed: u64) -> Result<Scene, Error> {
+        /* Generators draw from their own stream, independent of the render's. */
+        let mut rng = Rng::new(rng::hash(&[seed, *self as u64]));
+        match self {
+            Builtin::Spheres => spheres(),
+            Builtin::RandomSpheres => random_spheres(&mut rng),
+            Builtin::SphereGrid => sphere_grid(&mut rng),
+            Builtin::CornellBox => cornell_box(),
+            Builtin::TorusKnot => torus_knot(),
+        }
+    }
+
+    fn view(&self) -> View {
+        let (from, at, vfov) = match self {
+            Builtin::Spheres => ([0., 0.5, 2.], [0., 0., -1.], 40.),
+            Builtin::RandomSpheres => ([13., 2., 3.], [0., 0., 0.], 20.),
+            Builtin::SphereGrid => ([14., 10., 18.], [0., 0., 0.], 40.),
+            Builtin::CornellBox => ([278., 278., -800.], [278., 278., 0.], 40.),
+            Builtin::TorusKnot => ([0., 2., 9.], [0., 0., 0.], 40.),
+        };
+        View {
+            from: Point::from(from),
+            at: Point::from(at),
+            vfov,
+        }
+    }
+
+    /** The camera the scene is meant to be seen through, for images of the given aspect. */
+    pub fn camera(&self, aspect: f32) -> Camera {
+        if *self == Builtin::Spheres {
+            return Camera::new();
+        }
+        let View { from, at, vfov } = self.view();
+        Camera::look_at(from, at, Vec::from([0., 1., 0.]), vfov, aspect)
+    }
+
+    /** Orbits the camera around the point it looks at, once every four seconds. */
+    pub fn turntable(&self) -> CameraAnimation {
+        let View { from, at, vfov } = self.view();
+        let center = at - Point::new();
+        let offset = from - at;
+        let radius = f32::hypot(offset.x(), offset.z());
+        let start = f32::atan2(offset.x(), offset.z());
+        let keys = (0..=8)
+            .map(|i| {
+                let angle = (i as f32 * 45.).to_radians() + start;
+                let from =
+                    center + Vec::from([radius * angle.sin(), offset.y(), radius * angle.cos()]);
+                Key::tcb(i as f32 * 0.5, from, 0., 0., 0.)
+            })
+            .collect();
+        CameraAnimation {
+            from: Curve::new(keys).unwrap(),
+            at: Curve::constant(center),
+            vup: Vec::from([0., 1., 0.]),
+            vfov: Curve::constant(vfov),
+        }
+    }
+}
+
+fn random_color(rng: &mut Rng) -> Vec {
+    Vec::from([rng.random(), rng.random(), rng.random()])
+}
+
+fn spheres() -> Result<Scene, Error> {
+    SceneBuilder::new()
+        .material("center", Lambertian::new_const([0.1, 0.2, 0.5]))
+        .material("ground", Lambertian::new_const([0.8, 0.8, 0.]))
+        .material("glass", Dielectric::new_const(1.5))
+        .material("gold", Metal::new_const([0.8, 0.6, 0.2], 0.))
+        .sphere(Point::from([0., 0., -1.]), 0.5, "center")
+        .sphere(Point::from([0., -100.5, -1.]), 100., "ground")
+        .sphere(Point::from([-1., 0., -1.]), -0.4, "glass")
+        .sphere(Point::from([1., 0., -1.]), 0.5, "gold")
+        .build()
+}
+
+fn random_spheres(rng: &mut Rng) -> Result<Scene, Error> {
+    let mut builder = SceneBuilder::new()
+        .material("ground", Lambertian::new_const([0.5, 0.5, 0.5]))
+        .material("glass", Dielectric::new_const(1.5))
+        .sphere(Point::from([0., -1000., 0.]), 1000., "ground");
+    for a in -11..11 {
+        for b in -11..11 {
+            let choice = rng.random();
+            let center = Point::from([
+                a as f32 + 0.9 * rng.random(),
+                0.2,
+                b as f32 + 0.9 * rng.random(),
+            ]);
+            if (center - Point::from([4., 0.2, 0.])).len() <= 0.9 {
+                continue;
+            }
+            /* Glass spheres all share one material. */
+            let name = if choice < 0.95 {
+                format!("small {a} {b}")
+            } else {
+                "glass".to_string()
+            };
+            if choice < 0.8 {
+                let albedo = random_color(rng).scale(random_color(rng));
+                builder = builder.material(&name, Lambertian::new(albedo));
+            } else if choice < 0.95 {
+                let albedo = Vec::from([0.5; 3]) + 0.5 * random_color(rng);
+                builder = builder.material(&name, Metal::new(albedo, 0.5 * rng.random()));
+            }
+            builder = builder.sphere(center, 0.2, &name);
+        }
+    }
+    builder
+        .material("brown", Lambertian::new_const([0.4, 0.2, 0.1]))
+        .material("steel", Metal::new_const([0.7, 0.6, 0.5], 0.))
+        .sphere(Point::from([0., 1., 0.]), 1., "glass")
+        .sphere(Point::from([-4., 1., 0.]), 1., "brown")
+        .sphere(Point::from([4., 1., 0.]), 1., "steel")
+        .build()
+}
+
+fn sphere_grid(rng: &mut Rng) -> Result<Scene, Error> {
+    const SIZE: usize = 8;
+    let mut builder = SceneBuilder::new();
+    for k in 0..SIZE * SIZE * SIZE {
+        let [i, j, l] = [k % SIZE, k / SIZE % SIZE, k / (SIZE * SIZE)];
+        let center = Point::from([i, j, l].map(|x| 1.5 * (x as f32 - (SIZE - 1) as f32 / 2.)));
+        let name = format!("sphere {i} {j} {l}");
+        builder = if rng.random() < 0.7 {
+            builder.material(&name, Lambertian::new(random_color(rng)))
+        } else {
+            builder.material(&name, Metal::new(random_color(rng), 0.2 * rng.random()))
+        };
+        builder = builder.sphere(center, 0.5, &name);
+    }
+    builder.build()
+}
+
+fn cornell_box() -> Result<Scene, Error> {
+    let (x, y, z) = (
+        Vec::from([555., 0., 0.]),
+        Vec::from([0., 555., 0.]),
+        Vec::from([0., 0., 555.]),
+    );
+    let origin = Point::new();
+    let boxes = [
+        (Point::from([130., 0., 65.]), 165., -18.),
+        (Point::from([265., 0., 295.]), 330., 15.),
+    ];
+    let mut builder = SceneBuilder::new()
+        .material("red", Lambertian::new_const([0.65, 0.05, 0.05]))
+        .material("white", Lambertian::new_const([0.73, 0.73, 0.73]))
+        .material("green", Lambertian::new_const([0.12, 0.45, 0.15]))
+        .light("light", Vec::from([15., 15., 15.]))
+        .background(Background::Constant(Vec::new()))
+        /* The walls face into the room. */
+        .quad(origin + x, z, y, "green")
+        .quad(origin, y, z, "red")
+        .quad(origin, z, x, "white")
+        .quad(origin + y, x, z, "white")
+        .quad(origin + z, y, x, "white")
+        .quad(
+            Point::from([343., 554., 332.]),
+            Vec::from([-130., 0., 0.]),
+            Vec::from([0., 0., -105.]),
+            "light",
+        );
+    for (corner, height, degrees) in boxes {
+        let size = Point::from([165., height, 165.]);
+        let transform = Transform::rotate(Vec::from([0., 1., 0.]), degrees)
+            .then(&Transform::translate(corner - Point::new()));
+        builder = builder.shape("box", "white", move |material| {
+            let cuboid = Cuboid::new(Point::new(), size, material)?;
+            Transformed::new(cuboid, transform)
+        });
+    }
+    builder.build()
+}
+
+fn torus_knot() -> Result<Scene, Error> {
+    const SEGMENTS: usize = 192;
+    const SIDES: usize = 12;
+    const TUBE: f32 = 0.35;
+    let (p, q) = (2., 3.);
+    let curve = |t: f32| {
+        let r = 2. + (q * t).cos();
+        Vec::from([r * (p * t).cos(), -(q * t).sin(), r * (p * t).sin()])
+    };
+
+    let mut vertices = std::vec::Vec::with_capacity(SEGMENTS * SIDES);
+    for i in 0..SEGMENTS {
+        let t = 2. * PI * i as f32 / SEGMENTS as f32;
+        /* The Frenet frame turns smoothly along the knot, whose curvature never vanishes. */
+        let (before, center, after) = (curve(t - 1e-2), curve(t), curve(t + 1e-2));
+        let tangent = (after - before).to_unit();
+        let bend = after - 2. * center + before;
+        let normal = (bend - (bend * tangent) * tangent).to_unit();
+        let binormal = tangent.cross(&normal);
+        for j in 0..SIDES {
+            let angle = 2. * PI * j as f32 / SIDES as f32;
+            let offset = TUBE * (angle.cos() * normal + angle.sin() * binormal);
+            vertices.push(Point::new() + center + offset);
+        }
+    }
+    let mut triangles = std::vec::Vec::with_capacity(2 * SEGMENTS * SIDES);
+    for i in 0..SEGMENTS {
+        for j in 0..SIDES {
+            let index = |i: usize, j: usize| (i % SEGMENTS) * SIDES + j % SIDES;
+            let (a, b) = (index(i, j), index(i + 1, j));
+            let (c, d) = (index(i + 1, j + 1), index(i, j + 1));
+            triangles.push([a, b, c]);
+            triangles.push([a, c, d]);
+        }
+    }
+
+    SceneBuilder::new()
+        .material("ground", Lambertian::new_const([0.5, 0.5, 0.5]))
+        .material("copper", Metal::new(Vec::from([0.95, 0.64, 0.54]), 0.1))
+        .sphere(Point::from([0., -1003.5, 0.]), 1000., "ground")
+        .mesh("knot", vertices, triangles, "copper")
+        .instance("knot", Transform::new())
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        diff::LinearImage,
+        image::{Image, Ppm},
+        ray::Ray,
+        render::{render, Settings},
+    };
+
+    const REFERENCE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/reference/cornell-box.ppm"
+    );
+    const SIZE: usize = 32;
+    const SPP: u32 = 64;
+
+    fn cornell_box(seed: u64, spp: u32) -> Ppm {
+        let builtin = Builtin::CornellBox;
+        let settings = Settings {
+            width: SIZE,
+            height: SIZE,
+            spp,
+            seed,
+            ..Settings::default()
+        };
+        let film = render(&builtin.scene(0).unwrap(), &builtin.camera(1.), &settings).unwrap();
+        let mut image = Ppm::with_size(SIZE, SIZE);
+        film.develop(&mut image).unwrap();
+        image
+    }
+
+    #[test]
+    fn test_builtins() {
+        for builtin in Builtin::ALL {
+            assert_eq!(Builtin::from_name(builtin.name()), Some(builtin));
+            let scene = builtin.scene(7).unwrap();
+            let View { from, at, .. } = builtin.view();
+            assert!(scene
+                .hit(Ray::from(from, at - from), 0.001, f32::MAX)
+                .is_some());
+        }
+        assert_eq!(Builtin::from_name("teapot"), None);
+    }
+
+    #[test]
+    fn test_seed() {
+        /* Looks down on the field of small spheres along a grid of rays. */
+        let heights = |seed| {
+            let scene = Builtin::RandomSpheres.scene(seed).unwrap();
+            (0..64 * 64)
+                .map(|k| {
+                    let x = -11. + 22. * (k % 64) as f32 / 64.;
+                    let z = -11. + 22. * (k / 64) as f32 / 64.;
+                    let ray = Ray::from(Point::from([x, 5., z]), Vec::from([0., -1., 0.]));
+                    scene.hit(ray, 0.001, f32::MAX).unwrap().t
+                })
+                .collect::<std::vec::Vec<_>>()
+        };
+        assert_eq!(heights(7), heights(7));
+        assert_ne!(heights(7), heights(8));
+    }
+
+    #[test]
+    fn test_scene_keeps_render_stream() {
+        rng::seed(3);
+        let expected = rng::random();
+        rng::seed(3);
+        Builtin::SphereGrid.scene(7).unwrap();
+        assert_eq!(rng::random(), expected);
+    }
+
+    /* Renders the Cornell box small and compares it with a stored render of 16 times the
+     * samples, close to converged. With light sampling, renders from different seeds
+     * differ from it by 0.001 to 0.004 over 8x8 blocks, with a light 20% dimmer by 0.016
+     * to 0.018 and with the red wall swapped for a green one by 0.03. Set
+     * RTUS_UPDATE_REFERENCE=1 to store a new reference instead, after checking it. On
+     * failure, the render and a heatmap of the difference are written to the temporary
+     * directory.
+     */
+    #[test]
+    fn test_cornell_box_reference() {
+        if std::env::var_os("RTUS_UPDATE_REFERENCE").is_some() {
+            cornell_box(0, 16 * SPP).to_file(REFERENCE).unwrap();
+            return;
+        }
+        let render = cornell_box(1, SPP);
+        let reference = Ppm::from_file(REFERENCE).unwrap();
+        assert_eq!((reference.width, reference.height), (SIZE, SIZE));
+        let (image, reference) = (
+            LinearImage::from_image(&render),
+            LinearImage::from_image(&reference),
+        );
+        let error = image
+            .downsampled(8)
+            .compare(&reference.downsampled(8))
+            .unwrap()
+            .rmse;
+        if error > 0.008 {
+            let directory = std::env::temp_dir();
+            let actual = directory.join("rtus-cornell-box.ppm");
+            let heatmap = directory.join("rtus-cornell-box-heatmap.ppm");
+            let (actual, heatmap) = (actual.to_str().unwrap(), heatmap.to_str().unwrap());
+            render.to_file(actual).unwrap();
+            let mut difference = Ppm::with_size(SIZE, SIZE);
+            image.heatmap(&reference, &mut difference).unwrap();
+            difference.to_file(heatmap).unwrap();
+            panic!(
+                "render differs from {REFERENCE} by {error} over 8x8 blocks; \
+                 see {actual} and {heatmap}"
+            );
+        }
+    }
+}
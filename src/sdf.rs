@@ -0,0 +1,289 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    csg::Solid,
+    material::Material,
+    point::Point,
+    ray::{HitRecord, Ray},
+    shape::Shape,
+    vec::Vec,
+};
+
+/** Signed distance functions: negative inside, positive outside. They need not be exact,
+ * but must never overestimate the distance, or sphere tracing may step through surfaces.
+ */
+pub trait Distance {
+    fn distance(&self, p: Point) -> f32;
+}
+
+impl<F: Fn(Point) -> f32> Distance for F {
+    fn distance(&self, p: Point) -> f32 {
+        self(p)
+    }
+}
+
+/** A composable distance expression. Primitives are built with the associated functions
+ * and combined with the methods, e.g. `SdfExpr::sphere(..).smooth_union(SdfExpr::round_box(..), 0.1)`.
+ */
+#[derive(Debug, Clone)]
+pub enum SdfExpr {
+    Sphere(Point, f32),
+    RoundBox(Point, Vec, f32),
+    Torus(Point, f32, f32),
+    Plane(Vec, f32),
+    Union(Box<SdfExpr>, Box<SdfExpr>),
+    Intersection(Box<SdfExpr>, Box<SdfExpr>),
+    Difference(Box<SdfExpr>, Box<SdfExpr>),
+    SmoothUnion(Box<SdfExpr>, Box<SdfExpr>, f32),
+    SmoothIntersection(Box<SdfExpr>, Box<SdfExpr>, f32),
+    SmoothDifference(Box<SdfExpr>, Box<SdfExpr>, f32),
+    Translate(Box<SdfExpr>, Vec),
+    Repeat(Box<SdfExpr>, Vec),
+}
+
+impl SdfExpr {
+    pub fn sphere(center: Point, radius: f32) -> SdfExpr {
+        SdfExpr::Sphere(center, radius)
+    }
+
+    /** A box of the given half extents whose edges are rounded by `radius`. */
+    pub fn round_box(center: Point, half_extent: Vec, radius: f32) -> SdfExpr {
+        SdfExpr::RoundBox(center, half_extent, radius)
+    }
+
+    /** A torus around the y axis. */
+    pub fn torus(center: Point, major_radius: f32, minor_radius: f32) -> SdfExpr {
+        SdfExpr::Torus(center, major_radius, minor_radius)
+    }
+
+    /** The half space below the plane n . p = offset. */
+    pub fn plane(n: Vec, offset: f32) -> SdfExpr {
+        SdfExpr::Plane(n.to_unit(), offset)
+    }
+
+    pub fn union(self, rhs: SdfExpr) -> SdfExpr {
+        SdfExpr::Union(Box::new(self), Box::new(rhs))
+    }
+
+    pub fn intersection(self, rhs: SdfExpr) -> SdfExpr {
+        SdfExpr::Intersection(Box::new(self), Box::new(rhs))
+    }
+
+    pub fn difference(self, rhs: SdfExpr) -> SdfExpr {
+        SdfExpr::Difference(Box::new(self), Box::new(rhs))
+    }
+
+    /** Blends the two surfaces over a band of width k. */
+    pub fn smooth_union(self, rhs: SdfExpr, k: f32) -> SdfExpr {
+        SdfExpr::SmoothUnion(Box::new(self), Box::new(rhs), k)
+    }
+
+    pub fn smooth_intersection(self, rhs: SdfExpr, k: f32) -> SdfExpr {
+        SdfExpr::SmoothIntersection(Box::new(self), Box::new(rhs), k)
+    }
+
+    pub fn smooth_difference(self, rhs: SdfExpr, k: f32) -> SdfExpr {
+        SdfExpr::SmoothDifference(Box::new(self), Box::new(rhs), k)
+    }
+
+    pub fn translate(self, delta: Vec) -> SdfExpr {
+        SdfExpr::Translate(Box::new(self), delta)
+    }
+
+    /** Repeats the expression infinitely with the given period along each axis;
+     * a period of zero leaves that axis alone.
+     */
+    pub fn repeat(self, period: Vec) -> SdfExpr {
+        SdfExpr::Repeat(Box::new(self), period)
+    }
+}
+
+/* Polynomial smooth minimum, from Inigo Quilez. */
+fn smooth_min(a: f32, b: f32, k: f32) -> f32 {
+    if k <= 0. {
+        return a.min(b);
+    }
+    let h = (0.5 + 0.5 * (b - a) / k).clamp(0., 1.);
+    b + (a - b) * h - k * h * (1. - h)
+}
+
+impl Distance for SdfExpr {
+    fn distance(&self, p: Point) -> f32 {
+        match self {
+            SdfExpr::Sphere(center, radius) => (p - *center).len() - radius,
+            SdfExpr::RoundBox(center, half_extent, radius) => {
+                let q = Vec::from(
+                    [0, 1, 2].map(|i| (p.at(i) - center.at(i)).abs() - half_extent.at(i) + radius),
+                );
+                let outside = Vec::from([0, 1, 2].map(|i| q.at(i).max(0.))).len();
+                let inside = q.x().max(q.y()).max(q.z()).min(0.);
+                outside + inside - radius
+            }
+            SdfExpr::Torus(center, major_radius, minor_radius) => {
+                let q = p - *center;
+                let ring = f32::hypot(q.x(), q.z()) - major_radius;
+                f32::hypot(ring, q.y()) - minor_radius
+            }
+            SdfExpr::Plane(n, offset) => (p - Point::new()) * *n - offset,
+            SdfExpr::Union(a, b) => a.distance(p).min(b.distance(p)),
+            SdfExpr::Intersection(a, b) => a.distance(p).max(b.distance(p)),
+            SdfExpr::Difference(a, b) => a.distance(p).max(-b.distance(p)),
+            SdfExpr::SmoothUnion(a, b, k) => smooth_min(a.distance(p), b.distance(p), *k),
+            SdfExpr::SmoothIntersection(a, b, k) => -smooth_min(-a.distance(p), -b.distance(p), *k),
+            SdfExpr::SmoothDifference(a, b, k) => -smooth_min(-a.distance(p), b.distance(p), *k),
+            SdfExpr::Translate(a, delta) => a.distance(p - *delta),
+            SdfExpr::Repeat(a, period) => {
+                let q = Point::from([0, 1, 2].map(|i| {
+                    let c = period.at(i);
+                    if c > 0. {
+                        (p.at(i) + 0.5 * c).rem_euclid(c) - 0.5 * c
+                    } else {
+                        p.at(i)
+                    }
+                }));
+                a.distance(q)
+            }
+        }
+    }
+}
+
+/** A surface given by the zero set of a distance function, found by sphere tracing
+ * inside `bounds`.
+ */
+pub struct Sdf<D: Distance> {
+    distance: D,
+    bounds: Aabb,
+    material: Arc<dyn Material>,
+}
+
+impl<D: Distance> Sdf<D> {
+    const MAX_STEPS: usize = 256;
+    const EPSILON: f32 = 1e-4;
+
+    pub fn new(distance: D, bounds: Aabb, material: Arc<dyn Material>) -> Sdf<D> {
+        Sdf {
+            distance,
+            bounds,
+            material,
+        }
+    }
+
+    /** Outward normal from the gradient, estimated by central differences. */
+    pub fn normal(&self, p: Point) -> Vec {
+        let h = Sdf::<D>::EPSILON;
+        Vec::from([0, 1, 2].map(|i| {
+            let mut delta = Vec::new();
+            *delta.at_mut(i) = h;
+            self.distance.distance(p + delta) - self.distance.distance(p - delta)
+        }))
+        .to_unit()
+    }
+}
+
+impl<D: Distance> Shape for Sdf<D> {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (t_enter, t_exit) = self.bounds.hit(ray, t_min, t_max)?;
+        let ray_len = ray.direct.len();
+        let eps = Sdf::<D>::EPSILON;
+
+        /* March on the side of the surface the ray starts on. A ray starting on the
+         * surface, as scattered rays do, first has to leave it before it can hit again.
+         */
+        let mut t = t_enter;
+        let start = self.distance.distance(ray.at(t));
+        let mut leaving = start.abs() < eps;
+        let side = if leaving {
+            (self.normal(ray.at(t)) * ray.direct).signum()
+        } else {
+            start.signum()
+        };
+        for _ in 0..Sdf::<D>::MAX_STEPS {
+            let d = side * self.distance.distance(ray.at(t));
+            if d < eps && !leaving {
+                let n = self.normal(ray.at(t));
+                return Some(HitRecord::new(ray, t, n, self.material.as_ref()));
+            }
+            if d >= eps {
+                leaving = false;
+            }
+            t += d.max(eps) / ray_len;
+            if t > t_exit {
+                return None;
+            }
+        }
+        None
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+impl<D: Distance> Solid for Sdf<D> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new_const([0.5, 0.5, 0.5]))
+    }
+
+    fn bounds() -> Aabb {
+        Aabb::from(Point::from([-2., -2., -2.]), Point::from([2., 2., 2.]))
+    }
+
+    #[test]
+    fn test_sphere_trace() {
+        let sdf = Sdf::new(SdfExpr::sphere(Point::new(), 1.), bounds(), material());
+        let ray = Ray::from(Point::from([0.3, 0.2, -5.]), Vec::from([0., 0., 1.]));
+        let record = sdf.hit(ray, 0.001, f32::MAX).unwrap();
+        let z = -f32::sqrt(1. - 0.3 * 0.3 - 0.2 * 0.2);
+        assert!((record.p.z() - z).abs() < 1e-3);
+        assert!((record.n - Vec::from([0.3, 0.2, z])).len() < 1e-3);
+        assert!(record.is_front);
+
+        let inside = Ray::from(record.p, Vec::from([0., 0., 1.]));
+        let record = sdf.hit(inside, 0.001, f32::MAX).unwrap();
+        assert!((record.p.z() + z).abs() < 1e-3);
+        assert!(!record.is_front);
+
+        let outside = Ray::from(record.p, Vec::from([0., 0., 1.]));
+        assert!(sdf.hit(outside, 0.001, f32::MAX).is_none());
+    }
+
+    #[test]
+    fn test_closure() {
+        let plane = Sdf::new(|p: Point| p.y() + 1., bounds(), material());
+        let ray = Ray::from(Point::from([0., 1., 0.]), Vec::from([0., -1., 0.]));
+        let record = plane.hit(ray, 0.001, f32::MAX).unwrap();
+        assert!((record.t - 2.).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_expressions() {
+        let a = SdfExpr::sphere(Point::new(), 1.);
+        let b = SdfExpr::sphere(Point::from([1.5, 0., 0.]), 1.);
+        let p = Point::from([0.75, 0.7, 0.]);
+        let sharp = a.clone().union(b.clone()).distance(p);
+        let smooth = a.clone().smooth_union(b.clone(), 0.5).distance(p);
+        assert!(smooth < sharp);
+        assert!(
+            a.clone()
+                .difference(b.clone())
+                .distance(Point::from([1., 0., 0.]))
+                > 0.
+        );
+
+        let rounded = SdfExpr::round_box(Point::new(), Vec::from([1., 1., 1.]), 0.2);
+        assert!((rounded.distance(Point::from([2., 0., 0.])) - 1.).abs() < 1e-6);
+        assert!(rounded.distance(Point::from([1., 1., 1.])) > 0.);
+
+        let grid = a
+            .translate(Vec::from([0., 5., 0.]))
+            .repeat(Vec::from([4., 0., 0.]));
+        assert!((grid.distance(Point::from([8., 5., 0.])) + 1.).abs() < 1e-6);
+    }
+}
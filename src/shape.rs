@@ -1,31 +1,136 @@
+use std::{f32::consts::PI, sync::Arc};
+
 use crate::{
+    aabb::Aabb,
+    error::{ensure, Error},
     material::Material,
     point::Point,
     ray::{HitRecord, Ray},
+    rng,
+    vec::Vec,
 };
 
 pub trait Shape {
-    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>>;
+
+    /** None for unbounded shapes. */
+    fn bounding_box(&self) -> Option<Aabb>;
+}
+
+/** Shapes whose surface can be sampled, so they can act as area lights. */
+pub trait Surface: Shape {
+    fn area(&self) -> f32;
+
+    /** Returns a point on the surface together with its outward normal. */
+    fn sample(&self) -> (Point, Vec);
+
+    /** Samples a point on the surface for lighting `from`, returning it with its outward
+     * normal and the density of the direction to it, per unit solid angle. None when the
+     * point cannot light `from`. Uniform by area unless a surface knows better.
+     */
+    fn sample_from(&self, from: Point) -> Option<(Point, Vec, f32)> {
+        sample_area(self, from)
+    }
+
+    /** The density with which `sample_from(from)` gives the point `p` with normal `n`. */
+    fn pdf_from(&self, from: Point, p: Point, n: Vec) -> f32 {
+        area_pdf(self.area(), from, p, n)
+    }
+}
+
+/* A point drawn uniformly by area, with its density as seen from `from`. */
+fn sample_area<S: Surface + ?Sized>(surface: &S, from: Point) -> Option<(Point, Vec, f32)> {
+    let (p, n) = surface.sample();
+    let pdf = area_pdf(surface.area(), from, p, n);
+    pdf.is_finite().then_some((p, n, pdf))
+}
+
+/* The solid angle density at `from` of picking `p` uniformly from an area. */
+fn area_pdf(area: f32, from: Point, p: Point, n: Vec) -> f32 {
+    let direct = p - from;
+    let distance2 = direct * direct;
+    let cos = (n * direct).abs() / distance2.sqrt();
+    if cos < 1e-6 {
+        return f32::INFINITY;
+    }
+    distance2 / (cos * area)
+}
+
+impl<S: Shape + ?Sized> Shape for Arc<S> {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.as_ref().hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.as_ref().bounding_box()
+    }
+}
+
+impl<S: Surface + ?Sized> Surface for Arc<S> {
+    fn area(&self) -> f32 {
+        self.as_ref().area()
+    }
+
+    fn sample(&self) -> (Point, Vec) {
+        self.as_ref().sample()
+    }
+
+    fn sample_from(&self, from: Point) -> Option<(Point, Vec, f32)> {
+        self.as_ref().sample_from(from)
+    }
+
+    fn pdf_from(&self, from: Point, p: Point, n: Vec) -> f32 {
+        self.as_ref().pdf_from(from, p, n)
+    }
 }
 
-pub struct Sphere<'a> {
+pub struct Sphere {
     center: Point,
     radius: f32,
-    material: &'a dyn Material,
+    material: Arc<dyn Material>,
 }
 
-impl<'a> Sphere<'a> {
-    pub fn new<'b: 'a>(center: Point, radius: f32, material: &'b dyn Material) -> Sphere {
-        Sphere {
+impl Sphere {
+    /** A negative radius flips the normals inwards, which makes hollow glass spheres. */
+    pub fn new(center: Point, radius: f32, material: Arc<dyn Material>) -> Result<Sphere, Error> {
+        let object = format!("sphere at {center}");
+        ensure(center.is_finite(), &object, "center is not finite")?;
+        ensure(
+            radius.is_finite() && radius != 0.,
+            &object,
+            format!("radius is {radius}"),
+        )?;
+        Ok(Sphere {
             center,
             radius,
             material,
+        })
+    }
+}
+
+impl Sphere {
+    /* u goes around the y axis starting from -x, v from the bottom pole to the top one. */
+    fn uv(n: Vec) -> (f32, f32) {
+        let theta = f32::acos(-n.y());
+        let phi = f32::atan2(-n.z(), n.x()) + PI;
+        (phi / (2. * PI), theta / PI)
+    }
+
+    /* 1 - cos of the half-angle of the cone the sphere fills as seen from `from`, None
+     * from inside. Computed from the sine, which keeps it accurate for small cones.
+     */
+    fn cone(&self, from: Point) -> Option<f32> {
+        let distance2 = (self.center - from) * (self.center - from);
+        let sin2_max = self.radius * self.radius / distance2;
+        if sin2_max >= 1. {
+            return None;
         }
+        Some(sin2_max / (1. + (1. - sin2_max).sqrt()))
     }
 }
 
-impl<'a> Shape for Sphere<'a> {
-    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+impl Shape for Sphere {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let oc = ray.origin - self.center;
         let a = ray.direct * ray.direct;
         let b = oc * ray.direct;
@@ -43,11 +148,103 @@ impl<'a> Shape for Sphere<'a> {
             }
         }
         let p = ray.at(t);
-        Some(HitRecord::new(
-            ray,
-            t,
-            (p - self.center) / self.radius,
-            self.material,
-        ))
+        let outward_n = (p - self.center) / self.radius;
+        let (u, v) = Sphere::uv(outward_n);
+        Some(HitRecord::new(ray, t, outward_n, self.material.as_ref()).with_uv(u, v))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = Vec::from([self.radius.abs(); 3]);
+        Some(Aabb::from(self.center - r, self.center + r))
+    }
+}
+
+impl Surface for Sphere {
+    fn area(&self) -> f32 {
+        4. * PI * self.radius * self.radius
+    }
+
+    fn sample(&self) -> (Point, Vec) {
+        let n = Vec::new_rand_unit_sphere().to_unit();
+        (self.center + self.radius * n, n)
+    }
+
+    /* From outside, only the cap facing `from` is visible, and directions are drawn
+     * uniformly from the cone around it (pbrt's sphere sampling). From inside, the whole
+     * sphere is, and points are drawn by area.
+     */
+    fn sample_from(&self, from: Point) -> Option<(Point, Vec, f32)> {
+        let Some(one_minus_cos_max) = self.cone(from) else {
+            return sample_area(self, from);
+        };
+        let axis = self.center - from;
+        let distance = axis.len();
+        let axis = axis / distance;
+        let (tangent, bitangent) = axis.orthonormal_basis();
+        let cos_theta = 1. - rng::random() * one_minus_cos_max;
+        let sin_theta = f32::sqrt(f32::max(0., 1. - cos_theta * cos_theta));
+        let phi = 2. * PI * rng::random();
+        let direct =
+            sin_theta * phi.cos() * tangent + sin_theta * phi.sin() * bitangent + cos_theta * axis;
+        /* The nearer hit of the direction, the whole way across where it grazes. */
+        let radius2 = self.radius * self.radius;
+        let half_chord2 = radius2 - distance * distance * sin_theta * sin_theta;
+        let t = distance * cos_theta - half_chord2.max(0.).sqrt();
+        let p = from + t * direct;
+        let n = (p - self.center) / self.radius;
+        Some((p, n, 1. / (2. * PI * one_minus_cos_max)))
+    }
+
+    fn pdf_from(&self, from: Point, p: Point, n: Vec) -> f32 {
+        match self.cone(from) {
+            Some(one_minus_cos_max) => 1. / (2. * PI * one_minus_cos_max),
+            None => area_pdf(self.area(), from, p, n),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new_const([0.5, 0.5, 0.5]))
+    }
+
+    #[test]
+    fn test_invalid_sphere() {
+        let center = Point::from([0., 0., -1.]);
+        let error = Sphere::new(center, 0., material()).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "invalid sphere at (0.0, 0.0, -1.0): radius is 0"
+        );
+        assert!(Sphere::new(center, f32::NAN, material()).is_err());
+        assert!(Sphere::new(Point::from([f32::INFINITY, 0., 0.]), 1., material()).is_err());
+        assert!(Sphere::new(center, -0.4, material()).is_ok());
+    }
+
+    #[test]
+    fn test_sample_from() {
+        let sphere = Sphere::new(Point::new(), 0.5, material()).unwrap();
+        let from = Point::from([0., 0., 3.]);
+        rng::seed(1);
+        for _ in 0..1000 {
+            let (p, n, pdf) = sphere.sample_from(from).unwrap();
+            assert!(((p - Point::new()).len() - 0.5).abs() < 1e-5);
+            /* Only the cap facing `from` is sampled. */
+            assert!(n * (from - p) >= -1e-5);
+            assert_eq!(pdf, sphere.pdf_from(from, p, n));
+        }
+        /* The cone fills 2 pi (1 - cos) of the directions. */
+        let cos_max = (1. - 0.5_f32 * 0.5 / 9.).sqrt();
+        let pdf = sphere.pdf_from(from, Point::from([0., 0., 0.5]), Vec::from([0., 0., 1.]));
+        assert!((pdf * 2. * PI * (1. - cos_max) - 1.).abs() < 1e-4);
+        let far = Point::from([0., 0., 1e4]);
+        assert!(sphere.sample_from(far).unwrap().2.is_finite());
+
+        let (_, _, pdf) = sphere.sample_from(Point::from([0.1, 0., 0.])).unwrap();
+        assert!(pdf > 0. && pdf.is_finite());
     }
 }
@@ -0,0 +1,159 @@
+use std::{cell::Cell, fmt, ops::AddAssign, time::Duration};
+
+/* Counters of what a render does, kept per thread like the random number generator.
+ * Without the `stats` feature, counting compiles to nothing.
+ */
+
+/** Whether this build counts anything; without the `stats` feature all counts are 0. */
+pub const ENABLED: bool = cfg!(feature = "stats");
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Counter {
+    CameraRays,
+    ScatterRays,
+    ShadowRays,
+    IntersectionTests,
+    RouletteTerminations,
+}
+
+thread_local! {
+    static COUNTS: [Cell<u64>; 5] = const { [const { Cell::new(0) }; 5] };
+}
+
+#[inline]
+pub(crate) fn count(counter: Counter) {
+    add(counter, 1);
+}
+
+#[inline]
+pub(crate) fn add(counter: Counter, n: u64) {
+    if ENABLED {
+        COUNTS.with(|counts| {
+            let count = &counts[counter as usize];
+            count.set(count.get() + n);
+        });
+    }
+}
+
+/* The counts of this thread since the last call, which it resets. */
+pub(crate) fn take(elapsed: Duration) -> Stats {
+    let [camera_rays, scatter_rays, shadow_rays, intersection_tests, roulette_terminations] =
+        COUNTS.with(|counts| counts.each_ref().map(|count| count.take()));
+    Stats {
+        camera_rays,
+        scatter_rays,
+        shadow_rays,
+        intersection_tests,
+        roulette_terminations,
+        elapsed,
+    }
+}
+
+/** What a render did. The scene tests every ray against every shape and there is no BVH,
+ * so there are no BVH nodes visited to count, and intersection tests count shapes and,
+ * within meshes, triangles. Shadow rays test whether a light or the surroundings of a
+ * point are visible from it.
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Stats {
+    pub camera_rays: u64,
+    /** Rays leaving a surface or a medium after scattering. */
+    pub scatter_rays: u64,
+    pub shadow_rays: u64,
+    pub intersection_tests: u64,
+    pub roulette_terminations: u64,
+    pub elapsed: Duration,
+}
+
+impl Stats {
+    pub fn rays(&self) -> u64 {
+        self.camera_rays + self.scatter_rays + self.shadow_rays
+    }
+
+    pub fn rays_per_second(&self) -> f64 {
+        self.rays() as f64 / self.elapsed.as_secs_f64().max(1e-9)
+    }
+
+    /** Segments per path, counting the camera ray and all scattered rays. */
+    pub fn average_path_length(&self) -> f64 {
+        (self.camera_rays + self.scatter_rays) as f64 / self.camera_rays.max(1) as f64
+    }
+
+    /** The counts and the rates derived from them as one JSON object. */
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"camera_rays\": {}, \"scatter_rays\": {}, \"shadow_rays\": {}, \
+             \"intersection_tests\": {}, \"roulette_terminations\": {}, \"rays\": {}, \
+             \"seconds\": {:.6}, \"rays_per_second\": {:.1}, \"average_path_length\": {:.4}}}",
+            self.camera_rays,
+            self.scatter_rays,
+            self.shadow_rays,
+            self.intersection_tests,
+            self.roulette_terminations,
+            self.rays(),
+            self.elapsed.as_secs_f64(),
+            self.rays_per_second(),
+            self.average_path_length()
+        )
+    }
+}
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, rhs: Stats) {
+        self.camera_rays += rhs.camera_rays;
+        self.scatter_rays += rhs.scatter_rays;
+        self.shadow_rays += rhs.shadow_rays;
+        self.intersection_tests += rhs.intersection_tests;
+        self.roulette_terminations += rhs.roulette_terminations;
+        self.elapsed += rhs.elapsed;
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !ENABLED {
+            return write!(f, "no statistics, built without the stats feature");
+        }
+        write!(
+            f,
+            "{} rays ({} camera, {} scatter, {} shadow), {:.2} Mrays/s, {} intersection \
+             tests, {:.2} segments per path, {} paths ended by Russian roulette",
+            self.rays(),
+            self.camera_rays,
+            self.scatter_rays,
+            self.shadow_rays,
+            self.rays_per_second() / 1e6,
+            self.intersection_tests,
+            self.average_path_length(),
+            self.roulette_terminations
+        )
+    }
+}
+
+#[cfg(all(test, feature = "stats"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count() {
+        take(Duration::ZERO);
+        count(Counter::CameraRays);
+        count(Counter::CameraRays);
+        add(Counter::ScatterRays, 3);
+        add(Counter::ShadowRays, 5);
+        add(Counter::IntersectionTests, 10);
+        let stats = take(Duration::from_secs(2));
+        assert_eq!((stats.camera_rays, stats.scatter_rays), (2, 3));
+        assert_eq!(stats.rays(), 10);
+        assert_eq!(stats.rays_per_second(), 5.);
+        assert_eq!(stats.average_path_length(), 2.5);
+        assert_eq!(take(Duration::ZERO).rays(), 0);
+
+        let mut total = stats;
+        total += stats;
+        assert_eq!((total.intersection_tests, total.elapsed.as_secs()), (20, 4));
+        let json = stats.to_json();
+        assert!(json.starts_with("{\"camera_rays\": 2, \"scatter_rays\": 3,"));
+        assert!(json.ends_with("\"average_path_length\": 2.5000}"));
+    }
+}
@@ -0,0 +1,99 @@
+use crate::{diff::LinearImage, error::Error, ray::HitRecord, vec::Vec};
+
+/** A color that varies over surfaces, looked up where a ray hits them. */
+pub trait Texture: Send + Sync {
+    fn value(&self, record: &HitRecord) -> Vec;
+}
+
+/** One color everywhere. */
+impl Texture for Vec {
+    fn value(&self, _record: &HitRecord) -> Vec {
+        *self
+    }
+}
+
+/** Squares of two colors alternating in the UV coordinates, `scale` squares to the unit. */
+pub struct Checker {
+    even: Vec,
+    odd: Vec,
+    scale: f32,
+}
+
+impl Checker {
+    pub fn new(even: Vec, odd: Vec, scale: f32) -> Checker {
+        Checker { even, odd, scale }
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, record: &HitRecord) -> Vec {
+        let square = |x: f32| (x * self.scale).floor() as i64;
+        if (square(record.u) + square(record.v)).rem_euclid(2) == 0 {
+            self.even
+        } else {
+            self.odd
+        }
+    }
+}
+
+/** An image stretched over the UV square, u = 0 at its left and v = 0 at its bottom. */
+pub struct ImageTexture {
+    image: LinearImage,
+}
+
+impl ImageTexture {
+    pub fn new(image: LinearImage) -> ImageTexture {
+        ImageTexture { image }
+    }
+
+    /** Reads a PPM, PNG or EXR file as `LinearImage::from_file` does. */
+    pub fn from_file(fname: &str) -> Result<ImageTexture, Error> {
+        Ok(ImageTexture::new(LinearImage::from_file(fname)?))
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, record: &HitRecord) -> Vec {
+        let (width, height) = (self.image.width, self.image.height);
+        if width == 0 || height == 0 {
+            return Vec::new();
+        }
+        let texel = |t: f32, size: usize| ((t.clamp(0., 1.) * size as f32) as usize).min(size - 1);
+        self.image
+            .at(texel(record.v, height), texel(record.u, width))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::Color, image::Ppm, material::Lambertian, point::Point, ray::Ray};
+
+    fn record(u: f32, v: f32) -> HitRecord<'static> {
+        static GREY: Lambertian = Lambertian::new_const([0.5, 0.5, 0.5]);
+        let ray = Ray::from(Point::new(), Vec::from([0., 0., -1.]));
+        HitRecord::new(ray, 1., Vec::from([0., 0., 1.]), &GREY).with_uv(u, v)
+    }
+
+    #[test]
+    fn test_checker() {
+        let (white, black) = (Vec::from([1., 1., 1.]), Vec::new());
+        let checker = Checker::new(white, black, 2.);
+        assert_eq!(checker.value(&record(0.1, 0.1)).x(), 1.);
+        assert_eq!(checker.value(&record(0.6, 0.1)).x(), 0.);
+        assert_eq!(checker.value(&record(0.6, 0.6)).x(), 1.);
+        assert_eq!(white.value(&record(0.6, 0.1)).x(), 1.);
+    }
+
+    #[test]
+    fn test_image() {
+        let mut ppm = Ppm::with_size(2, 2);
+        ppm.plot(0, 1, Color::from([255, 0, 0])).unwrap();
+        ppm.plot(1, 0, Color::from([0, 0, 255])).unwrap();
+        let texture = ImageTexture::new(LinearImage::from_image(&ppm));
+        assert_eq!(texture.value(&record(0.9, 0.1)).x(), 1.);
+        assert_eq!(texture.value(&record(0.1, 0.9)).z(), 1.);
+        assert_eq!(texture.value(&record(0.1, 0.1)).len(), 0.);
+        assert_eq!(texture.value(&record(2., 2.)).len(), 0.);
+    }
+}
@@ -0,0 +1,287 @@
+use std::ops::Mul;
+
+use crate::{point::Point, ray::Ray, vec::Vec};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix4 {
+    coeff: [[f32; 4]; 4],
+}
+
+impl Matrix4 {
+    pub fn identity() -> Matrix4 {
+        let mut coeff = [[0.; 4]; 4];
+        for (i, row) in coeff.iter_mut().enumerate() {
+            row[i] = 1.;
+        }
+        Matrix4 { coeff }
+    }
+
+    pub fn at(&self, row: usize, col: usize) -> f32 {
+        self.coeff[row][col]
+    }
+
+    pub fn transpose(&self) -> Matrix4 {
+        let mut coeff = [[0.; 4]; 4];
+        for (i, row) in coeff.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = self.coeff[j][i];
+            }
+        }
+        Matrix4 { coeff }
+    }
+
+    /** Gauss-Jordan elimination with partial pivoting; None if the matrix is singular. */
+    pub fn inverse(&self) -> Option<Matrix4> {
+        let mut a = self.coeff;
+        let mut inv = Matrix4::identity().coeff;
+        for col in 0..4 {
+            let pivot = (col..4).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+            let scale = 1. / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+            for row in 0..4 {
+                if row != col {
+                    let factor = a[row][col];
+                    for j in 0..4 {
+                        a[row][j] -= factor * a[col][j];
+                        inv[row][j] -= factor * inv[col][j];
+                    }
+                }
+            }
+        }
+        Some(Matrix4 { coeff: inv })
+    }
+
+    pub fn apply_point(&self, p: Point) -> Point {
+        let m = &self.coeff;
+        let x = |i: usize| m[i][0] * p.x() + m[i][1] * p.y() + m[i][2] * p.z() + m[i][3];
+        let w = x(3);
+        let mut result = Point::from([x(0), x(1), x(2)]);
+        if w != 1. {
+            result /= w;
+        }
+        result
+    }
+
+    pub fn apply_vec(&self, v: Vec) -> Vec {
+        let m = &self.coeff;
+        let x = |i: usize| m[i][0] * v.x() + m[i][1] * v.y() + m[i][2] * v.z();
+        Vec::from([x(0), x(1), x(2)])
+    }
+}
+
+impl From<[[f32; 4]; 4]> for Matrix4 {
+    fn from(value: [[f32; 4]; 4]) -> Self {
+        Matrix4 { coeff: value }
+    }
+}
+
+impl Mul<Matrix4> for Matrix4 {
+    type Output = Matrix4;
+    fn mul(self, rhs: Matrix4) -> Self::Output {
+        let mut coeff = [[0.; 4]; 4];
+        for (i, row) in coeff.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = (0..4).map(|k| self.coeff[i][k] * rhs.coeff[k][j]).sum();
+            }
+        }
+        Matrix4 { coeff }
+    }
+}
+
+/** An affine transform, kept together with its inverse. Scaling by 0 makes one that
+ * cannot be inverted, which `is_invertible` tells and shapes refuse.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    m: Matrix4,
+    m_inv: Matrix4,
+}
+
+impl Transform {
+    pub fn new() -> Transform {
+        Transform::default()
+    }
+
+    pub fn from_matrix(m: Matrix4) -> Option<Transform> {
+        Some(Transform {
+            m,
+            m_inv: m.inverse()?,
+        })
+    }
+
+    pub fn translate(delta: Vec) -> Transform {
+        let mut m = Matrix4::identity();
+        let mut m_inv = Matrix4::identity();
+        for i in 0..=2 {
+            m.coeff[i][3] = delta.at(i);
+            m_inv.coeff[i][3] = -delta.at(i);
+        }
+        Transform { m, m_inv }
+    }
+
+    pub fn scale(factor: Vec) -> Transform {
+        let mut m = Matrix4::identity();
+        let mut m_inv = Matrix4::identity();
+        for i in 0..=2 {
+            m.coeff[i][i] = factor.at(i);
+            m_inv.coeff[i][i] = 1. / factor.at(i);
+        }
+        Transform { m, m_inv }
+    }
+
+    /** Counter-clockwise rotation by `degrees` around `axis` (Rodrigues' formula). */
+    pub fn rotate(axis: Vec, degrees: f32) -> Transform {
+        let a = axis.to_unit();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let m = Matrix4::from([
+            [
+                a.x() * a.x() + (1. - a.x() * a.x()) * cos,
+                a.x() * a.y() * (1. - cos) - a.z() * sin,
+                a.x() * a.z() * (1. - cos) + a.y() * sin,
+                0.,
+            ],
+            [
+                a.x() * a.y() * (1. - cos) + a.z() * sin,
+                a.y() * a.y() + (1. - a.y() * a.y()) * cos,
+                a.y() * a.z() * (1. - cos) - a.x() * sin,
+                0.,
+            ],
+            [
+                a.x() * a.z() * (1. - cos) - a.y() * sin,
+                a.y() * a.z() * (1. - cos) + a.x() * sin,
+                a.z() * a.z() + (1. - a.z() * a.z()) * cos,
+                0.,
+            ],
+            [0., 0., 0., 1.],
+        ]);
+        Transform {
+            m,
+            m_inv: m.transpose(),
+        }
+    }
+
+    pub fn matrix(&self) -> Matrix4 {
+        self.m
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform {
+            m: self.m_inv,
+            m_inv: self.m,
+        }
+    }
+
+    /** Whether the transform and its inverse are finite, which a scale factor of 0 or
+     * infinity breaks.
+     */
+    pub fn is_invertible(&self) -> bool {
+        [self.m, self.m_inv]
+            .iter()
+            .all(|m| m.coeff.iter().flatten().all(|x| x.is_finite()))
+    }
+
+    /** Composes two transforms; `rhs` is applied after `self`. */
+    pub fn then(&self, rhs: &Transform) -> Transform {
+        Transform {
+            m: rhs.m * self.m,
+            m_inv: self.m_inv * rhs.m_inv,
+        }
+    }
+
+    pub fn apply_point(&self, p: Point) -> Point {
+        self.m.apply_point(p)
+    }
+
+    pub fn apply_vec(&self, v: Vec) -> Vec {
+        self.m.apply_vec(v)
+    }
+
+    /** Normals transform by the inverse transpose to stay perpendicular to the surface. */
+    pub fn apply_normal(&self, n: Vec) -> Vec {
+        self.m_inv.transpose().apply_vec(n)
+    }
+
+    pub fn apply_ray(&self, ray: Ray) -> Ray {
+        Ray::new(
+            self.apply_point(ray.origin),
+            self.apply_vec(ray.direct),
+            ray.time,
+        )
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            m: Matrix4::identity(),
+            m_inv: Matrix4::identity(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{a} != {b}");
+    }
+
+    #[test]
+    fn test_inverse() {
+        let m = Matrix4::from([
+            [2., 0., 0., 1.],
+            [0., 0., 3., 2.],
+            [0., 4., 0., 3.],
+            [0., 0., 0., 1.],
+        ]);
+        let id = m * m.inverse().unwrap();
+        for i in 0..4 {
+            for j in 0..4 {
+                assert_near(id.at(i, j), Matrix4::identity().at(i, j));
+            }
+        }
+        assert!(Matrix4::from([[0.; 4]; 4]).inverse().is_none());
+    }
+
+    #[test]
+    fn test_transform() {
+        let t = Transform::translate(Vec::from([1., 2., 3.]))
+            .then(&Transform::rotate(Vec::from([0., 0., 1.]), 90.));
+        let p = t.apply_point(Point::from([1., 0., 0.]));
+        assert_near(p.x(), -2.);
+        assert_near(p.y(), 2.);
+        assert_near(p.z(), 3.);
+        let q = t.inverse().apply_point(p);
+        assert_near(q.x(), 1.);
+        assert_near(q.y(), 0.);
+        assert_near(q.z(), 0.);
+    }
+
+    #[test]
+    fn test_normal() {
+        let t = Transform::scale(Vec::from([2., 1., 1.]));
+        let tangent = t.apply_vec(Vec::from([1., -1., 0.]));
+        let n = t.apply_normal(Vec::from([1., 1., 0.]));
+        assert_near(tangent * n, 0.);
+    }
+
+    #[test]
+    fn test_invertible() {
+        let rotated = Transform::rotate(Vec::from([0., 1., 0.]), 30.);
+        assert!(rotated
+            .then(&Transform::scale(Vec::from([2., 1., 1.])))
+            .is_invertible());
+        let flattened = rotated.then(&Transform::scale(Vec::from([1., 0., 1.])));
+        assert!(!flattened.is_invertible());
+        assert!(!Transform::scale(Vec::from([1., f32::INFINITY, 1.])).is_invertible());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::rng;
 use std::{
     fmt::Display,
     iter::Sum,
@@ -21,9 +22,9 @@ impl Vec {
     pub fn new_rand_unit_sphere() -> Vec {
         loop {
             let vec = Vec::from([
-                rand::random::<f32>() * 2. - 1.,
-                rand::random::<f32>() * 2. - 1.,
-                rand::random::<f32>() * 2. - 1.,
+                rng::random() * 2. - 1.,
+                rng::random() * 2. - 1.,
+                rng::random() * 2. - 1.,
             ]);
             if vec.len() <= 1. {
                 break vec;
@@ -79,13 +80,34 @@ impl Vec {
         let cos_theta = f32::min(-v * n, 1.);
         let perp = refract_ratio * (v + cos_theta * n);
         let parl = -f32::sqrt(f32::abs(1. - perp * perp)) * n;
-        return perp + parl;
+        perp + parl
+    }
+
+    /** Two unit vectors that, together with self (assumed unit), form an orthonormal basis.
+     * Follows Duff et al., "Building an Orthonormal Basis, Revisited".
+     */
+    pub fn orthonormal_basis(&self) -> (Vec, Vec) {
+        let sign = 1_f32.copysign(self.z());
+        let a = -1. / (sign + self.z());
+        let b = self.x() * self.y() * a;
+        (
+            Vec::from([
+                1. + sign * self.x() * self.x() * a,
+                sign * b,
+                -sign * self.x(),
+            ]),
+            Vec::from([b, sign + self.y() * self.y() * a, -self.y()]),
+        )
     }
 
     pub fn near_zero(&self) -> bool {
         self.len() < 1e-8
     }
 
+    pub fn is_finite(&self) -> bool {
+        self.coeff.iter().all(|x| x.is_finite())
+    }
+
     pub fn scale(&self, rhs: Vec) -> Vec {
         Vec::from([
             self.x().mul(rhs.x()),
@@ -94,6 +116,11 @@ impl Vec {
         ])
     }
 
+    /** Rec. 709 luminance, treating the vector as a linear RGB color. */
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.x() + 0.7152 * self.y() + 0.0722 * self.z()
+    }
+
     pub fn len(&self) -> f32 {
         self.coeff.map(|x| x * x).iter().fold(0., f32::add).sqrt()
     }
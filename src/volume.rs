@@ -0,0 +1,309 @@
+use std::{fs, sync::Arc};
+
+use crate::{
+    aabb::Aabb,
+    error::Error,
+    material::Material,
+    point::Point,
+    ray::{HitRecord, Ray},
+    rng,
+    shape::Shape,
+};
+
+/** A dense grid of scalar densities, sampled at cell centers and stored x-fastest. */
+#[derive(Debug, Clone)]
+pub struct Grid {
+    res: [usize; 3],
+    data: Vec<f32>,
+    max: f32,
+}
+
+impl Grid {
+    pub fn new(res: [usize; 3], data: Vec<f32>) -> Result<Grid, Error> {
+        if voxels(res) != Some(data.len()) || data.is_empty() {
+            return Err(Error::invalid_argument(
+                "grid",
+                format!(
+                    "{} densities for a {}x{}x{} grid",
+                    data.len(),
+                    res[0],
+                    res[1],
+                    res[2]
+                ),
+            ));
+        }
+        let max = data.iter().copied().fold(0., f32::max);
+        Ok(Grid { res, data, max })
+    }
+
+    /** Reads raw little-endian f32 densities of the given resolution. */
+    pub fn from_raw(fname: &str, res: [usize; 3]) -> Result<Grid, Error> {
+        let bytes = fs::read(fname).map_err(|e| Error::io(fname, e))?;
+        if voxels(res).and_then(|n| n.checked_mul(4)) != Some(bytes.len()) {
+            return Err(Error::parse(
+                fname,
+                format!("expected {}x{}x{} floats", res[0], res[1], res[2]),
+            ));
+        }
+        Grid::new(res, read_f32s(&bytes)).map_err(|e| Error::parse(fname, e.to_string()))
+    }
+
+    /** Reads a Mitsuba grid volume: "VOL", version 3, encoding 1 (f32),
+     * resolution, channel count and bounding box, followed by the data.
+     * Only the first channel of multi-channel volumes is kept.
+     */
+    pub fn from_vol(fname: &str) -> Result<(Grid, Aabb), Error> {
+        let bytes = fs::read(fname).map_err(|e| Error::io(fname, e))?;
+        if bytes.len() < 48 || &bytes[0..3] != b"VOL" {
+            return Err(Error::parse(fname, "not a .vol file"));
+        }
+        if bytes[3] != 3 {
+            return Err(Error::unsupported_format(
+                fname,
+                format!(".vol version {}", bytes[3]),
+            ));
+        }
+        let header = read_i32s(&bytes[4..24]);
+        if header[0] != 1 {
+            return Err(Error::unsupported_format(
+                fname,
+                format!(".vol encoding {}", header[0]),
+            ));
+        }
+        let res = [header[1], header[2], header[3]].map(|x| x.max(0) as usize);
+        let channels = header[4].max(1) as usize;
+        let bounds = read_f32s(&bytes[24..48]);
+        let data = read_f32s(&bytes[48..]);
+        if voxels(res).and_then(|n| n.checked_mul(channels)) != Some(data.len()) {
+            return Err(Error::parse(
+                fname,
+                "data size does not match the resolution",
+            ));
+        }
+        let data = data.into_iter().step_by(channels).collect();
+        let aabb = Aabb::from(
+            Point::from([bounds[0], bounds[1], bounds[2]]),
+            Point::from([bounds[3], bounds[4], bounds[5]]),
+        );
+        let grid = Grid::new(res, data).map_err(|e| Error::parse(fname, e.to_string()))?;
+        Ok((grid, aabb))
+    }
+
+    pub fn max(&self) -> f32 {
+        self.max
+    }
+
+    fn at(&self, x: usize, y: usize, z: usize) -> f32 {
+        self.data[(z * self.res[1] + y) * self.res[0] + x]
+    }
+
+    /** Trilinear lookup at normalized coordinates in [0, 1]^3. */
+    pub fn lookup(&self, uvw: [f32; 3]) -> f32 {
+        let mut base = [0; 3];
+        let mut frac = [0.; 3];
+        for i in 0..=2 {
+            let x = (uvw[i] * self.res[i] as f32 - 0.5).clamp(0., (self.res[i] - 1) as f32);
+            base[i] = (x as usize).min(self.res[i].saturating_sub(2));
+            frac[i] = x - base[i] as f32;
+        }
+        let next = |i: usize| (base[i] + 1).min(self.res[i] - 1);
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let [x0, y0, z0] = base;
+        let [x1, y1, z1] = [next(0), next(1), next(2)];
+        let [fx, fy, fz] = frac;
+        lerp(
+            lerp(
+                lerp(self.at(x0, y0, z0), self.at(x1, y0, z0), fx),
+                lerp(self.at(x0, y1, z0), self.at(x1, y1, z0), fx),
+                fy,
+            ),
+            lerp(
+                lerp(self.at(x0, y0, z1), self.at(x1, y0, z1), fx),
+                lerp(self.at(x0, y1, z1), self.at(x1, y1, z1), fx),
+                fy,
+            ),
+            fz,
+        )
+    }
+}
+
+/* The number of voxels at a resolution, or None if it does not fit in a usize. */
+fn voxels(res: [usize; 3]) -> Option<usize> {
+    res[0].checked_mul(res[1])?.checked_mul(res[2])
+}
+
+fn read_f32s(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+fn read_i32s(bytes: &[u8]) -> Vec<i32> {
+    bytes
+        .chunks_exact(4)
+        .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+/** A heterogeneous medium whose density is a grid stretched over a bounding box.
+ * Scattering events are found by delta tracking against the grid maximum.
+ */
+pub struct GridMedium {
+    grid: Grid,
+    bounds: Aabb,
+    scale: f32,
+    phase: Arc<dyn Material>,
+}
+
+impl GridMedium {
+    pub fn new(grid: Grid, bounds: Aabb, scale: f32, phase: Arc<dyn Material>) -> GridMedium {
+        GridMedium {
+            grid,
+            bounds,
+            scale,
+            phase,
+        }
+    }
+
+    pub fn density(&self, p: Point) -> f32 {
+        let uvw = [0, 1, 2].map(|i| {
+            (p.at(i) - self.bounds.min.at(i)) / (self.bounds.max.at(i) - self.bounds.min.at(i))
+        });
+        self.scale * self.grid.lookup(uvw)
+    }
+
+    fn majorant(&self) -> f32 {
+        self.scale * self.grid.max()
+    }
+
+    /* Delta tracking: returns the parameter of a real collision in [t_min, t_max], if any. */
+    fn delta_track(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<f32> {
+        let majorant = self.majorant();
+        if majorant <= 0. {
+            return None;
+        }
+        let ray_len = ray.direct.len();
+        let mut t = t_min;
+        loop {
+            t -= (1. - rng::random()).ln() / majorant / ray_len;
+            if t >= t_max {
+                return None;
+            }
+            if rng::random() * majorant < self.density(ray.at(t)) {
+                return Some(t);
+            }
+        }
+    }
+
+    /** Ratio tracking: an unbiased estimate of the transmittance along `ray` in [t_min, t_max]. */
+    pub fn transmittance(&self, ray: Ray, t_min: f32, t_max: f32) -> f32 {
+        let majorant = self.majorant();
+        let Some((t_min, t_max)) = self.bounds.hit(ray, t_min, t_max) else {
+            return 1.;
+        };
+        if majorant <= 0. {
+            return 1.;
+        }
+        let ray_len = ray.direct.len();
+        let mut t = t_min;
+        let mut transmittance = 1.;
+        loop {
+            t -= (1. - rng::random()).ln() / majorant / ray_len;
+            if t >= t_max {
+                return transmittance;
+            }
+            transmittance *= 1. - self.density(ray.at(t)) / majorant;
+        }
+    }
+}
+
+impl Shape for GridMedium {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (enter, exit) = self.bounds.hit(ray, t_min, t_max)?;
+        let t = self.delta_track(ray, enter, exit)?;
+        Some(HitRecord::new(
+            ray,
+            t,
+            -ray.direct.to_unit(),
+            self.phase.as_ref(),
+        ))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::Isotropic, vec::Vec};
+
+    fn phase() -> Arc<dyn Material> {
+        Arc::new(Isotropic::new_const([1., 1., 1.]))
+    }
+
+    fn unit_box() -> Aabb {
+        Aabb::from(Point::new(), Point::from([1., 1., 1.]))
+    }
+
+    #[test]
+    fn test_trilinear_lookup() {
+        let grid = Grid::new([2, 1, 1], vec![0., 1.]).unwrap();
+        assert_eq!(grid.lookup([0., 0.5, 0.5]), 0.);
+        assert_eq!(grid.lookup([0.5, 0.5, 0.5]), 0.5);
+        assert_eq!(grid.lookup([1., 0.5, 0.5]), 1.);
+        assert_eq!(grid.max(), 1.);
+    }
+
+    #[test]
+    fn test_ratio_tracking() {
+        let medium = GridMedium::new(
+            Grid::new([2, 2, 2], vec![0.5, 1., 0.5, 1., 0.5, 1., 0.5, 1.]).unwrap(),
+            unit_box(),
+            2.,
+            phase(),
+        );
+        let ray = Ray::from(Point::from([0.5, 0.5, -1.]), Vec::from([0., 0., 1.]));
+        let n = 20000;
+        let estimate = (0..n)
+            .map(|_| medium.transmittance(ray, 0., f32::MAX))
+            .sum::<f32>()
+            / n as f32;
+        assert!((estimate - (-1.5_f32).exp()).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_load_vol() {
+        let fname = std::env::temp_dir().join("rtus_test_load.vol");
+        let mut bytes = b"VOL\x03".to_vec();
+        for x in [1, 2, 1, 1, 1] {
+            bytes.extend(i32::to_le_bytes(x));
+        }
+        for x in [0., 0., 0., 2., 1., 1., 0.25, 0.75] {
+            bytes.extend(f32::to_le_bytes(x));
+        }
+        fs::write(&fname, bytes).unwrap();
+        let (grid, bounds) = Grid::from_vol(fname.to_str().unwrap()).unwrap();
+        assert_eq!(grid.max(), 0.75);
+        assert_eq!(bounds.max.x(), 2.);
+        assert_eq!(grid.lookup([0.5, 0.5, 0.5]), 0.5);
+        assert!(Grid::from_raw(fname.to_str().unwrap(), [3, 3, 3]).is_err());
+
+        let mut empty = b"VOL\x03".to_vec();
+        for x in [1, 0, 4, 4, 1, 0, 0, 0, 0, 0, 0] {
+            empty.extend(i32::to_le_bytes(x));
+        }
+        fs::write(&fname, empty).unwrap();
+        assert!(Grid::from_vol(fname.to_str().unwrap()).is_err());
+
+        let mut huge = b"VOL\x03".to_vec();
+        for x in [1, i32::MAX, i32::MAX, i32::MAX, i32::MAX, 0, 0, 0, 0, 0, 0] {
+            huge.extend(i32::to_le_bytes(x));
+        }
+        fs::write(&fname, huge).unwrap();
+        assert!(Grid::from_vol(fname.to_str().unwrap()).is_err());
+        assert!(Grid::new([usize::MAX, 2, 1], vec![1.]).is_err());
+    }
+}